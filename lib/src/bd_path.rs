@@ -1,7 +1,15 @@
+use crate::{compression::Codec, dyn_binary::DynStorage, encryption::EncryptionKey};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BDPath {
     pub dir_path: String,
     pub rel_file_path: String,
+    /// Where the new `DynanicBinary` values are written.
+    pub dyn_storage: DynStorage,
+    /// How the new pages and `DynanicBinary` values are compressed.
+    pub codec: Codec,
+    /// The key encrypting the file and the dynamic data, none if they are plaintext.
+    pub key: Option<EncryptionKey>,
 }
 
 impl BDPath {
@@ -9,6 +17,9 @@ impl BDPath {
         Self {
             dir_path: path.to_owned(),
             rel_file_path: "main.bin".to_owned(),
+            dyn_storage: DynStorage::Files,
+            codec: Codec::None,
+            key: None,
         }
     }
 
@@ -16,6 +27,9 @@ impl BDPath {
         Self {
             dir_path: path,
             rel_file_path: "main.bin".to_owned(),
+            dyn_storage: DynStorage::Files,
+            codec: Codec::None,
+            key: None,
         }
     }
 
@@ -23,6 +37,9 @@ impl BDPath {
         Self {
             dir_path: path,
             rel_file_path: format!("dyn/{nb}.bin"),
+            dyn_storage: DynStorage::Files,
+            codec: Codec::None,
+            key: None,
         }
     }
 
@@ -30,6 +47,30 @@ impl BDPath {
         Self {
             dir_path: path,
             rel_file_path: format!("index/{name}.bin"),
+            dyn_storage: DynStorage::Files,
+            codec: Codec::None,
+            key: None,
+        }
+    }
+
+    pub fn with_dyn_storage(&self, dyn_storage: DynStorage) -> Self {
+        Self {
+            dyn_storage,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_codec(&self, codec: Codec) -> Self {
+        Self {
+            codec,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_key(&self, key: Option<EncryptionKey>) -> Self {
+        Self {
+            key,
+            ..self.clone()
         }
     }
 
@@ -41,6 +82,15 @@ impl BDPath {
         format!("{}/dyn", self.dir_path)
     }
 
+    /// The file holding the next id given to a `DynanicBinary`.
+    pub fn dyn_next_path(&self) -> String {
+        format!("{}/dyn.next", self.dir_path)
+    }
+
+    pub fn heap_path(&self) -> String {
+        format!("{}/heap.bin", self.dir_path)
+    }
+
     pub fn folder(&self) -> String {
         format!(
             "{}{}",
@@ -54,4 +104,18 @@ impl BDPath {
     pub fn index_path(&self) -> String {
         format!("{}/index", self.dir_path)
     }
+
+    /// The main file owns the dynamic data, the index files only reference it.
+    pub fn is_main(&self) -> bool {
+        self.folder().eq(&self.dir_path) && self.rel_file_path.eq("main.bin")
+    }
+
+    /// The file checking that the table is opened with the key it is encrypted with.
+    pub fn key_path(&self) -> String {
+        format!("{}/key", self.dir_path)
+    }
+
+    pub fn wal_path(&self) -> String {
+        format!("{}/wal", self.dir_path)
+    }
 }
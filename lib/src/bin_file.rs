@@ -1,8 +1,16 @@
-use crate::{bd_path::BDPath, binary::Binary};
+use crate::{
+    bd_path::BDPath,
+    binary::Binary,
+    dyn_binary::clear_dyn,
+    encryption::{self, OVERHEAD, TAG_SIZE},
+    table::Table,
+};
 use std::{
-    fs::{self, File, create_dir_all, remove_dir_all, remove_file},
-    io::{self, Error, Read, Seek, SeekFrom, Write},
+    collections::VecDeque,
+    fs::{self, File, create_dir_all, remove_file},
+    io::{self, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write},
     marker::PhantomData,
+    ops::Range,
     path::Path,
 };
 
@@ -16,15 +24,193 @@ where
 
 pub trait BaseBinFile<Row>: Sized {
     fn new(path: BDPath) -> io::Result<Self>;
+    /// Another handle on the same file, sharing the cache of this one.
+    fn share(&self) -> Self;
     fn path(&self) -> &BDPath;
     fn get(&self, index: usize) -> io::Result<Row>;
     fn gets(&self, index: usize, len: Option<usize>) -> io::Result<Vec<Row>>;
+    /// Like `gets`, without keeping the rows in a cache, for the rows read once.
+    fn gets_uncached(&self, index: usize, len: Option<usize>) -> io::Result<Vec<Row>> {
+        self.gets(index, len)
+    }
+    /// The first index where `pred` is false for the id of the row, the rows where it is true must be first.
+    fn partition_point_by_id(&self, pred: &dyn Fn(&Row::ID) -> bool) -> io::Result<usize>
+    where
+        Row: Table,
+    {
+        let (mut from, mut to) = (0, self.len()?);
+        while from < to {
+            let mid = from + (to - from) / 2;
+            if pred(self.get(mid)?.id()) {
+                from = mid + 1;
+            } else {
+                to = mid;
+            }
+        }
+        Ok(from)
+    }
     fn is_empty(&self) -> io::Result<bool>;
     fn len(&self) -> io::Result<usize>;
     fn insert(&mut self, index: usize, data: &mut Row) -> std::io::Result<()>;
     fn inserts(&mut self, index: usize, datas: &mut [Row]) -> std::io::Result<()>;
+    /// Inserts a row already converted with `Binary::as_bin`.
+    fn insert_bin(&mut self, index: usize, data: &[u8]) -> std::io::Result<()>;
+    fn set(&mut self, index: usize, data: &mut Row) -> std::io::Result<()>;
+    /// Sets a row already converted with `Binary::as_bin`.
+    fn set_bin(&mut self, index: usize, data: &[u8]) -> std::io::Result<()>;
     fn remove(&mut self, index: usize, len: Option<usize>) -> std::io::Result<()>;
     fn clear(&mut self) -> std::io::Result<()>;
+    /// Drops a row that was partially written by a crash.
+    fn repair(&mut self) -> std::io::Result<()>;
+
+    fn iter(&self) -> io::Result<BinIter<'_, Row, Self>> {
+        Ok(self.iter_range(0..self.len()?))
+    }
+
+    /// Reads the rows by chunks, so only a chunk of rows is in memory at a time,
+    /// without filling the cache of a `CachedBinFile`.
+    fn iter_range(&self, range: Range<usize>) -> BinIter<'_, Row, Self> {
+        BinIter {
+            bin: self,
+            range,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+        }
+    }
+}
+
+const CHUNK: usize = 256;
+
+pub struct BinIter<'a, Row, BinFile: BaseBinFile<Row>> {
+    bin: &'a BinFile,
+    /// The rows not read yet.
+    range: Range<usize>,
+    front: VecDeque<Row>,
+    back: VecDeque<Row>,
+}
+
+impl<Row, BinFile: BaseBinFile<Row>> BinIter<'_, Row, BinFile> {
+    fn read(&mut self, index: usize, len: usize) -> io::Result<VecDeque<Row>> {
+        match self.bin.gets_uncached(index, Some(len)) {
+            Ok(rows) => Ok(rows.into()),
+            Err(e) => {
+                self.range = 0..0;
+                self.front.clear();
+                self.back.clear();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<Row, BinFile: BaseBinFile<Row>> Iterator for BinIter<'_, Row, BinFile> {
+    type Item = io::Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front.is_empty() && !self.range.is_empty() {
+            let len = self.range.len().min(CHUNK);
+            match self.read(self.range.start, len) {
+                Ok(rows) => self.front = rows,
+                Err(e) => return Some(Err(e)),
+            }
+            self.range.start += len;
+        }
+        self.front
+            .pop_front()
+            .or_else(|| self.back.pop_front())
+            .map(Ok)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.range.len() + self.front.len() + self.back.len();
+        (len, Some(len))
+    }
+}
+
+impl<Row, BinFile: BaseBinFile<Row>> ExactSizeIterator for BinIter<'_, Row, BinFile> {}
+
+impl<Row, BinFile: BaseBinFile<Row>> DoubleEndedIterator for BinIter<'_, Row, BinFile> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back.is_empty() && !self.range.is_empty() {
+            let len = self.range.len().min(CHUNK);
+            match self.read(self.range.end - len, len) {
+                Ok(rows) => self.back = rows,
+                Err(e) => return Some(Err(e)),
+            }
+            self.range.end -= len;
+        }
+        self.back
+            .pop_back()
+            .or_else(|| self.front.pop_back())
+            .map(Ok)
+    }
+}
+
+/// The authentication of all the rows of an encrypted `BinFile`, in the `.mac` file next to it,
+/// checked when the file is opened so a row replaced by an older version of itself,
+/// or the rows cut from the end, are detected.
+///
+/// It is the xor of the keyed tags of the rows with their index, so a row is changed without
+/// reading the others. During a write, the file keeps the mac from before it, with the index
+/// and the tag of the row overwritten in place, so the file left by a crash is accepted,
+/// a row written partially failing its own authentication until the wal rewrites it.
+#[derive(Clone, Copy)]
+struct Mac {
+    mac: [u8; TAG_SIZE],
+    before: [u8; TAG_SIZE],
+    /// `u64::MAX` when no row is overwritten in place.
+    index: u64,
+    tag: [u8; TAG_SIZE],
+}
+
+const MAC_SIZE: usize = 3 * TAG_SIZE + 8;
+
+impl Mac {
+    const fn done(mac: [u8; TAG_SIZE]) -> Self {
+        Self {
+            mac,
+            before: mac,
+            index: u64::MAX,
+            tag: [0; TAG_SIZE],
+        }
+    }
+
+    fn bin(&self) -> Vec<u8> {
+        [
+            self.mac.as_slice(),
+            &self.before,
+            &self.index.to_le_bytes(),
+            &self.tag,
+        ]
+        .concat()
+    }
+
+    fn from_bin(data: &[u8]) -> io::Result<Self> {
+        if data.len() != MAC_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid mac file"));
+        }
+        let mut mac = Self::done([0; TAG_SIZE]);
+        mac.mac.copy_from_slice(&data[..TAG_SIZE]);
+        mac.before.copy_from_slice(&data[TAG_SIZE..2 * TAG_SIZE]);
+        let mut index = [0; 8];
+        index.copy_from_slice(&data[2 * TAG_SIZE..2 * TAG_SIZE + 8]);
+        mac.index = u64::from_le_bytes(index);
+        mac.tag.copy_from_slice(&data[2 * TAG_SIZE + 8..]);
+        Ok(mac)
+    }
+}
+
+fn xor(mac: &mut [u8; TAG_SIZE], other: &[u8; TAG_SIZE]) {
+    for (byte, other) in mac.iter_mut().zip(other) {
+        *byte ^= other;
+    }
+}
+
+/// The tag ending an encrypted row.
+fn tag(row: &[u8]) -> [u8; TAG_SIZE] {
+    let mut tag = [0; TAG_SIZE];
+    tag.copy_from_slice(&row[row.len() - TAG_SIZE..]);
+    tag
 }
 
 impl<Row: Binary> BinFile<Row> {
@@ -32,8 +218,183 @@ impl<Row: Binary> BinFile<Row> {
         &self.path
     }
 
+    /// The size of a row in the file, the row is followed by its authentication if encrypted.
+    fn row_size(&self) -> usize {
+        Row::bin_size() + self.path.key.map_or(0, |_| OVERHEAD)
+    }
+
+    /// The name of the file the rows are authenticated with,
+    /// the rows written by a migration in `main.migrate.bin` being moved to `main.bin`.
+    fn name(&self) -> &[u8] {
+        match self.path.rel_file_path.as_str() {
+            "main.migrate.bin" => b"main.bin",
+            name => name.as_bytes(),
+        }
+    }
+
+    /// The rows are authenticated with the name of their file and their index.
+    fn aad(&self, index: usize) -> Vec<u8> {
+        [self.name(), &(index as u64).to_le_bytes()].concat()
+    }
+
+    /// The rows converted with `Binary::as_bin` as they are written in the file from `index`.
+    ///
+    /// The rows are authenticated with their index, so the ones moved by an insert
+    /// or a remove are encrypted again.
+    fn encrypt(&self, index: usize, data: &[u8]) -> io::Result<Vec<u8>> {
+        if self.path.key.is_none() {
+            return Ok(data.to_vec());
+        }
+        let mut bin = Vec::with_capacity(data.len() / Row::bin_size() * self.row_size());
+        for (i, row) in data.chunks(Row::bin_size()).enumerate() {
+            bin.extend(encryption::encrypt(
+                self.path.key,
+                &self.aad(index + i),
+                row,
+            )?);
+        }
+        Ok(bin)
+    }
+
+    /// The encrypted rows from `index` as returned by `Binary::as_bin`.
+    fn decrypt(&self, index: usize, data: &[u8]) -> io::Result<Vec<u8>> {
+        if self.path.key.is_none() {
+            return Ok(data.to_vec());
+        }
+        let mut bin = Vec::with_capacity(data.len() / self.row_size() * Row::bin_size());
+        for (i, row) in data.chunks(self.row_size()).enumerate() {
+            bin.extend(encryption::decrypt(
+                self.path.key,
+                &self.aad(index + i),
+                row,
+            )?);
+        }
+        Ok(bin)
+    }
+
+    fn decrypt_row(&self, index: usize, row: &[u8]) -> io::Result<Row> {
+        match self.path.key {
+            None => Row::from_bin(row, &self.path),
+            Some(_) => Row::from_bin(
+                &encryption::decrypt(self.path.key, &self.aad(index), row)?,
+                &self.path,
+            ),
+        }
+    }
+
+    fn mac_path(&self) -> String {
+        format!("{}.mac", self.path.full())
+    }
+
+    /// The xor of the keyed tags of the encrypted rows of `data`, the first one at `index`.
+    fn rows_mac(&self, index: usize, data: &[u8]) -> io::Result<[u8; TAG_SIZE]> {
+        let mut mac = [0; TAG_SIZE];
+        let Some(key) = self.path.key else {
+            return Ok(mac);
+        };
+        for (i, row) in data.chunks(self.row_size()).enumerate() {
+            xor(
+                &mut mac,
+                &encryption::keyed_tag(key, (index + i) as u64, &tag(row))?,
+            );
+        }
+        Ok(mac)
+    }
+
+    fn read_mac(&self) -> io::Result<Mac> {
+        let data = fs::read(self.mac_path())?;
+        Mac::from_bin(&encryption::decrypt(
+            self.path.key,
+            &[self.name(), b".mac"].concat(),
+            &data,
+        )?)
+    }
+
+    fn write_mac(&self, mac: &Mac) -> io::Result<()> {
+        let temp = format!("{}.temp", self.mac_path());
+        {
+            let mut file = File::create(temp.as_str())?;
+            file.write_all(&encryption::encrypt(
+                self.path.key,
+                &[self.name(), b".mac"].concat(),
+                &mac.bin(),
+            )?)?;
+            file.sync_all()?;
+        }
+        fs::rename(temp, self.mac_path())
+    }
+
+    /// Runs `write`, which changes the rows from `from` with `removed` to `inserted`,
+    /// the row at `from` being overwritten in place if `in_place`.
+    fn authenticated(
+        &self,
+        from: usize,
+        removed: &[u8],
+        inserted: &[u8],
+        in_place: bool,
+        write: impl FnOnce() -> io::Result<()>,
+    ) -> io::Result<()> {
+        if self.path.key.is_none() {
+            return write();
+        }
+        let before = self.read_mac()?.mac;
+        let mut mac = before;
+        xor(&mut mac, &self.rows_mac(from, removed)?);
+        xor(&mut mac, &self.rows_mac(from, inserted)?);
+        self.write_mac(&Mac {
+            mac,
+            before,
+            index: if in_place { from as u64 } else { u64::MAX },
+            tag: if in_place {
+                tag(removed)
+            } else {
+                [0; TAG_SIZE]
+            },
+        })?;
+        write()?;
+        self.write_mac(&Mac::done(mac))
+    }
+
+    /// Fails if the rows aren't the ones of the mac file, but for the file left by a crash
+    /// during a write. The row written partially by a crash is left to the `repair` and the wal.
+    fn check_mac(&self) -> io::Result<()> {
+        let Some(key) = self.path.key else {
+            return Ok(());
+        };
+        let stored = self.read_mac()?;
+        let mut reader = BufReader::new(File::open(self.path.full())?);
+        let mut row = vec![0; self.row_size()];
+        let (mut mac, mut overwritten) = ([0; TAG_SIZE], None);
+        for index in 0..self.len()? {
+            reader.read_exact(&mut row)?;
+            let row_mac = encryption::keyed_tag(key, index as u64, &tag(&row))?;
+            xor(&mut mac, &row_mac);
+            if index as u64 == stored.index {
+                overwritten = Some(row_mac);
+            }
+        }
+        if mac == stored.mac {
+            return Ok(());
+        }
+        let mut partial = mac;
+        if let Some(row_mac) = overwritten {
+            xor(&mut partial, &row_mac);
+            xor(
+                &mut partial,
+                &encryption::keyed_tag(key, stored.index, &stored.tag)?,
+            );
+        }
+        if mac == stored.before || (overwritten.is_some() && partial == stored.before) {
+            return self.write_mac(&Mac::done(mac));
+        }
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} was changed outside of the table", self.path.full()),
+        ))
+    }
+
     fn read(&self, index: usize, len: Option<usize>) -> io::Result<Vec<u8>> {
-        let first_byte = index * Row::bin_size();
+        let first_byte = index * self.row_size();
         let file_len = self.file_len()?;
 
         if first_byte == file_len {
@@ -47,7 +408,7 @@ impl<Row: Binary> BinFile<Row> {
             if len == 0 {
                 return Err(Error::other("must read at least 1 element"));
             }
-            let len = len * Row::bin_size();
+            let len = len * self.row_size();
             if (first_byte + len) > file_len {
                 return Err(io::Error::other(format!(
                     "first_byte:{} < file_len:{}",
@@ -63,11 +424,11 @@ impl<Row: Binary> BinFile<Row> {
                 )));
             }
             let len = file_len - first_byte;
-            if !len.is_multiple_of(Row::bin_size()) {
+            if !len.is_multiple_of(self.row_size()) {
                 return Err(io::Error::other(format!(
                     "len:{} not divisable by {}",
                     len,
-                    Row::bin_size()
+                    self.row_size()
                 )));
             }
             len
@@ -82,23 +443,43 @@ impl<Row: Binary> BinFile<Row> {
         Ok(result)
     }
 
+    fn temp_path(&self) -> BDPath {
+        BDPath {
+            dir_path: self.path.dir_path.clone(),
+            rel_file_path: self.path.rel_file_path.replace(".", ".temp."),
+            dyn_storage: self.path.dyn_storage,
+            codec: self.path.codec,
+            key: self.path.key,
+        }
+    }
+
     fn file_len(&self) -> io::Result<usize> {
         Ok(fs::metadata(self.path.full())?.len() as usize)
     }
 
+    /// Inserts the rows converted with `Binary::as_bin` at `index`.
     fn base_insert(&mut self, index: usize, data: &[u8]) -> std::io::Result<()> {
         if index == self.len()? {
-            let mut file = File::options()
-                .append(true)
-                .create(true)
-                .open(self.path.full().as_str())?;
-            file.write_all(data)?;
-            return file.sync_all();
+            let bin = self.encrypt(index, data)?;
+            return self.authenticated(index, &[], &bin, false, || {
+                let mut file = File::options()
+                    .append(true)
+                    .create(true)
+                    .open(self.path.full().as_str())?;
+                file.write_all(&bin)?;
+                file.sync_all()
+            });
         }
-        let temp = BDPath {
-            dir_path: self.path.dir_path.clone(),
-            rel_file_path: self.path.rel_file_path.replace(".", ".temp."),
-        };
+        if self.path.key.is_some() {
+            let moved = self.read(index, None)?;
+            let mut rows = data.to_vec();
+            rows.extend(self.decrypt(index, &moved)?);
+            let bin = self.encrypt(index, &rows)?;
+            return self.authenticated(index, &moved, &bin, false, || {
+                self.rewrite_from(index, &bin)
+            });
+        }
+        let temp = self.temp_path();
 
         let mut buf = [0_u8; 1028];
 
@@ -112,7 +493,7 @@ impl<Row: Binary> BinFile<Row> {
 
             let (start, end) = {
                 let mut i: usize = 0;
-                let until = Row::bin_size() * index;
+                let until = self.row_size() * index;
                 loop {
                     let n = original.read(&mut buf)?;
                     if n == 0 {
@@ -142,21 +523,48 @@ impl<Row: Binary> BinFile<Row> {
         }
         fs::rename(temp.full().as_str(), self.path.full().as_str())
     }
+
+    /// Keeps the rows before `index` and writes `bin` after them, in a new file put in place.
+    fn rewrite_from(&self, index: usize, bin: &[u8]) -> io::Result<()> {
+        let temp = self.temp_path();
+        {
+            let mut new = File::create(temp.full().as_str())?;
+            let mut original =
+                File::open(self.path.full().as_str())?.take((index * self.row_size()) as u64);
+            io::copy(&mut original, &mut new)?;
+            new.write_all(bin)?;
+            new.sync_all()?;
+        }
+        fs::rename(temp.full().as_str(), self.path.full().as_str())
+    }
 }
 
 impl<Row: Binary> BaseBinFile<Row> for BinFile<Row> {
+    /// Fails if the rows of an encrypted file were changed outside of the table.
     fn new(path: BDPath) -> std::io::Result<Self> {
-        if !Path::new(path.full().as_str()).exists() {
-            create_dir_all(path.folder())?;
-            create_dir_all(path.dyn_path())?;
-            File::create(path.full())?;
-        } else if !Path::new(path.dyn_path().as_str()).exists() {
-            create_dir_all(path.dyn_path())?;
-        }
-        Ok(BinFile {
+        let file = BinFile {
             path,
             phantom_row: PhantomData,
-        })
+        };
+        if !Path::new(file.path.full().as_str()).exists() {
+            create_dir_all(file.path.folder())?;
+            create_dir_all(file.path.dyn_path())?;
+            File::create(file.path.full())?;
+            if file.path.key.is_some() {
+                file.write_mac(&Mac::done([0; TAG_SIZE]))?;
+            }
+        } else if !Path::new(file.path.dyn_path().as_str()).exists() {
+            create_dir_all(file.path.dyn_path())?;
+        }
+        file.check_mac()?;
+        Ok(file)
+    }
+
+    fn share(&self) -> Self {
+        BinFile {
+            path: self.path.clone(),
+            phantom_row: PhantomData,
+        }
     }
 
     fn path(&self) -> &BDPath {
@@ -164,29 +572,30 @@ impl<Row: Binary> BaseBinFile<Row> for BinFile<Row> {
     }
 
     fn get(&self, index: usize) -> io::Result<Row> {
-        let first_byte = index * Row::bin_size();
+        let first_byte = index * self.row_size();
         let file_len = self.file_len()?;
 
-        if (first_byte + Row::bin_size()) > file_len {
+        if (first_byte + self.row_size()) > file_len {
             return Err(io::Error::other(format!(
                 "first_byte:{} < file_len:{}",
-                first_byte + Row::bin_size(),
+                first_byte + self.row_size(),
                 file_len
             )));
         }
-        let mut result = vec![0; Row::bin_size()];
+        let mut result = vec![0; self.row_size()];
         {
             let mut file = File::open(self.path.full())?;
             file.seek(SeekFrom::Start(first_byte as u64))?;
             file.read_exact(&mut result)?;
         }
-        Row::from_bin(&result, &self.path)
+        self.decrypt_row(index, &result)
     }
 
     fn gets(&self, index: usize, len: Option<usize>) -> io::Result<Vec<Row>> {
         self.read(index, len)?
-            .chunks(Row::bin_size())
-            .map(|row| Row::from_bin(row, &self.path))
+            .chunks(self.row_size())
+            .enumerate()
+            .map(|(i, row)| self.decrypt_row(index + i, row))
             .collect()
     }
 
@@ -195,7 +604,7 @@ impl<Row: Binary> BaseBinFile<Row> for BinFile<Row> {
     }
 
     fn len(&self) -> io::Result<usize> {
-        Ok(fs::metadata(self.path.full())?.len() as usize / Row::bin_size())
+        Ok(fs::metadata(self.path.full())?.len() as usize / self.row_size())
     }
 
     fn insert(&mut self, index: usize, data: &mut Row) -> std::io::Result<()> {
@@ -203,47 +612,86 @@ impl<Row: Binary> BaseBinFile<Row> for BinFile<Row> {
     }
 
     fn inserts(&mut self, index: usize, datas: &mut [Row]) -> std::io::Result<()> {
-        self.base_insert(
-            index,
-            &datas
-                .iter_mut()
-                .flat_map(|data| data.as_bin(&self.path))
-                .flatten()
-                .collect::<Vec<u8>>(),
-        )
+        let mut bin = Vec::with_capacity(datas.len() * Row::bin_size());
+        for data in datas {
+            bin.extend(data.as_bin(&self.path)?);
+        }
+        self.base_insert(index, &bin)
+    }
+
+    fn insert_bin(&mut self, index: usize, data: &[u8]) -> std::io::Result<()> {
+        self.base_insert(index, data)
+    }
+
+    fn set(&mut self, index: usize, data: &mut Row) -> std::io::Result<()> {
+        self.set_bin(index, &data.as_bin(&self.path)?)
+    }
+
+    fn set_bin(&mut self, index: usize, data: &[u8]) -> std::io::Result<()> {
+        if index >= self.len()? {
+            return Err(Error::other("out of bound"));
+        }
+        let old = match self.path.key {
+            Some(_) => self.read(index, Some(1))?,
+            None => Vec::new(),
+        };
+        let bin = self.encrypt(index, data)?;
+        self.authenticated(index, &old, &bin, true, || {
+            let mut file = File::options().write(true).open(self.path.full())?;
+            file.seek(SeekFrom::Start((index * self.row_size()) as u64))?;
+            file.write_all(&bin)?;
+            file.sync_all()
+        })
     }
 
     fn remove(&mut self, index: usize, len: Option<usize>) -> std::io::Result<()> {
         let data = self.read(0, None)?;
 
         let end = if let Some(len) = len {
-            (index + len) * Row::bin_size()
+            (index + len) * self.row_size()
         } else {
             data.len()
         };
-        for to_delete in data[(index * Row::bin_size())..end]
-            .chunks(Row::bin_size())
-            .map(|row| Row::from_bin(row, &self.path))
-        {
-            to_delete?.delete(&self.path)?;
-        }
 
-        let mut file = File::create(self.path.full().as_str())?;
-        if index > 0 {
-            file.write_all(&data[..(index * Row::bin_size())])?;
+        let start = index * self.row_size();
+        let moved = self.decrypt(end / self.row_size(), &data[end..])?;
+        let bin = self.encrypt(index, &moved)?;
+        self.authenticated(index, &data[start..], &bin, false, || {
+            self.rewrite_from(index, &bin)
+        })?;
+
+        if !self.path.is_main() {
+            return Ok(());
         }
-        if let Some(len) = len {
-            file.write_all(&data[((index + len) * Row::bin_size())..])?;
+        for (i, row) in data[start..end].chunks(self.row_size()).enumerate() {
+            self.decrypt_row(index + i, row)?.delete(&self.path)?;
         }
-        file.sync_all()
+        Ok(())
     }
 
     fn clear(&mut self) -> std::io::Result<()> {
-        remove_file(self.path.full())?;
-        File::create(self.path.full())?;
-        if self.path.folder().eq(&self.path.dir_path) && self.path.rel_file_path.eq("main.bin") {
-            remove_dir_all(self.path.dyn_path())?;
-            create_dir_all(self.path.dyn_path())?;
+        let data = match self.path.key {
+            Some(_) => self.read(0, None)?,
+            None => Vec::new(),
+        };
+        self.authenticated(0, &data, &[], false, || {
+            remove_file(self.path.full())?;
+            File::create(self.path.full())?;
+            Ok(())
+        })?;
+        if self.path.is_main() {
+            clear_dyn(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn repair(&mut self) -> std::io::Result<()> {
+        let file_len = self.file_len()?;
+        let len = file_len - file_len % self.row_size();
+        if len != file_len {
+            let file = File::options().write(true).open(self.path.full())?;
+            file.set_len(len as u64)?;
+            file.sync_all()?;
         }
         Ok(())
     }
@@ -1,4 +1,8 @@
-use std::io::{self, Error};
+use std::{
+    io::{self, Error, ErrorKind},
+    num::NonZero,
+    ptr,
+};
 
 pub use rust_db_macro::Binary;
 
@@ -8,7 +12,25 @@ pub trait Binary: Sized + AsBinary {
     fn from_bin(data: &[u8], path: &BDPath) -> io::Result<Self>;
     fn as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>>;
     fn bin_size() -> usize;
+    /// The size of the start of the bin the rows are sorted by, none by default,
+    /// kept in the internal pages of a `PageBinFile` to find the rows by it.
+    fn key_size() -> usize {
+        0
+    }
     fn delete(&self, path: &BDPath) -> io::Result<()>;
+    /// Deletes the dynamic data of `self` that `new` doesn't reference anymore.
+    ///
+    /// By default all of it unless `new` is `self`, the types whose dynamic data can be kept
+    /// by another value override it.
+    fn delete_replaced(&self, new: &Self, path: &BDPath) -> io::Result<()> {
+        if ptr::eq(self, new) {
+            Ok(())
+        } else {
+            self.delete(path)
+        }
+    }
+    /// Calls `visit` with the id of every `DynanicBinary` of `self`, none by default.
+    fn visit_dyn(&mut self, _: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {}
 }
 
 impl<T: Binary> AsBinary for T {
@@ -18,14 +40,64 @@ impl<T: Binary> AsBinary for T {
     fn as_as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>> {
         self.as_bin(path)
     }
+    fn visit_as_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {
+        self.visit_dyn(visit);
+    }
 }
 
 impl Binary for char {
+    fn from_bin(data: &[u8], path: &BDPath) -> io::Result<Self> {
+        let value = u32::from_bin(data, path)?;
+        Self::from_u32(value)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("invalid char: {value:#x}")))
+    }
+    fn as_bin(&mut self, _: &BDPath) -> io::Result<Vec<u8>> {
+        Ok(Vec::from(u32::from(*self).to_le_bytes()))
+    }
+    fn bin_size() -> usize {
+        4
+    }
+    fn delete(&self, _: &BDPath) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A `char` up to U+00FF stored in 1 byte, the layout of `char` columns before they took 4 bytes.
+///
+/// Existing tables keep reading with a `Latin1Char` column,
+/// or migrate to `char` with `SpecificTableFile::migrate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Latin1Char(char);
+
+impl Latin1Char {
+    /// Fails if `value` is above U+00FF.
+    pub fn new(value: char) -> io::Result<Self> {
+        if u32::from(value) > 0xff {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{value:?} doesn't fit in a Latin1Char"),
+            ));
+        }
+        Ok(Self(value))
+    }
+
+    pub const fn char(self) -> char {
+        self.0
+    }
+}
+
+impl From<Latin1Char> for char {
+    fn from(value: Latin1Char) -> Self {
+        value.0
+    }
+}
+
+impl Binary for Latin1Char {
     fn from_bin(data: &[u8], _: &BDPath) -> io::Result<Self> {
-        Ok(data[0] as char)
+        Ok(Self(char::from(data[0])))
     }
     fn as_bin(&mut self, _: &BDPath) -> io::Result<Vec<u8>> {
-        Ok(vec![*self as u8])
+        Ok(vec![u8::try_from(self.0).map_err(Error::other)?])
     }
     fn bin_size() -> usize {
         1
@@ -50,6 +122,47 @@ impl Binary for bool {
     }
 }
 
+impl<T: Binary> Binary for Option<T> {
+    fn from_bin(data: &[u8], path: &BDPath) -> io::Result<Self> {
+        if data[0] == 0 {
+            Ok(None)
+        } else {
+            T::from_bin(&data[1..], path).map(Some)
+        }
+    }
+    fn as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>> {
+        match self {
+            None => Ok(vec![0; Self::bin_size()]),
+            Some(value) => {
+                let mut bin = vec![1];
+                bin.extend(value.as_bin(path)?);
+                Ok(bin)
+            }
+        }
+    }
+    fn bin_size() -> usize {
+        1 + T::bin_size()
+    }
+    fn delete(&self, path: &BDPath) -> io::Result<()> {
+        match self {
+            Some(value) => value.delete(path),
+            None => Ok(()),
+        }
+    }
+    fn delete_replaced(&self, new: &Self, path: &BDPath) -> io::Result<()> {
+        match (self, new) {
+            (Some(value), Some(new)) => value.delete_replaced(new, path),
+            (Some(value), None) => value.delete(path),
+            (None, _) => Ok(()),
+        }
+    }
+    fn visit_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {
+        if let Some(value) = self {
+            value.visit_dyn(visit);
+        }
+    }
+}
+
 macro_rules! to_binary {
     ($($self: ty),+) => {
         to_binary!($($self: $self),+);
@@ -78,24 +191,22 @@ to_binary!(
 );
 to_binary!(f32: u32, f64: u64);
 
-impl<T, const LEN: usize> Binary for [T; LEN]
-where
-    T: Binary + Copy + Default,
-{
+impl<T: Binary, const LEN: usize> Binary for [T; LEN] {
     fn from_bin(data: &[u8], path: &BDPath) -> io::Result<Self> {
-        let mut result: [T; LEN] = [T::default(); LEN];
-        for (i, item) in result.iter_mut().enumerate() {
-            *item = T::from_bin(&data[i * T::bin_size()..], path)?;
-        }
-        Ok(result)
+        let items = (0..LEN)
+            .map(|i| T::from_bin(&data[i * T::bin_size()..], path))
+            .collect::<io::Result<Vec<T>>>()?;
+        items
+            .try_into()
+            .map_err(|_| Error::other("wrong array length"))
     }
 
     fn as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>> {
-        Ok(self
-            .iter_mut()
-            .flat_map(|item| item.as_bin(path))
-            .flatten()
-            .collect())
+        let mut bin = Vec::with_capacity(Self::bin_size());
+        for item in self {
+            bin.extend(item.as_bin(path)?);
+        }
+        Ok(bin)
     }
 
     fn bin_size() -> usize {
@@ -107,4 +218,66 @@ where
         }
         Ok(())
     }
+    fn delete_replaced(&self, new: &Self, path: &BDPath) -> io::Result<()> {
+        for (item, new) in self.iter().zip(new) {
+            item.delete_replaced(new, path)?;
+        }
+        Ok(())
+    }
+    fn visit_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {
+        for item in self {
+            item.visit_dyn(visit);
+        }
+    }
 }
+
+macro_rules! tuple_binary {
+    ($(($($item: ident $index: tt),+))+) => {
+        $(
+            impl<$($item: Binary),+> Binary for ($($item,)+) {
+                #[expect(unused_assignments)]
+                fn from_bin(data: &[u8], path: &BDPath) -> io::Result<Self> {
+                    let mut offset = 0;
+                    Ok(($({
+                        let item = $item::from_bin(&data[offset..], path)?;
+                        offset += $item::bin_size();
+                        item
+                    },)+))
+                }
+                fn as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>> {
+                    let mut bin = Vec::with_capacity(Self::bin_size());
+                    $(bin.extend(self.$index.as_bin(path)?);)+
+                    Ok(bin)
+                }
+                fn bin_size() -> usize {
+                    0 $(+ $item::bin_size())+
+                }
+                fn delete(&self, path: &BDPath) -> io::Result<()> {
+                    $(self.$index.delete(path)?;)+
+                    Ok(())
+                }
+                fn delete_replaced(&self, new: &Self, path: &BDPath) -> io::Result<()> {
+                    $(self.$index.delete_replaced(&new.$index, path)?;)+
+                    Ok(())
+                }
+                fn visit_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {
+                    $(self.$index.visit_dyn(visit);)+
+                }
+            }
+        )+
+    };
+}
+tuple_binary!(
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11)
+);
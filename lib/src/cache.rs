@@ -124,6 +124,14 @@ impl<Row: Clone> Cache<Row> {
         }
     }
 
+    /// Replaces the row only if it is in cache.
+    pub fn set(&mut self, index: usize, data: Row) {
+        if let Ok(i) = self.frind_first_cache(index) {
+            let cache = &mut self.cache[i];
+            cache.data[index - cache.from] = data;
+        }
+    }
+
     /// # Safety
     /// Their isn't any validation so you can corrupt the data structure.
     pub unsafe fn move_cache(&mut self, index: usize, amount: isize) {
@@ -246,55 +254,53 @@ impl<Row: Clone> Cache<Row> {
                 if len == 0 {
                     panic!("len is 0")
                 }
-                loop {
-                    if let Some(cache) = self.cache.get_mut(i) {
-                        if cache.from + 1 > index + len {
-                            break;
-                        }
-                        if cache.from >= index {
-                            if cache.to < index + len {
-                                self.len -= cache.to - cache.from + 1;
-                                self.cache.remove(i);
-                                continue;
-                            } else {
-                                self.len -= index + len - cache.from;
-                                cache.data.splice(..(index + len - cache.from), []);
-                                cache.from = index + len;
-                                break;
-                            }
-                        }
-
+                while let Some(cache) = self.cache.get_mut(i) {
+                    if cache.from + 1 > index + len {
+                        break;
+                    }
+                    if cache.from >= index {
                         if cache.to < index + len {
-                            self.len -= cache.to - index + 1;
-                            i += 1;
-                            cache.to = index - 1;
-                            cache.data.splice((index - cache.from).., []);
+                            self.len -= cache.to - cache.from + 1;
+                            self.cache.remove(i);
                             continue;
                         } else {
-                            self.len -= len;
-                            let (left, right) = cache.data.split_at(index - cache.from);
-                            let (left, right) = (Vec::from(left), Vec::from(&right[len..]));
-                            let (from, to) = (cache.from, cache.to);
-                            self.cache.remove(i);
-                            self.cache.insert(
-                                i,
-                                CacheNode {
-                                    from,
-                                    to: index - 1,
-                                    data: left,
-                                },
-                            );
-                            self.cache.insert(
-                                i + 1,
-                                CacheNode {
-                                    from: index + len,
-                                    to,
-                                    data: right,
-                                },
-                            );
+                            self.len -= index + len - cache.from;
+                            cache.data.splice(..(index + len - cache.from), []);
+                            cache.from = index + len;
                             break;
                         }
                     }
+
+                    if cache.to < index + len {
+                        self.len -= cache.to - index + 1;
+                        i += 1;
+                        cache.to = index - 1;
+                        cache.data.splice((index - cache.from).., []);
+                        continue;
+                    } else {
+                        self.len -= len;
+                        let (left, right) = cache.data.split_at(index - cache.from);
+                        let (left, right) = (Vec::from(left), Vec::from(&right[len..]));
+                        let (from, to) = (cache.from, cache.to);
+                        self.cache.remove(i);
+                        self.cache.insert(
+                            i,
+                            CacheNode {
+                                from,
+                                to: index - 1,
+                                data: left,
+                            },
+                        );
+                        self.cache.insert(
+                            i + 1,
+                            CacheNode {
+                                from: index + len,
+                                to,
+                                data: right,
+                            },
+                        );
+                        break;
+                    }
                 }
             }
         }
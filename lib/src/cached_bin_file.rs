@@ -1,4 +1,4 @@
-use std::{cell::RefCell, io};
+use std::{cell::RefCell, io, rc::Rc};
 
 use crate::{
     A,
@@ -10,7 +10,7 @@ use crate::{
 
 pub struct CachedBinFile<Row: Binary + Clone> {
     bin: BinFile<Row>,
-    cache: RefCell<Cache<Row>>,
+    cache: Rc<RefCell<Cache<Row>>>,
 }
 
 impl<Row: Binary + Clone> CachedBinFile<Row> {
@@ -47,10 +47,17 @@ impl<Row: Binary + Clone> BaseBinFile<Row> for CachedBinFile<Row> {
     fn new(path: BDPath) -> io::Result<Self> {
         Ok(Self {
             bin: BinFile::new(path)?,
-            cache: RefCell::new(Cache::new()),
+            cache: Rc::new(RefCell::new(Cache::new())),
         })
     }
 
+    fn share(&self) -> Self {
+        Self {
+            bin: self.bin.share(),
+            cache: Rc::clone(&self.cache),
+        }
+    }
+
     fn path(&self) -> &BDPath {
         self.bin.path()
     }
@@ -103,6 +110,11 @@ impl<Row: Binary + Clone> BaseBinFile<Row> for CachedBinFile<Row> {
             .collect())
     }
 
+    /// Every change is written to the file, so it is read without the cache.
+    fn gets_uncached(&self, index: usize, len: Option<usize>) -> io::Result<Vec<Row>> {
+        self.bin.gets(index, len)
+    }
+
     fn is_empty(&self) -> io::Result<bool> {
         self.bin.is_empty()
     }
@@ -126,6 +138,24 @@ impl<Row: Binary + Clone> BaseBinFile<Row> for CachedBinFile<Row> {
         self.bin.inserts(index, datas)
     }
 
+    fn insert_bin(&mut self, index: usize, data: &[u8]) -> std::io::Result<()> {
+        unsafe {
+            self.cache.borrow_mut().move_cache(index, 1);
+        }
+        self.bin.insert_bin(index, data)
+    }
+
+    fn set(&mut self, index: usize, data: &mut Row) -> std::io::Result<()> {
+        self.bin.set(index, data)?;
+        self.cache.borrow_mut().set(index, data.clone());
+        Ok(())
+    }
+
+    fn set_bin(&mut self, index: usize, data: &[u8]) -> std::io::Result<()> {
+        self.cache.borrow_mut().remove(index, Some(1));
+        self.bin.set_bin(index, data)
+    }
+
     fn remove(&mut self, index: usize, len: Option<usize>) -> std::io::Result<()> {
         self.remove_from_cache(index, len);
         self.bin.remove(index, len)
@@ -135,4 +165,9 @@ impl<Row: Binary + Clone> BaseBinFile<Row> for CachedBinFile<Row> {
         self.cache.borrow_mut().clear();
         self.bin.clear()
     }
+
+    fn repair(&mut self) -> std::io::Result<()> {
+        self.cache.borrow_mut().clear();
+        self.bin.repair()
+    }
 }
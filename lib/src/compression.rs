@@ -0,0 +1,92 @@
+use std::io::{self, Error, ErrorKind};
+
+/// How the new pages of a `PageBinFile` and the new `DynanicBinary` values are compressed,
+/// chosen with `#[Compression(..)]` on the struct or on a field of a `Table`.
+///
+/// The compressed data starts with its codec, so the codec can be changed without a migration.
+/// The codecs other than `None` need the `compression` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    None,
+    /// The LZ4 block format, fast with a moderate ratio.
+    Lz4,
+}
+
+impl Codec {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Lz4 => "lz4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> io::Result<Self> {
+        match name {
+            "none" => Ok(Codec::None),
+            "lz4" => Ok(Codec::Lz4),
+            _ => Err(Error::other(format!("unknown codec {name}"))),
+        }
+    }
+
+    const fn byte(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Lz4 => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Lz4),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown codec {byte}"),
+            )),
+        }
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn unsupported(codec: Codec) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!("the {} codec needs the compression feature", codec.name()),
+    )
+}
+
+/// The codec followed by the compressed data, or `Codec::None` followed by the data
+/// if it doesn't get smaller.
+pub fn compress(codec: Codec, data: &[u8]) -> io::Result<Vec<u8>> {
+    let compressed: Option<Vec<u8>> = match codec {
+        Codec::None => None,
+        #[cfg(feature = "compression")]
+        Codec::Lz4 => Some(lz4_flex::block::compress_prepend_size(data)),
+        #[cfg(not(feature = "compression"))]
+        Codec::Lz4 => return Err(unsupported(codec)),
+    };
+    let (codec, data) = match &compressed {
+        Some(compressed) if compressed.len() < data.len() => (codec, compressed.as_slice()),
+        _ => (Codec::None, data),
+    };
+    let mut bin = Vec::with_capacity(1 + data.len());
+    bin.push(codec.byte());
+    bin.extend_from_slice(data);
+    Ok(bin)
+}
+
+/// The data compressed by `compress`.
+pub fn decompress(bin: &[u8]) -> io::Result<Vec<u8>> {
+    let (codec, data) = bin
+        .split_first()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "no codec"))?;
+    match Codec::from_byte(*codec)? {
+        Codec::None => Ok(data.to_vec()),
+        #[cfg(feature = "compression")]
+        Codec::Lz4 => lz4_flex::block::decompress_size_prepended(data)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error)),
+        #[cfg(not(feature = "compression"))]
+        codec => Err(unsupported(codec)),
+    }
+}
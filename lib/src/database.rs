@@ -0,0 +1,351 @@
+use std::{
+    any::{Any, TypeId, type_name},
+    fs::{File, copy, create_dir_all, read_dir, read_to_string, rename, write},
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+use crate::{
+    bin_file::BaseBinFile,
+    binary::Binary,
+    foreign::References,
+    schema::Schema,
+    table::{SpecificTableFile, Table, TableInsert},
+    transaction::Transaction,
+};
+
+/// A table type that can be registered in a `Database`, like `TableFile<Row>`.
+pub trait DatabaseTable: Any {
+    type Row: Table + 'static;
+    type BinFile: BaseBinFile<Self::Row> + 'static;
+}
+
+impl<Row: Table + 'static, BinFile: BaseBinFile<Row> + 'static> DatabaseTable
+    for SpecificTableFile<Row, BinFile>
+{
+    type Row = Row;
+    type BinFile = BinFile;
+}
+
+/// A table listed in the catalog of a `Database`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub name: String,
+    /// The `type_name` of the rows.
+    pub row: String,
+    pub row_size: usize,
+    /// The `Schema::version` of the rows.
+    pub version: u32,
+    pub indexes: Vec<String>,
+}
+
+impl CatalogEntry {
+    /// The lines written before the version was recorded have the version 0.
+    fn parse(line: &str) -> io::Result<Self> {
+        let (name, row, row_size, version, indexes) =
+            match line.split('\t').collect::<Vec<&str>>()[..] {
+                [name, row, row_size, indexes] => (name, row, row_size, "0", indexes),
+                [name, row, row_size, version, indexes] => (name, row, row_size, version, indexes),
+                _ => return Err(Error::other(format!("invalid catalog line: {line}"))),
+            };
+        Ok(Self {
+            name: name.to_owned(),
+            row: row.to_owned(),
+            row_size: row_size.parse().map_err(Error::other)?,
+            version: version.parse().map_err(Error::other)?,
+            indexes: indexes
+                .split(',')
+                .filter(|index| !index.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        })
+    }
+
+    /// Fails if the `schema` file of the table has another version or row size than the entry.
+    fn check(&self, path: &str) -> io::Result<()> {
+        let Some(schema) = Schema::read(format!("{path}/{}/schema", self.name).as_str())? else {
+            return Ok(());
+        };
+        let row_size = schema
+            .columns
+            .iter()
+            .map(|column| column.size)
+            .sum::<usize>();
+        if schema.version == self.version && row_size == self.row_size {
+            return Ok(());
+        }
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "the schema of {} changed since it was registered: version {} of {} bytes, \
+                version {} of {row_size} bytes stored",
+                self.name, self.version, self.row_size, schema.version
+            ),
+        ))
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            self.name,
+            self.row,
+            self.row_size,
+            self.version,
+            self.indexes.join(",")
+        )
+    }
+}
+
+type AddTo = for<'a> fn(&'a mut (dyn Any + 'static), &mut Transaction<'a>);
+type Checkpoint = fn(&mut dyn Any) -> io::Result<()>;
+
+struct RegisteredTable {
+    table_type: TypeId,
+    table: Box<dyn Any>,
+    add_to: AddTo,
+    checkpoint: Checkpoint,
+}
+
+fn downcast<T: Any>(data: &dyn Any) -> io::Result<&T> {
+    data.downcast_ref()
+        .ok_or_else(|| Error::other(format!("expected a {}", type_name::<T>())))
+}
+
+fn downcast_mut<T: Any>(data: &mut dyn Any) -> io::Result<&mut T> {
+    data.downcast_mut()
+        .ok_or_else(|| Error::other(format!("expected a {}", type_name::<T>())))
+}
+
+fn add_to<'a, T: DatabaseTable>(
+    table: &'a mut (dyn Any + 'static),
+    transaction: &mut Transaction<'a>,
+) where
+    <T::Row as Table>::ID: 'static,
+{
+    if let Some(table) = table.downcast_mut::<SpecificTableFile<T::Row, T::BinFile>>() {
+        transaction.table(table);
+    }
+}
+
+fn checkpoint<T: DatabaseTable>(table: &mut dyn Any) -> io::Result<()> {
+    downcast_mut::<SpecificTableFile<T::Row, T::BinFile>>(table)?.recover()
+}
+
+/// A folder of tables registered by type, with a catalog file listing them.
+///
+/// Going through the database checks the `References` registered between its tables
+/// and groups the changes on multiple tables in transactions.
+pub struct Database {
+    path: String,
+    /// Locked while the database is opened, so no other `Database` writes in it.
+    _lock: File,
+    catalog: Vec<CatalogEntry>,
+    tables: Vec<RegisteredTable>,
+    /// The indexes of the child and parent tables linked by `references`.
+    relations: Vec<(usize, usize)>,
+}
+
+impl Database {
+    /// Reads the catalog of the tables created by the previous registrations,
+    /// and checks it against the schemas of the tables.
+    ///
+    /// Fails if another `Database` has it opened.
+    pub fn open(path: String) -> io::Result<Self> {
+        create_dir_all(path.as_str())?;
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(format!("{path}/lock"))?;
+        lock.try_lock()
+            .map_err(|_| Error::other(format!("{path} is opened by another Database")))?;
+        let catalog_path = format!("{path}/catalog");
+        let catalog: Vec<CatalogEntry> = if Path::new(catalog_path.as_str()).exists() {
+            read_to_string(catalog_path)?
+                .lines()
+                .map(CatalogEntry::parse)
+                .collect::<io::Result<_>>()?
+        } else {
+            Vec::new()
+        };
+        for entry in &catalog {
+            entry.check(path.as_str())?;
+        }
+        Ok(Self {
+            path,
+            _lock: lock,
+            catalog,
+            tables: Vec::new(),
+            relations: Vec::new(),
+        })
+    }
+
+    pub fn catalog(&self) -> &[CatalogEntry] {
+        &self.catalog
+    }
+
+    /// Opens the table in the `name` folder and records it in the catalog.
+    pub fn register<T: DatabaseTable>(&mut self, name: &str) -> io::Result<()>
+    where
+        <T::Row as Table>::ID: 'static,
+    {
+        if self.index::<T>().is_ok() {
+            return Err(Error::other(format!(
+                "{} is already registered",
+                type_name::<T>()
+            )));
+        }
+        if name.is_empty() || name.contains(['\t', '\n', '/']) || name == "transaction" {
+            return Err(Error::other(format!("invalid table name: {name:?}")));
+        }
+        let table = SpecificTableFile::<T::Row, T::BinFile>::new(format!("{}/{name}", self.path))?;
+        let entry = CatalogEntry {
+            name: name.to_owned(),
+            row: type_name::<T::Row>().to_owned(),
+            row_size: T::Row::bin_size(),
+            version: T::Row::schema().version,
+            indexes: table
+                .other_index
+                .iter()
+                .map(|index| index.name().to_owned())
+                .collect(),
+        };
+        match self.catalog.iter_mut().find(|other| other.name == name) {
+            Some(other) => *other = entry,
+            None => self.catalog.push(entry),
+        }
+        self.write_catalog()?;
+
+        self.tables.push(RegisteredTable {
+            table_type: TypeId::of::<T>(),
+            table: Box::new(table),
+            add_to: add_to::<T>,
+            checkpoint: checkpoint::<T>,
+        });
+        Ok(())
+    }
+
+    /// Checks the `References` of `Child` rows to `Parent` rows on `insert` and `update`,
+    /// and applies their `OnDelete` on the `remove` of `Parent` rows.
+    ///
+    /// The tables are linked with `SpecificTableFile::add_references`,
+    /// so their handles given by `table_mut` check the `References` too.
+    pub fn references<Child: DatabaseTable, Parent: DatabaseTable>(&mut self) -> io::Result<()>
+    where
+        Child::Row: References<Parent::Row>,
+        <Child::Row as Table>::ID: 'static,
+    {
+        let (child, parent) = (self.index::<Child>()?, self.index::<Parent>()?);
+        if child == parent {
+            return Err(Error::other(
+                "a table can't reference itself through the database",
+            ));
+        }
+        if !self.relations.contains(&(child, parent)) {
+            let [child_table, parent_table] = self
+                .tables
+                .get_disjoint_mut([child, parent])
+                .map_err(Error::other)?;
+            downcast_mut::<SpecificTableFile<Child::Row, Child::BinFile>>(
+                child_table.table.as_mut(),
+            )?
+            .add_references(downcast_mut::<
+                SpecificTableFile<Parent::Row, Parent::BinFile>,
+            >(parent_table.table.as_mut())?);
+            self.relations.push((child, parent));
+        }
+        Ok(())
+    }
+
+    pub fn table<T: DatabaseTable>(&self) -> io::Result<&SpecificTableFile<T::Row, T::BinFile>> {
+        downcast(self.tables[self.index::<T>()?].table.as_ref())
+    }
+
+    pub fn table_mut<T: DatabaseTable>(
+        &mut self,
+    ) -> io::Result<&mut SpecificTableFile<T::Row, T::BinFile>> {
+        let index = self.index::<T>()?;
+        downcast_mut(self.tables[index].table.as_mut())
+    }
+
+    pub fn insert<T: DatabaseTable>(
+        &mut self,
+        data: &mut T::Row,
+    ) -> io::Result<TableInsert<<T::Row as Table>::ID>> {
+        self.table_mut::<T>()?.insert(data)
+    }
+
+    pub fn update<T: DatabaseTable>(
+        &mut self,
+        data: &mut T::Row,
+    ) -> io::Result<TableInsert<<T::Row as Table>::ID>> {
+        self.table_mut::<T>()?.update(data)
+    }
+
+    /// Removes the row and applies the `OnDelete` of the rows referencing it,
+    /// and of the rows referencing the removed ones, in one transaction.
+    pub fn remove<T: DatabaseTable>(&mut self, id: &<T::Row as Table>::ID) -> io::Result<()>
+    where
+        <T::Row as Table>::ID: 'static,
+    {
+        self.table_mut::<T>()?.remove(id)
+    }
+
+    /// A transaction on all the registered tables, found with `Transaction::table_of`.
+    ///
+    /// The `References` between the tables are checked when it is committed.
+    pub fn transaction(&mut self) -> io::Result<Transaction<'_>> {
+        let mut transaction = Transaction::new(format!("{}/transaction", self.path))?;
+        for table in &mut self.tables {
+            (table.add_to)(table.table.as_mut(), &mut transaction);
+        }
+        Ok(transaction)
+    }
+
+    /// Copies the folder of the database, `path` must not exist.
+    ///
+    /// The lock taken by `open` and the `&mut` borrow exclude the writes during the copy,
+    /// and the write-ahead logs of the tables are applied first.
+    pub fn backup(&mut self, path: &str) -> io::Result<()> {
+        if Path::new(path).exists() {
+            return Err(Error::other(format!("{path} already exists")));
+        }
+        for table in &mut self.tables {
+            (table.checkpoint)(table.table.as_mut())?;
+        }
+        copy_dir(Path::new(self.path.as_str()), Path::new(path))
+    }
+
+    fn index<T: DatabaseTable>(&self) -> io::Result<usize> {
+        self.tables
+            .iter()
+            .position(|table| table.table_type == TypeId::of::<T>())
+            .ok_or_else(|| Error::other(format!("{} isn't registered", type_name::<T>())))
+    }
+
+    /// The catalog is written to a temporary file first so it is never left half written.
+    fn write_catalog(&self) -> io::Result<()> {
+        let temporary = format!("{}/catalog.tmp", self.path);
+        write(
+            temporary.as_str(),
+            self.catalog
+                .iter()
+                .map(CatalogEntry::line)
+                .collect::<String>(),
+        )?;
+        rename(temporary, format!("{}/catalog", self.path))
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    create_dir_all(to)?;
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+        } else {
+            copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::{
+    fs::{self, create_dir_all, read_to_string, remove_file, rename, write},
+    io::{self, Error, ErrorKind},
+    num::NonZero,
+    path::Path,
+};
+
+use crate::{
+    bd_path::BDPath,
+    compression::{Codec, compress, decompress},
+    encryption::{self, keyed_hash},
+};
+
+/// Set on the ids of the deduplicated data, under the tag of the heap file.
+const DEDUP_TAG: usize = 1 << (usize::BITS - 2);
+
+/// Whether `id` is the id of deduplicated data, stored in `dyn/{id}.bin` with its count in `dyn/{id}.refs`,
+/// followed by `compressed` if the data starts with its codec.
+pub const fn is_dedup(id: NonZero<usize>) -> bool {
+    id.get() >> (usize::BITS - 2) == 1
+}
+
+/// The FNV-1a hash of `data` in the ids of the deduplicated data, stable between builds,
+/// keyed with the key of the table so the ids of an encrypted table don't reveal the data.
+fn hashed_id(path: &BDPath, data: &[u8]) -> io::Result<NonZero<usize>> {
+    let hash = data.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    Ok(with_tag(keyed_hash(path.key, hash)? as usize))
+}
+
+const fn with_tag(id: usize) -> NonZero<usize> {
+    NonZero::<usize>::MIN.saturating_add((DEDUP_TAG | (id & (DEDUP_TAG - 1))) - 1)
+}
+
+fn blob_path(path: &BDPath, id: NonZero<usize>) -> String {
+    format!("{}/{id}.bin", path.dyn_path())
+}
+
+fn refs_path(path: &BDPath, id: NonZero<usize>) -> String {
+    format!("{}/{id}.refs", path.dyn_path())
+}
+
+/// The number of rows referencing the data, 0 if the count wasn't written, and if it is compressed.
+fn counted(path: &BDPath, id: NonZero<usize>) -> io::Result<(usize, bool)> {
+    let refs = refs_path(path, id);
+    if !Path::new(refs.as_str()).exists() {
+        return Ok((0, false));
+    }
+    let text = read_to_string(refs)?;
+    let (refs, compressed) = match text.trim().split_once('\t') {
+        Some((refs, "compressed")) => (refs, true),
+        Some((_, flag)) => return Err(Error::other(format!("unknown flag {flag}"))),
+        None => (text.trim(), false),
+    };
+    Ok((refs.parse().map_err(Error::other)?, compressed))
+}
+
+/// The number of rows referencing the data, 0 if the count wasn't written.
+pub fn refs(path: &BDPath, id: NonZero<usize>) -> io::Result<usize> {
+    Ok(counted(path, id)?.0)
+}
+
+fn write_refs(path: &BDPath, id: NonZero<usize>, refs: usize, compressed: bool) -> io::Result<()> {
+    let temp = format!("{}.tmp", refs_path(path, id));
+    let flag = if compressed { "\tcompressed" } else { "" };
+    write(temp.as_str(), format!("{refs}{flag}\n"))?;
+    rename(temp, refs_path(path, id))
+}
+
+/// Writes the count through a temporary file, removes the data when it reaches 0.
+pub fn set_refs(path: &BDPath, id: NonZero<usize>, refs: usize) -> io::Result<()> {
+    if refs == 0 {
+        return remove(path, id);
+    }
+    write_refs(path, id, refs, counted(path, id)?.1)
+}
+
+/// The data, decrypted and decompressed.
+pub fn read(path: &BDPath, id: NonZero<usize>) -> io::Result<Vec<u8>> {
+    let blob = encryption::decrypt(
+        path.key,
+        &id.get().to_le_bytes(),
+        &fs::read(blob_path(path, id))?,
+    )?;
+    if counted(path, id)?.1 {
+        decompress(&blob)
+    } else {
+        Ok(blob)
+    }
+}
+
+/// Whether the data was removed while other data after it in the probe chain of its hash may be kept,
+/// its count of 0 being left so the chain isn't broken.
+fn is_tombstone(path: &BDPath, id: NonZero<usize>) -> bool {
+    !Path::new(blob_path(path, id).as_str()).exists()
+        && Path::new(refs_path(path, id).as_str()).exists()
+}
+
+/// Finds the data with the same content, or writes it without reference,
+/// compressed with the codec of `path` and encrypted with its key,
+/// in the first tombstone of the probe chain of its hash.
+/// The ids whose file has another content, or can't be decrypted, are skipped.
+///
+/// The reference is added by `reference` once the write of the row is logged.
+/// The count is written before the data, so the data is never read without knowing its codec.
+pub fn insert(path: &BDPath, data: &[u8]) -> io::Result<NonZero<usize>> {
+    create_dir_all(path.dyn_path())?;
+    let mut id = hashed_id(path, data)?;
+    let mut tombstone = None;
+    loop {
+        if is_tombstone(path, id) {
+            tombstone.get_or_insert(id);
+        } else if !Path::new(blob_path(path, id).as_str()).exists() {
+            let id = tombstone.unwrap_or(id);
+            let (bin, compressed) = match path.codec {
+                Codec::None => (data.to_vec(), false),
+                codec => (compress(codec, data)?, true),
+            };
+            write_refs(path, id, 0, compressed)?;
+            let temp = format!("{}/{id}.temp.bin", path.dyn_path());
+            write(
+                temp.as_str(),
+                encryption::encrypt(path.key, &id.get().to_le_bytes(), &bin)?,
+            )?;
+            rename(temp, blob_path(path, id))?;
+            return Ok(id);
+        } else {
+            match read(path, id) {
+                Ok(old) if old == data => return Ok(id),
+                Ok(_) => {}
+                Err(error) if error.kind() == ErrorKind::InvalidData => {}
+                Err(error) => return Err(error),
+            }
+        }
+        id = with_tag(id.get().wrapping_add(1));
+    }
+}
+
+/// Keeps the id if the data didn't change, else finds or writes the new content.
+/// Each write of a row in the main file adds a reference with `reference`, even to the same content,
+/// and the reference to the old content is removed by `Binary::delete_replaced`.
+pub fn write_data(path: &BDPath, id: NonZero<usize>, data: &[u8]) -> io::Result<NonZero<usize>> {
+    if Path::new(blob_path(path, id).as_str()).exists() && read(path, id)? == data {
+        return Ok(id);
+    }
+    insert(path, data)
+}
+
+/// Adds the reference of a row whose write is logged.
+pub fn reference(path: &BDPath, id: NonZero<usize>) -> io::Result<()> {
+    if !Path::new(blob_path(path, id).as_str()).exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("the deduplicated data {id} was removed"),
+        ));
+    }
+    set_refs(path, id, refs(path, id)? + 1)
+}
+
+/// Removes a reference to the data.
+pub fn delete(path: &BDPath, id: NonZero<usize>) -> io::Result<()> {
+    set_refs(path, id, refs(path, id)?.saturating_sub(1))
+}
+
+/// Removes the data whatever its number of references, leaving a tombstone if the probe chain continues,
+/// else removing it with the tombstones before it.
+pub fn remove(path: &BDPath, id: NonZero<usize>) -> io::Result<()> {
+    let blob = blob_path(path, id);
+    if Path::new(blob.as_str()).exists() {
+        remove_file(blob)?;
+    }
+    let next = with_tag(id.get().wrapping_add(1));
+    if is_tombstone(path, next) || Path::new(blob_path(path, next).as_str()).exists() {
+        return write_refs(path, id, 0, false);
+    }
+    let mut id = id;
+    loop {
+        let refs = refs_path(path, id);
+        if Path::new(refs.as_str()).exists() {
+            remove_file(refs)?;
+        }
+        id = with_tag(id.get().wrapping_sub(1));
+        if !is_tombstone(path, id) {
+            return Ok(());
+        }
+    }
+}
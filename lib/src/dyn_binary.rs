@@ -1,13 +1,32 @@
 use std::{
     collections::HashMap,
-    fs::{File, create_dir_all, remove_file},
+    fs::{
+        File, OpenOptions, create_dir_all, read_to_string, remove_dir_all, remove_file, rename,
+        write,
+    },
     hash::Hash,
-    io::{self, Error, Read, Write},
+    io::{self, Error, ErrorKind, Read, Write},
     num::NonZero,
     path::Path,
+    process,
 };
 
-use crate::{bd_path::BDPath, binary::Binary};
+use crate::{
+    bd_path::BDPath,
+    binary::Binary,
+    compression::{Codec, compress, decompress},
+    dedup,
+    encryption::{decrypt, encrypt},
+    heap_file,
+};
+
+/// Set on the ids of the `dyn/{id}.bin` files starting with their codec, under the tag of the deduplicated data.
+const COMPRESSED_TAG: usize = 1 << (usize::BITS - 3);
+
+/// Whether `id` is the id of a `dyn/{id}.bin` file starting with its codec.
+const fn is_compressed(id: NonZero<usize>) -> bool {
+    id.get() >> (usize::BITS - 3) == 1
+}
 
 #[derive(Debug, Clone)]
 pub struct DynanicBinary<DATA>
@@ -30,9 +49,157 @@ impl<DATA: AsBinary + PartialOrd> PartialOrd for DynanicBinary<DATA> {
     }
 }
 
+/// Where the new `DynanicBinary` values of a table are written,
+/// chosen with `#[DynStorage(..)]` on the struct or on a field of a `Table`.
+///
+/// The ids tell where the existing values are, so the storage can be changed without a migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DynStorage {
+    /// One `dyn/{id}.bin` file per value.
+    #[default]
+    Files,
+    /// Records packed in the slotted pages of `heap.bin`, for the many small values.
+    Heap,
+    /// One `dyn/{id}.bin` file per content, the id being its hash, shared by the equal values
+    /// and deleted with the last row referencing it.
+    Dedup,
+}
+
+/// Writes the data of the `dyn/{id}.bin` file through a temporary file,
+/// encrypted with the key of the table and authenticated with the id.
+fn write_file(path: &BDPath, id: NonZero<usize>, data: &[u8]) -> io::Result<NonZero<usize>> {
+    let temp = format!("{}/{id}.temp.bin", path.dyn_path());
+    {
+        let mut file = File::create(temp.as_str())?;
+        let aad = id.get().to_le_bytes();
+        if is_compressed(id) {
+            file.write_all(&encrypt(path.key, &aad, &compress(path.codec, data)?)?)?;
+        } else {
+            file.write_all(&encrypt(path.key, &aad, data)?)?;
+        }
+        file.sync_all()?;
+    }
+    rename(temp, format!("{}/{id}.bin", path.dyn_path()))?;
+    Ok(id)
+}
+
+/// Reads the data of the `dyn/{id}.bin` file, decrypted and decompressed.
+fn read_file(path: &BDPath, id: NonZero<usize>) -> io::Result<Vec<u8>> {
+    let mut file = File::open(format!("{}/{id}.bin", path.dyn_path()))?;
+    let mut data = vec![0; file.metadata()?.len() as usize];
+    file.read_exact(&mut data)?;
+    let data = decrypt(path.key, &id.get().to_le_bytes(), &data)?;
+    if is_compressed(id) {
+        decompress(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Removes the reference of a row to the data.
+pub(crate) fn delete_dyn(path: &BDPath, id: NonZero<usize>) -> io::Result<()> {
+    if dedup::is_dedup(id) {
+        dedup::delete(path, id)
+    } else {
+        remove_dyn(path, id)
+    }
+}
+
+/// Removes the data, even if it is deduplicated and other rows reference it.
+pub(crate) fn remove_dyn(path: &BDPath, id: NonZero<usize>) -> io::Result<()> {
+    if heap_file::is_heap(id) {
+        heap_file::delete(path, id)
+    } else if dedup::is_dedup(id) {
+        dedup::remove(path, id)
+    } else {
+        remove_file(format!("{}/{id}.bin", path.dyn_path()))
+    }
+}
+
+/// Counts the references of `row` to each `DynanicBinary` id in `ids`.
+pub(crate) fn dyn_ids<Row: Binary>(row: &mut Row, ids: &mut HashMap<NonZero<usize>, usize>) {
+    row.visit_dyn(&mut |id| {
+        if let Some(id) = id {
+            *ids.entry(*id).or_default() += 1;
+        }
+    });
+}
+
+/// Adds the references of `row` to its deduplicated data, once the write of the row is logged.
+pub(crate) fn reference_dyn<Row: Binary>(row: &mut Row, path: &BDPath) -> io::Result<()> {
+    let mut ids = Vec::new();
+    row.visit_dyn(&mut |id| {
+        if let Some(id) = *id
+            && dedup::is_dedup(id)
+        {
+            ids.push(id);
+        }
+    });
+    for id in ids {
+        dedup::reference(path, id)?;
+    }
+    Ok(())
+}
+
+/// Removes all the dynamic data of the table of `path`.
+pub(crate) fn clear_dyn(path: &BDPath) -> io::Result<()> {
+    remove_dir_all(path.dyn_path())?;
+    create_dir_all(path.dyn_path())?;
+    for heap in [path.heap_path(), format!("{}.journal", path.heap_path())] {
+        if Path::new(heap.as_str()).exists() {
+            remove_file(heap)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reserves the id following the last one given in the table, by creating its empty file.
+///
+/// The ids only depend on the order of the inserts, so rebuilding a table gives the same files.
+/// An id already taken, by another process or an older table, is skipped.
+/// The id is tagged if the data is compressed, so the ids stay below the tags.
+fn next_id(path: &BDPath) -> io::Result<NonZero<usize>> {
+    let tag = if path.codec == Codec::None {
+        0
+    } else {
+        COMPRESSED_TAG
+    };
+    create_dir_all(path.dyn_path())?;
+    let next_path = path.dyn_next_path();
+    let mut id = if Path::new(next_path.as_str()).exists() {
+        read_to_string(next_path.as_str())?
+            .trim()
+            .parse()
+            .map_err(Error::other)?
+    } else {
+        NonZero::<usize>::MIN
+    };
+    loop {
+        if id.get() >= COMPRESSED_TAG {
+            return Err(Error::other("no dynamic id left"));
+        }
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(format!("{}/{}.bin", path.dyn_path(), id | tag))
+        {
+            Ok(_) => break,
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                id = id.saturating_add(1);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    let temp = format!("{next_path}.{}.tmp", process::id());
+    write(temp.as_str(), format!("{}\n", id.saturating_add(1)))?;
+    rename(temp, next_path)?;
+    Ok(id | tag)
+}
+
 pub trait AsBinary: Sized {
     fn from_as_bin(data: Vec<u8>, path: &BDPath) -> io::Result<Self>;
     fn as_as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>>;
+    fn visit_as_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>));
 }
 
 impl<DATA> DynanicBinary<DATA>
@@ -68,46 +235,33 @@ where
         let id = NonZero::new(usize::from_bin(data, path)?)
             .ok_or_else(|| io::Error::other("id is zero"))?;
 
-        let mut file = File::open(format!("{}/{id}.bin", path.dyn_path()))?;
-        let mut result = vec![0; file.metadata()?.len() as usize];
-        file.read_exact(&mut result)?;
+        let result = if heap_file::is_heap(id) {
+            heap_file::read(path, id)?
+        } else if dedup::is_dedup(id) {
+            dedup::read(path, id)?
+        } else {
+            read_file(path, id)?
+        };
         Ok(DynanicBinary {
             id: Some(id),
             data: DATA::from_as_bin(result, path)?,
         })
     }
 
+    /// A `dyn/{id}.bin` file is kept if the data didn't change, else the data is written with a new id,
+    /// so the row still has its data until the write is logged and applied,
+    /// and the old one is deleted by `Binary::delete_replaced`.
     fn as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>> {
-        let id = self.id.get_or_insert_with(move || unsafe {
-            NonZero::new_unchecked({
-                if !Path::new(path.dyn_path().as_str()).exists() {
-                    create_dir_all(path.dyn_path()).expect("creation of dyn folder");
-                }
-                loop {
-                    let r = match usize::BITS {
-                        8 => rand::random::<u8>() as usize,
-                        16 => rand::random::<u16>() as usize,
-                        32 => rand::random::<u32>() as usize,
-                        64 => rand::random::<u64>() as usize,
-                        128 => rand::random::<u128>() as usize,
-                        _ => panic!("usize size not defined"),
-                    };
-                    if r == 0 {
-                        continue;
-                    }
-
-                    if !Path::new(BDPath::new_dyn(path.dir_path.clone(), r).full().as_str())
-                        .exists()
-                    {
-                        break r;
-                    }
-                }
-            })
-        });
-        let mut file = File::create(format!("{}/{id}.bin", path.dyn_path()))?;
-        file.write_all(&self.data.as_as_bin(path)?)?;
-        file.sync_all()?;
-
+        let data = self.data.as_as_bin(path)?;
+        let id = match (self.id, path.dyn_storage) {
+            (Some(id), _) if heap_file::is_heap(id) => heap_file::write(path, id, &data)?,
+            (Some(id), _) if dedup::is_dedup(id) => dedup::write_data(path, id, &data)?,
+            (None, DynStorage::Heap) => heap_file::insert(path, &data)?,
+            (None, DynStorage::Dedup) => dedup::insert(path, &data)?,
+            (Some(id), _) if read_file(path, id).is_ok_and(|old| old == data) => id,
+            (Some(_), _) | (None, DynStorage::Files) => write_file(path, next_id(path)?, &data)?,
+        };
+        self.id = Some(id);
         id.get().as_bin(path)
     }
 
@@ -116,7 +270,21 @@ where
     }
 
     fn delete(&self, path: &BDPath) -> io::Result<()> {
-        remove_file(format!("{}/{}.bin", path.dyn_path(), self.id_error()?))
+        delete_dyn(path, self.id_error()?)
+    }
+
+    /// The deduplicated data is referenced once per write, so its reference is removed even if it is kept.
+    fn delete_replaced(&self, new: &Self, path: &BDPath) -> io::Result<()> {
+        if self.id == new.id && !self.id.is_some_and(dedup::is_dedup) {
+            Ok(())
+        } else {
+            self.delete(path)
+        }
+    }
+
+    fn visit_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {
+        visit(&mut self.id);
+        self.data.visit_as_dyn(visit);
     }
 }
 
@@ -128,6 +296,8 @@ impl AsBinary for String {
     fn as_as_bin(&mut self, _: &BDPath) -> io::Result<Vec<u8>> {
         Ok(self.bytes().collect())
     }
+
+    fn visit_as_dyn(&mut self, _: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {}
 }
 
 impl<T> AsBinary for Vec<T>
@@ -141,11 +311,16 @@ where
     }
 
     fn as_as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>> {
-        Ok(self
-            .iter_mut()
-            .flat_map(|item| item.as_bin(path))
-            .flatten()
-            .collect())
+        let mut bin = Vec::with_capacity(self.len() * T::bin_size());
+        for item in self {
+            bin.extend(item.as_bin(path)?);
+        }
+        Ok(bin)
+    }
+    fn visit_as_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {
+        for item in self {
+            item.visit_dyn(visit);
+        }
     }
 }
 
@@ -158,28 +333,45 @@ where
     where
         Self: Sized,
     {
-        Ok(data
-            .chunks(K::bin_size() + V::bin_size())
-            .flat_map(|data| -> io::Result<(K, V)> {
+        data.chunks(K::bin_size() + V::bin_size())
+            .map(|data| -> io::Result<(K, V)> {
                 Ok((
                     K::from_bin(data, path)?,
                     V::from_bin(&data[K::bin_size()..], path)?,
                 ))
             })
-            .collect())
+            .collect()
     }
 
+    /// The keys are written by taking the entries out, since writing them can change their ids.
     fn as_as_bin(&mut self, path: &BDPath) -> io::Result<Vec<u8>> {
-        Ok(self
-            .iter_mut()
-            .flat_map(|t| -> io::Result<Vec<u8>> {
-                let mut a = unsafe { (t.0 as *const K as *mut K).as_mut() }
-                    .ok_or(io::Error::other("HashMap as_bin. key cant mutate"))?
-                    .as_bin(path)?;
-                a.extend_from_slice(&t.1.as_bin(path)?);
-                Ok(a)
+        let mut bin = Vec::with_capacity(self.len() * (K::bin_size() + V::bin_size()));
+        let mut entries = Vec::with_capacity(self.len());
+        let mut result = Ok(());
+        for (mut key, mut value) in self.drain() {
+            if result.is_ok() {
+                result = key.as_bin(path).and_then(|key| {
+                    bin.extend(key);
+                    bin.extend(value.as_bin(path)?);
+                    Ok(())
+                });
+            }
+            entries.push((key, value));
+        }
+        self.extend(entries);
+        result.map(|()| bin)
+    }
+
+    /// The keys are visited like the values, the entries being taken out then put back.
+    fn visit_as_dyn(&mut self, visit: &mut dyn FnMut(&mut Option<NonZero<usize>>)) {
+        let entries = self
+            .drain()
+            .map(|(mut key, mut value)| {
+                key.visit_dyn(visit);
+                value.visit_dyn(visit);
+                (key, value)
             })
-            .flatten()
-            .collect())
+            .collect::<Vec<(K, V)>>();
+        self.extend(entries);
     }
 }
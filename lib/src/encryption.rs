@@ -0,0 +1,207 @@
+use std::{
+    fmt,
+    fs::{self, create_dir_all, rename},
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+use crate::bd_path::BDPath;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305,
+    aead::{Aead, Payload},
+};
+
+/// The random nonce written before the encrypted data.
+const NONCE_SIZE: usize = 24;
+/// The authentication tag written after the encrypted data.
+pub(crate) const TAG_SIZE: usize = 16;
+/// The bytes added to the data by `encrypt`.
+pub const OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+/// Encrypted in the `key` file of the tables, to tell a wrong key before reading the rows.
+const KEY_CHECK: &[u8] = b"rust_db key";
+
+/// The 256-bit key encrypting the files of a table with XChaCha20-Poly1305,
+/// given to `SpecificTableFile::new_encrypted`.
+///
+/// The data is authenticated, so reading data changed outside of the table fails.
+/// Needs the `encryption` feature.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub const fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// A random key, to be kept by the caller.
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+fn unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "the encryption needs the encryption feature",
+    )
+}
+
+#[cfg(feature = "encryption")]
+fn cipher(key: EncryptionKey) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(&key.0.into())
+}
+
+/// The nonce, the data encrypted with the key and the tag authenticating them with `aad`,
+/// the data itself without a key.
+///
+/// `aad` is where the data is written, so the encrypted data can't be moved elsewhere.
+pub fn encrypt(key: Option<EncryptionKey>, aad: &[u8], data: &[u8]) -> io::Result<Vec<u8>> {
+    let Some(key) = key else {
+        return Ok(data.to_vec());
+    };
+    #[cfg(feature = "encryption")]
+    {
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let mut bin = nonce.to_vec();
+        bin.extend(
+            cipher(key)
+                .encrypt(&nonce.into(), Payload { msg: data, aad })
+                .map_err(|_| Error::other("failed to encrypt"))?,
+        );
+        Ok(bin)
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = (key, aad);
+        Err(unsupported())
+    }
+}
+
+/// The data encrypted by `encrypt` with the same key and `aad`,
+/// fails if the key is another one or if the data was changed.
+pub fn decrypt(key: Option<EncryptionKey>, aad: &[u8], bin: &[u8]) -> io::Result<Vec<u8>> {
+    let Some(key) = key else {
+        return Ok(bin.to_vec());
+    };
+    if bin.len() < OVERHEAD {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "truncated encrypted data",
+        ));
+    }
+    #[cfg(feature = "encryption")]
+    {
+        let (nonce, data) = bin.split_at(NONCE_SIZE);
+        cipher(key)
+            .decrypt(nonce.into(), Payload { msg: data, aad })
+            .map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "the encrypted data was changed or the key is wrong",
+                )
+            })
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = (key, aad);
+        Err(unsupported())
+    }
+}
+
+/// The tag of the encryption of nothing with `nonce`, which only the key derives from it.
+fn empty_tag(key: EncryptionKey, nonce: [u8; NONCE_SIZE]) -> io::Result<[u8; TAG_SIZE]> {
+    #[cfg(feature = "encryption")]
+    {
+        let tag = cipher(key)
+            .encrypt(&nonce.into(), [].as_slice())
+            .map_err(|_| Error::other("failed to encrypt"))?;
+        let mut bytes = [0; TAG_SIZE];
+        bytes.copy_from_slice(&tag);
+        Ok(bytes)
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = (key, nonce);
+        Err(unsupported())
+    }
+}
+
+/// `hash` keyed with the key, so the ids derived from the data don't reveal it.
+///
+/// It is the tag of the encryption of nothing with `hash` as nonce,
+/// a nonce `encrypt` doesn't draw since its other bytes are 0.
+pub fn keyed_hash(key: Option<EncryptionKey>, hash: u64) -> io::Result<u64> {
+    let Some(key) = key else {
+        return Ok(hash);
+    };
+    let mut nonce = [0; NONCE_SIZE];
+    nonce[..8].copy_from_slice(&hash.to_le_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&empty_tag(key, nonce)?[..8]);
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// The tag of encrypted data at `index` keyed with the key, like `keyed_hash`,
+/// to authenticate many encrypted values together from their tags.
+pub(crate) fn keyed_tag(
+    key: EncryptionKey,
+    index: u64,
+    tag: &[u8; TAG_SIZE],
+) -> io::Result<[u8; TAG_SIZE]> {
+    let mut nonce = [0; NONCE_SIZE];
+    nonce[..8].copy_from_slice(&index.to_le_bytes());
+    nonce[8..].copy_from_slice(tag);
+    empty_tag(key, nonce)
+}
+
+/// The content of the `key` file of a table encrypted with `key`, empty without a key.
+pub(crate) fn key_check(key: Option<EncryptionKey>) -> io::Result<Vec<u8>> {
+    match key {
+        None => Ok(Vec::new()),
+        Some(_) => encrypt(key, b"key", KEY_CHECK),
+    }
+}
+
+/// Checks that the table of `path` is encrypted with the key of `path`, or isn't encrypted
+/// if it has none. A new table is encrypted by writing its `key` file.
+pub(crate) fn check_key(path: &BDPath) -> io::Result<()> {
+    let dir = path.dir_path.as_str();
+    let check = match fs::read(path.key_path()) {
+        Ok(check) => Some(check),
+        Err(error) if error.kind() == ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
+    match (check, path.key) {
+        (None, None) => Ok(()),
+        (Some(check), Some(_)) => match decrypt(path.key, b"key", &check) {
+            Ok(check) if check == KEY_CHECK => Ok(()),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{dir} is encrypted with another key"),
+            )),
+        },
+        (Some(_), None) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{dir} is encrypted, open it with new_encrypted"),
+        )),
+        (None, Some(_)) if Path::new(path.full().as_str()).exists() => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{dir} isn't encrypted, rotate_key encrypts it"),
+        )),
+        (None, Some(_)) => {
+            create_dir_all(dir)?;
+            let temp = format!("{}.tmp", path.key_path());
+            fs::write(temp.as_str(), key_check(path.key)?)?;
+            rename(temp, path.key_path())
+        }
+    }
+}
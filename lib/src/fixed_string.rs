@@ -0,0 +1,82 @@
+use std::{
+    fmt,
+    io::{self, Error, ErrorKind},
+};
+
+use crate::{bd_path::BDPath, binary::Binary};
+
+/// A `String` of at most `N` bytes stored inline in the row, unlike `DynanicBinary<String>`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FixedString<const N: usize> {
+    data: String,
+}
+
+impl<const N: usize> FixedString<N> {
+    /// Fails if `data` is longer than `N` bytes.
+    pub fn new(data: impl Into<String>) -> io::Result<Self> {
+        let data = data.into();
+        if data.len() > N {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} bytes don't fit in a FixedString<{N}>", data.len()),
+            ));
+        }
+        Ok(Self { data })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.data
+    }
+
+    pub fn into_string(self) -> String {
+        self.data
+    }
+
+    /// The number of bytes of the length written before the string.
+    const fn len_size() -> usize {
+        if N <= u8::MAX as usize {
+            1
+        } else if N <= u16::MAX as usize {
+            2
+        } else {
+            4
+        }
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.data)
+    }
+}
+
+impl<const N: usize> Binary for FixedString<N> {
+    fn from_bin(data: &[u8], _: &BDPath) -> io::Result<Self> {
+        let mut len = [0; 4];
+        len[..Self::len_size()].copy_from_slice(&data[..Self::len_size()]);
+        let len = u32::from_le_bytes(len) as usize;
+        if len > N {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid FixedString<{N}> length: {len}"),
+            ));
+        }
+        let bytes = &data[Self::len_size()..Self::len_size() + len];
+        let data = String::from_utf8(bytes.to_vec())
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        Ok(Self { data })
+    }
+    fn as_bin(&mut self, _: &BDPath) -> io::Result<Vec<u8>> {
+        let mut bin = Vec::with_capacity(Self::bin_size());
+        bin.extend(&(self.data.len() as u32).to_le_bytes()[..Self::len_size()]);
+        bin.extend(self.data.as_bytes());
+        bin.resize(Self::bin_size(), 0);
+        Ok(bin)
+    }
+    fn bin_size() -> usize {
+        Self::len_size() + N
+    }
+    fn delete(&self, _: &BDPath) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,8 +1,17 @@
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::VecDeque,
+    io::{self, Error},
+    rc::Rc,
+};
+
 use crate::{
     bd_path::BDPath,
     binary::Binary,
     prelude::BaseBinFile,
-    table::{SpecificTableFile, Table, TableGet},
+    table::{SpecificTableFile, Table, TableGet, TableInsert},
+    transaction::Transaction,
 };
 
 #[derive(Debug, Clone)]
@@ -61,3 +70,481 @@ impl<Row: Table> Foreign<Row> {
         table.get_by_id(&self.id)
     }
 }
+
+/// What happens to the rows referencing a removed row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDelete {
+    /// The row can't be removed while it is referenced.
+    Restrict,
+    /// The referencing rows are removed too.
+    Cascade,
+    /// The referencing `Option<Foreign>` column is set to `None`.
+    SetNull,
+}
+
+/// Implemented by the `Table` derive for the rows with `#[References(..)]` columns to `Parent`.
+pub trait References<Parent: Table>: Table {
+    /// The rows of `table` referencing the parent `id`, read from the indexes of the `Foreign` columns.
+    fn referencing<BinFile: BaseBinFile<Self>>(
+        table: &SpecificTableFile<Self, BinFile>,
+        id: &Parent::ID,
+    ) -> io::Result<Vec<Self>>;
+    /// The first column referencing a parent row, read from the start of the index of each column.
+    fn referenced<BinFile: BaseBinFile<Self>>(
+        table: &SpecificTableFile<Self, BinFile>,
+    ) -> io::Result<Option<&'static str>>;
+    /// The name, `OnDelete` and referenced id of the columns that aren't `None`.
+    fn references(&self) -> Vec<(&'static str, OnDelete, &Parent::ID)>;
+    /// Sets the `Option<Foreign>` column to `None`.
+    fn set_null(&mut self, column: &'static str);
+}
+
+/// Whether a transaction keeps (`Some(true)`) or removes (`Some(false)`) the row `id`
+/// of the table in the folder, `None` if it doesn't change it.
+pub(crate) type Staging<'s> = &'s dyn Fn(&str, &dyn Any) -> Option<bool>;
+
+/// The `References` between a table and an other one, checked by the table
+/// once added with `SpecificTableFile::add_references`.
+pub(crate) trait Relation<Row: Table> {
+    /// The first column of `row` referencing a row missing from the parent table.
+    fn missing_parent(
+        &self,
+        _row: &Row,
+        _staging: Staging<'_>,
+    ) -> io::Result<Option<&'static str>> {
+        Ok(None)
+    }
+    /// The first column of the child table referencing the row `id`,
+    /// but in the rows changed by the transaction, whose own references are checked.
+    fn referenced_by(
+        &self,
+        _id: &Row::ID,
+        _staging: Staging<'_>,
+    ) -> io::Result<Option<&'static str>> {
+        Ok(None)
+    }
+    /// The first column of the child table referencing a row of the table.
+    fn referenced(&self) -> io::Result<Option<&'static str>> {
+        Ok(None)
+    }
+    /// Plans the `OnDelete` of the child rows referencing the removed row `id`.
+    fn on_delete(&self, _id: &Row::ID, _cascade: &mut Cascade) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Added to the child table, with a handle on the parent table without its relations,
+/// which reach back the child table.
+struct ParentRelation<Parent: Table, ParentBinFile: BaseBinFile<Parent>> {
+    parent: SpecificTableFile<Parent, ParentBinFile>,
+}
+
+impl<Row, Parent, ParentBinFile> Relation<Row> for ParentRelation<Parent, ParentBinFile>
+where
+    Row: References<Parent>,
+    Parent: Table,
+    Parent::ID: 'static,
+    ParentBinFile: BaseBinFile<Parent>,
+{
+    fn missing_parent(&self, row: &Row, staging: Staging<'_>) -> io::Result<Option<&'static str>> {
+        for (column, _, id) in row.references() {
+            let kept = match staging(self.parent.bin.path().dir_path.as_str(), id) {
+                Some(kept) => kept,
+                None => self.parent.search(id)?.is_ok(),
+            };
+            if !kept {
+                return Ok(Some(column));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Added to the parent table, with a handle on the child table sharing its relations,
+/// so the rows removed by `Cascade` apply the `OnDelete` of their own children.
+struct ChildRelation<Child: Table, ChildBinFile: BaseBinFile<Child>> {
+    child: SpecificTableFile<Child, ChildBinFile>,
+}
+
+impl<Row, Child, ChildBinFile> Relation<Row> for ChildRelation<Child, ChildBinFile>
+where
+    Row: Table,
+    Child: References<Row> + 'static,
+    Child::ID: 'static,
+    ChildBinFile: BaseBinFile<Child> + 'static,
+{
+    fn referenced_by(
+        &self,
+        id: &Row::ID,
+        staging: Staging<'_>,
+    ) -> io::Result<Option<&'static str>> {
+        let path = self.child.bin.path().dir_path.as_str();
+        for (row, columns) in self.child.referencing_columns::<Row>(id)? {
+            if staging(path, row.id()).is_none() {
+                return Ok(columns.first().map(|(column, _)| *column));
+            }
+        }
+        Ok(None)
+    }
+
+    fn referenced(&self) -> io::Result<Option<&'static str>> {
+        Child::referenced(&self.child)
+    }
+
+    fn on_delete(&self, id: &Row::ID, cascade: &mut Cascade) -> io::Result<()> {
+        ReferencingTable::<Row>::on_delete(&self.child, id, cascade)
+    }
+}
+
+/// The name and `OnDelete` of the columns referencing a row.
+pub(crate) type Columns = Vec<(&'static str, OnDelete)>;
+
+/// The first `Restrict` column.
+pub(crate) fn restricted(columns: &[(&'static str, OnDelete)]) -> Option<&'static str> {
+    columns
+        .iter()
+        .find(|(_, on_delete)| *on_delete == OnDelete::Restrict)
+        .map(|(column, _)| *column)
+}
+
+/// The rows of a table removed or updated by the `OnDelete` of a removed row.
+struct Changes<Row: Table, BinFile: BaseBinFile<Row>> {
+    table: SpecificTableFile<Row, BinFile>,
+    removed: Vec<Row::ID>,
+    updated: Vec<Row>,
+}
+
+trait CascadeTable {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn len(&self) -> usize;
+    /// Adds the table to the transaction and stages its changes.
+    fn stage<'a>(&'a mut self, transaction: &mut Transaction<'a>) -> io::Result<()>;
+}
+
+impl<Row, BinFile> CascadeTable for Changes<Row, BinFile>
+where
+    Row: Table + 'static,
+    Row::ID: 'static,
+    BinFile: BaseBinFile<Row> + 'static,
+{
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn len(&self) -> usize {
+        self.removed.len() + self.updated.len()
+    }
+
+    fn stage<'a>(&'a mut self, transaction: &mut Transaction<'a>) -> io::Result<()> {
+        let table = transaction.table(&mut self.table);
+        for id in &self.removed {
+            transaction.remove(table, id)?;
+        }
+        for row in &mut self.updated {
+            if let TableInsert::DuplicateUnique(column, _) = transaction.update(table, row)? {
+                return Err(Error::other(format!(
+                    "setting {column} to null duplicates a unique value"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+type Planned = Box<dyn FnOnce(&mut Cascade) -> io::Result<()>>;
+
+/// The changes made by the `OnDelete` of the rows referencing a removed row,
+/// planned breadth first through the tables added with `add_references`.
+pub struct Cascade {
+    /// The changes of each table by folder, in the order the tables are reached.
+    tables: Vec<(String, Box<dyn CascadeTable>)>,
+    /// The removed rows whose children aren't planned yet.
+    removed: VecDeque<Planned>,
+}
+
+impl Cascade {
+    fn new<Row, BinFile>(table: &SpecificTableFile<Row, BinFile>, id: &Row::ID) -> io::Result<Self>
+    where
+        Row: Table + 'static,
+        Row::ID: 'static,
+        BinFile: BaseBinFile<Row> + 'static,
+    {
+        let mut cascade = Self {
+            tables: Vec::new(),
+            removed: VecDeque::new(),
+        };
+        cascade.changes(table)?.removed.push(id.clone());
+        cascade.remove(table, id.clone());
+        Ok(cascade)
+    }
+
+    /// The changes of `table`, added empty when it is reached the first time.
+    fn changes<Row, BinFile>(
+        &mut self,
+        table: &SpecificTableFile<Row, BinFile>,
+    ) -> io::Result<&mut Changes<Row, BinFile>>
+    where
+        Row: Table + 'static,
+        Row::ID: 'static,
+        BinFile: BaseBinFile<Row> + 'static,
+    {
+        let path = table.bin.path().dir_path.as_str();
+        let index = match self.tables.iter().position(|(other, _)| other == path) {
+            Some(index) => index,
+            None => {
+                self.tables.push((
+                    path.to_owned(),
+                    Box::new(Changes {
+                        table: table.share(),
+                        removed: Vec::new(),
+                        updated: Vec::new(),
+                    }),
+                ));
+                self.tables.len() - 1
+            }
+        };
+        self.tables[index]
+            .1
+            .as_any_mut()
+            .downcast_mut()
+            .ok_or_else(|| Error::other(format!("{path} is opened with another row type")))
+    }
+
+    /// Plans the `OnDelete` of the children of the removed row `id` once its siblings are planned.
+    fn remove<Row, BinFile>(&mut self, table: &SpecificTableFile<Row, BinFile>, id: Row::ID)
+    where
+        Row: Table + 'static,
+        Row::ID: 'static,
+        BinFile: BaseBinFile<Row>,
+    {
+        let relations = Rc::clone(&table.relations);
+        self.removed.push_back(Box::new(move |cascade| {
+            for relation in relations.borrow().iter() {
+                relation.on_delete(&id, cascade)?;
+            }
+            Ok(())
+        }));
+    }
+
+    /// Plans the rest of the cascade, then removes the row of `table` alone,
+    /// or all the rows in one transaction.
+    fn apply<Row, BinFile>(
+        mut self,
+        table: &mut SpecificTableFile<Row, BinFile>,
+        id: &Row::ID,
+    ) -> io::Result<()>
+    where
+        Row: Table,
+        BinFile: BaseBinFile<Row>,
+    {
+        while let Some(planned) = self.removed.pop_front() {
+            planned(&mut self)?;
+        }
+        if self
+            .tables
+            .iter()
+            .map(|(_, changes)| changes.len())
+            .sum::<usize>()
+            == 1
+        {
+            return table.remove_row(id);
+        }
+
+        let mut transaction =
+            Transaction::new(format!("{}/transaction", table.bin.path().dir_path))?;
+        for (_, changes) in &mut self.tables {
+            if let Err(error) = changes.stage(&mut transaction) {
+                transaction.rollback()?;
+                return Err(error);
+            }
+        }
+        transaction.commit()
+    }
+}
+
+/// A table whose rows reference the rows of `Parent`.
+pub trait ReferencingTable<Parent: Table> {
+    /// Plans the changes caused by the removal of the parent,
+    /// fails if it is referenced by a `Restrict` column.
+    fn on_delete(&self, id: &Parent::ID, cascade: &mut Cascade) -> io::Result<()>;
+}
+
+impl<Parent, Row, BinFile> ReferencingTable<Parent> for SpecificTableFile<Row, BinFile>
+where
+    Parent: Table,
+    Row: References<Parent> + 'static,
+    Row::ID: 'static,
+    BinFile: BaseBinFile<Row> + 'static,
+{
+    /// The rows already removed by the cascade are skipped,
+    /// and the rows set to null by another parent are removed if a column cascades.
+    fn on_delete(&self, id: &Parent::ID, cascade: &mut Cascade) -> io::Result<()> {
+        let children = self.referencing_columns(id)?;
+        if children.is_empty() {
+            return Ok(());
+        }
+        let changes = cascade.changes(self)?;
+        let same = |row: &Row, id: &Row::ID| row.id_cmp(id) == Some(Ordering::Equal);
+
+        let mut removed = Vec::new();
+        for (row, columns) in children {
+            if changes.removed.iter().any(|id| same(&row, id)) {
+                continue;
+            }
+            if let Some(column) = restricted(&columns) {
+                return Err(Error::other(format!(
+                    "the row is still referenced by {column}"
+                )));
+            }
+            let updated = changes
+                .updated
+                .iter()
+                .position(|updated| same(&row, updated.id()));
+            if columns
+                .iter()
+                .any(|(_, on_delete)| *on_delete == OnDelete::Cascade)
+            {
+                if let Some(updated) = updated {
+                    changes.updated.remove(updated);
+                }
+                changes.removed.push(row.id().clone());
+                removed.push(row.id().clone());
+                continue;
+            }
+            let row = match updated {
+                Some(updated) => &mut changes.updated[updated],
+                None => {
+                    changes.updated.push(row);
+                    changes
+                        .updated
+                        .last_mut()
+                        .ok_or_else(|| Error::other("missing row"))?
+                }
+            };
+            for (column, _) in columns {
+                row.set_null(column);
+            }
+        }
+        for id in removed {
+            cascade.remove(self, id);
+        }
+        Ok(())
+    }
+}
+
+impl<Row: Table + 'static, BinFile: BaseBinFile<Row> + 'static> SpecificTableFile<Row, BinFile>
+where
+    Row::ID: 'static,
+{
+    /// Checks the `References` of the rows to the rows of `parent` on `insert`, `update`
+    /// and in the transactions, and applies their `OnDelete` on the `remove` of a `parent` row.
+    ///
+    /// The tables keep a handle on each other, sharing the caches of `self` and `parent`.
+    pub fn add_references<Parent: Table + 'static, ParentBinFile: BaseBinFile<Parent> + 'static>(
+        &mut self,
+        parent: &mut SpecificTableFile<Parent, ParentBinFile>,
+    ) where
+        Row: References<Parent>,
+        Parent::ID: 'static,
+    {
+        let mut parent_handle = parent.share();
+        parent_handle.relations = Rc::default();
+        self.relations.borrow_mut().push(Box::new(ParentRelation {
+            parent: parent_handle,
+        }));
+        parent
+            .relations
+            .borrow_mut()
+            .push(Box::new(ChildRelation::<Row, BinFile> {
+                child: self.share(),
+            }));
+    }
+
+    /// Removes the row and applies the `OnDelete` of the `children` referencing it,
+    /// then of the tables added with `add_references` referencing the removed rows, in one transaction.
+    pub fn remove_referenced(
+        &mut self,
+        id: &Row::ID,
+        children: &mut [&mut dyn ReferencingTable<Row>],
+    ) -> io::Result<()> {
+        let mut cascade = Cascade::new(self, id)?;
+        for child in children.iter() {
+            child.on_delete(id, &mut cascade)?;
+        }
+        cascade.apply(self, id)
+    }
+
+    /// Removes the row and applies the `OnDelete` of the tables added with `add_references`
+    /// referencing it, then referencing the removed rows, in one transaction.
+    ///
+    /// Fails without changing anything if a removed row is referenced by a `Restrict` column.
+    pub fn remove(&mut self, id: &Row::ID) -> io::Result<()> {
+        Cascade::new(self, id)?.apply(self, id)
+    }
+}
+
+impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTableFile<Row, BinFile> {
+    /// The rows referencing the parent `id`, with the name and `OnDelete` of their columns referencing it.
+    pub(crate) fn referencing_columns<Parent: Table>(
+        &self,
+        id: &Parent::ID,
+    ) -> io::Result<Vec<(Row, Columns)>>
+    where
+        Row: References<Parent>,
+    {
+        let mut children = Vec::new();
+        for row in Row::referencing(self, id)? {
+            let columns: Columns = row
+                .references()
+                .into_iter()
+                .filter(|(_, _, parent)| parent.partial_cmp(&id) == Some(Ordering::Equal))
+                .map(|(column, on_delete, _)| (column, on_delete))
+                .collect();
+            if !columns.is_empty() {
+                children.push((row, columns));
+            }
+        }
+        Ok(children)
+    }
+
+    /// The first column of `child` referencing a row that isn't in this table.
+    pub fn missing_reference<Child: References<Row>>(
+        &self,
+        child: &Child,
+    ) -> io::Result<Option<&'static str>> {
+        for (column, _, id) in child.references() {
+            if self.search(id)?.is_err() {
+                return Ok(Some(column));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn insert_referencing<Parent: Table, ParentBinFile: BaseBinFile<Parent>>(
+        &mut self,
+        data: &mut Row,
+        parent: &SpecificTableFile<Parent, ParentBinFile>,
+    ) -> io::Result<TableInsert<Row::ID>>
+    where
+        Row: References<Parent>,
+    {
+        match parent.missing_reference(data)? {
+            Some(column) => Ok(TableInsert::MissingReference(column)),
+            None => self.insert(data),
+        }
+    }
+
+    pub fn update_referencing<Parent: Table, ParentBinFile: BaseBinFile<Parent>>(
+        &mut self,
+        data: &mut Row,
+        parent: &SpecificTableFile<Parent, ParentBinFile>,
+    ) -> io::Result<TableInsert<Row::ID>>
+    where
+        Row: References<Parent>,
+    {
+        match parent.missing_reference(data)? {
+            Some(column) => Ok(TableInsert::MissingReference(column)),
+            None => self.update(data),
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    fs::{self, File, remove_file, rename},
+    io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    num::NonZero,
+    path::Path,
+};
+
+use crate::{
+    bd_path::BDPath,
+    compression::{Codec, compress, decompress},
+    encryption::{self, EncryptionKey, OVERHEAD},
+};
+
+const PAGE_SIZE: usize = 4096;
+/// Span, number of slots and start of the records, as u32.
+const RUN_HEADER: usize = 12;
+/// Offset and len of a record, as u32.
+const SLOT_SIZE: usize = 8;
+/// The len of a free slot.
+const FREE: u32 = u32::MAX;
+/// Set on the len of the records starting with their codec.
+const COMPRESSED: usize = 1 << 31;
+const SLOT_BITS: u32 = 16;
+/// Set on the ids of the records of the heap file, the ids of `dyn/{id}.bin` files never reach it.
+const HEAP_TAG: usize = 1 << (usize::BITS - 1);
+
+/// Whether `id` is a (page, slot) reference of the heap file rather than a `dyn/{id}.bin` file.
+pub const fn is_heap(id: NonZero<usize>) -> bool {
+    id.get() & HEAP_TAG != 0
+}
+
+const fn slot_id(page: u64, slot: usize) -> NonZero<usize> {
+    NonZero::<usize>::MIN.saturating_add((HEAP_TAG | (page as usize) << SLOT_BITS | slot) - 1)
+}
+
+const fn page_slot(id: NonZero<usize>) -> (u64, usize) {
+    (
+        ((id.get() & !HEAP_TAG) >> SLOT_BITS) as u64,
+        id.get() & ((1 << SLOT_BITS) - 1),
+    )
+}
+
+fn read_u32(data: &[u8], at: usize) -> usize {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[at..at + 4]);
+    u32::from_le_bytes(bytes) as usize
+}
+
+fn write_u32(data: &mut [u8], at: usize, value: usize) -> io::Result<()> {
+    let value = u32::try_from(value).map_err(Error::other)?;
+    data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+/// Consecutive pages holding records, more than one page only for the records too big for a page.
+///
+/// The slots are after the header and the records are written from the end of the run.
+struct Run {
+    page: u64,
+    data: Vec<u8>,
+}
+
+impl Run {
+    fn new(page: u64, len: usize) -> io::Result<Self> {
+        let span = (RUN_HEADER + SLOT_SIZE + len).div_ceil(PAGE_SIZE);
+        let mut data = vec![0; span * PAGE_SIZE];
+        write_u32(&mut data, 0, span)?;
+        write_u32(&mut data, 8, span * PAGE_SIZE)?;
+        Ok(Self { page, data })
+    }
+
+    fn span(&self) -> u64 {
+        read_u32(&self.data, 0) as u64
+    }
+
+    fn slots(&self) -> usize {
+        read_u32(&self.data, 4)
+    }
+
+    fn start(&self) -> usize {
+        read_u32(&self.data, 8)
+    }
+
+    /// The offset and len of the record and whether it starts with its codec.
+    fn slot(&self, slot: usize) -> io::Result<(usize, usize, bool)> {
+        let at = RUN_HEADER + slot * SLOT_SIZE;
+        let len = read_u32(&self.data, at + 4);
+        if slot >= self.slots() || len == FREE as usize {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no record in slot {slot} of page {}", self.page),
+            ));
+        }
+        Ok((
+            read_u32(&self.data, at),
+            len & !COMPRESSED,
+            len & COMPRESSED != 0,
+        ))
+    }
+
+    /// The data of the record, decrypted with the key of the table and decompressed.
+    fn record(&self, slot: usize, key: Option<EncryptionKey>) -> io::Result<Vec<u8>> {
+        let (offset, len, compressed) = self.slot(slot)?;
+        let record = encryption::decrypt(
+            key,
+            &aad(slot_id(self.page, slot)),
+            &self.data[offset..offset + len],
+        )?;
+        if compressed {
+            decompress(&record)
+        } else {
+            Ok(record)
+        }
+    }
+
+    fn set_slot(
+        &mut self,
+        slot: usize,
+        offset: usize,
+        len: usize,
+        compressed: bool,
+    ) -> io::Result<()> {
+        let at = RUN_HEADER + slot * SLOT_SIZE;
+        write_u32(&mut self.data, at, offset)?;
+        write_u32(
+            &mut self.data,
+            at + 4,
+            if compressed { len | COMPRESSED } else { len },
+        )
+    }
+
+    fn free_slot(&self) -> Option<usize> {
+        (0..self.slots())
+            .find(|slot| read_u32(&self.data, RUN_HEADER + slot * SLOT_SIZE + 4) == FREE as usize)
+    }
+
+    /// The bytes left for a record of `len` bytes, once the freed records are compacted.
+    fn fits(&self, len: usize) -> bool {
+        let slots = self.slots() + usize::from(self.free_slot().is_none());
+        let used = (0..self.slots())
+            .filter_map(|slot| self.slot(slot).ok())
+            .map(|(_, len, _)| len)
+            .sum::<usize>();
+        slots < 1 << SLOT_BITS && RUN_HEADER + slots * SLOT_SIZE + used + len <= self.data.len()
+    }
+
+    /// Moves the records to the end of the run to join the space left by the freed ones,
+    /// the space left is zeroed so the moved records don't leave copies.
+    fn compact(&mut self) -> io::Result<()> {
+        let records = (0..self.slots())
+            .filter_map(|slot| {
+                let (offset, len, compressed) = self.slot(slot).ok()?;
+                Some((slot, self.data[offset..offset + len].to_vec(), compressed))
+            })
+            .collect::<Vec<(usize, Vec<u8>, bool)>>();
+        let mut start = self.data.len();
+        for (slot, record, compressed) in records {
+            start -= record.len();
+            self.data[start..start + record.len()].copy_from_slice(&record);
+            self.set_slot(slot, start, record.len(), compressed)?;
+        }
+        let slots_end = RUN_HEADER + self.slots() * SLOT_SIZE;
+        self.data[slots_end..start].fill(0);
+        write_u32(&mut self.data, 8, start)
+    }
+
+    /// `None` if the record doesn't fit, it is encrypted once its id is known.
+    fn insert(&mut self, record: &Record, key: Option<EncryptionKey>) -> io::Result<Option<usize>> {
+        let len = record.data.len() + key.map_or(0, |_| OVERHEAD);
+        if !self.fits(len) {
+            return Ok(None);
+        }
+        let free = self.free_slot();
+        let slots = self.slots() + usize::from(free.is_none());
+        if self.start() < RUN_HEADER + slots * SLOT_SIZE + len {
+            self.compact()?;
+        }
+        let slot = free.unwrap_or(slots - 1);
+        let data = encryption::encrypt(key, &aad(slot_id(self.page, slot)), &record.data)?;
+        write_u32(&mut self.data, 4, slots)?;
+        let start = self.start() - data.len();
+        self.data[start..start + data.len()].copy_from_slice(&data);
+        self.set_slot(slot, start, data.len(), record.compressed)?;
+        write_u32(&mut self.data, 8, start)?;
+        Ok(Some(slot))
+    }
+}
+
+/// The records of the `DynanicBinary` of a table stored with `DynStorage::Heap`, in `heap.bin`.
+///
+/// The first page holds the number of pages and the first page of the last run,
+/// where the new records are added. The space of the removed records is reused
+/// by the new versions of the records of their run, or by the new records if their run is the last one.
+///
+/// A run already written is first copied in `heap.bin.journal`, with its page,
+/// so a write interrupted by a crash is finished the next time the heap file is opened.
+struct Heap {
+    file: File,
+    journal: String,
+    pages: u64,
+    last: u64,
+}
+
+/// Writes the run of the journal left by an interrupted write, then removes the journal.
+fn replay_journal(path: &BDPath) -> io::Result<()> {
+    let journal = journal_path(path);
+    if !Path::new(journal.as_str()).exists() {
+        return Ok(());
+    }
+    let data = fs::read(journal.as_str())?;
+    let (page, run) = data.split_at(8);
+    let page = u64::from_le_bytes(page.try_into().map_err(Error::other)?);
+    let mut file = File::options().write(true).open(path.heap_path())?;
+    file.seek(SeekFrom::Start(page * PAGE_SIZE as u64))?;
+    file.write_all(run)?;
+    file.sync_all()?;
+    remove_file(journal)
+}
+
+fn journal_path(path: &BDPath) -> String {
+    format!("{}.journal", path.heap_path())
+}
+
+impl Heap {
+    fn open(path: &BDPath, write: bool) -> io::Result<Self> {
+        replay_journal(path)?;
+        let heap_path = path.heap_path();
+        if write && !Path::new(heap_path.as_str()).exists() {
+            let mut file = File::create(heap_path.as_str())?;
+            let mut header = vec![0; PAGE_SIZE];
+            header[..8].copy_from_slice(&1_u64.to_le_bytes());
+            file.write_all(&header)?;
+            file.sync_all()?;
+        }
+        let mut file = File::options().read(true).write(write).open(heap_path)?;
+        let mut header = [0; 16];
+        file.read_exact(&mut header)?;
+        let (pages, last) = header.split_at(8);
+        Ok(Self {
+            file,
+            journal: journal_path(path),
+            pages: u64::from_le_bytes(pages.try_into().map_err(Error::other)?),
+            last: u64::from_le_bytes(last.try_into().map_err(Error::other)?),
+        })
+    }
+
+    fn read_run(&mut self, page: u64) -> io::Result<Run> {
+        if page == 0 || page >= self.pages {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no page {page} in the heap file"),
+            ));
+        }
+        self.file.seek(SeekFrom::Start(page * PAGE_SIZE as u64))?;
+        let mut data = vec![0; PAGE_SIZE];
+        self.file.read_exact(&mut data)?;
+        let span = read_u32(&data, 0);
+        data.resize(span.max(1) * PAGE_SIZE, 0);
+        self.file.read_exact(&mut data[PAGE_SIZE..])?;
+        Ok(Run { page, data })
+    }
+
+    /// The runs below `pages` hold records, so they are written through the journal.
+    /// The journal is written to a temporary file first so it is never replayed half written.
+    fn write_run(&mut self, run: &Run) -> io::Result<()> {
+        let journaled = run.page < self.pages;
+        if journaled {
+            let temporary = format!("{}.tmp", self.journal);
+            let mut journal = File::create(temporary.as_str())?;
+            journal.write_all(&run.page.to_le_bytes())?;
+            journal.write_all(&run.data)?;
+            journal.sync_all()?;
+            rename(temporary, self.journal.as_str())?;
+        }
+        self.file
+            .seek(SeekFrom::Start(run.page * PAGE_SIZE as u64))?;
+        self.file.write_all(&run.data)?;
+        self.file.sync_all()?;
+        if journaled {
+            remove_file(self.journal.as_str())?;
+        }
+        Ok(())
+    }
+
+    /// Adds the record in the last run, or in a new run written before the header.
+    fn insert(
+        &mut self,
+        record: &Record,
+        key: Option<EncryptionKey>,
+    ) -> io::Result<NonZero<usize>> {
+        let len = record.data.len() + key.map_or(0, |_| OVERHEAD);
+        if len >= COMPRESSED {
+            return Err(Error::other("the record is too big for the heap file"));
+        }
+        if self.last != 0 {
+            let mut run = self.read_run(self.last)?;
+            if let Some(slot) = run.insert(record, key)? {
+                self.write_run(&run)?;
+                return Ok(slot_id(run.page, slot));
+            }
+        }
+        if self.pages >= (HEAP_TAG >> SLOT_BITS) as u64 {
+            return Err(Error::other("the heap file is full"));
+        }
+        let mut run = Run::new(self.pages, len)?;
+        let slot = run
+            .insert(record, key)?
+            .ok_or_else(|| Error::other("the record doesn't fit in its run"))?;
+        self.write_run(&run)?;
+        self.last = run.page;
+        self.pages += run.span();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&self.pages.to_le_bytes())?;
+        self.file.write_all(&self.last.to_le_bytes())?;
+        self.file.sync_all()?;
+        Ok(slot_id(run.page, slot))
+    }
+}
+
+pub fn read(path: &BDPath, id: NonZero<usize>) -> io::Result<Vec<u8>> {
+    let (page, slot) = page_slot(id);
+    Heap::open(path, false)?
+        .read_run(page)?
+        .record(slot, path.key)
+}
+
+/// The data compressed with the codec of the table, before its encryption.
+struct Record {
+    data: Vec<u8>,
+    compressed: bool,
+}
+
+/// The record of the data, compressed with the codec of `path`.
+fn encode(path: &BDPath, data: &[u8]) -> io::Result<Record> {
+    Ok(match path.codec {
+        Codec::None => Record {
+            data: data.to_vec(),
+            compressed: false,
+        },
+        codec => Record {
+            data: compress(codec, data)?,
+            compressed: true,
+        },
+    })
+}
+
+/// The records are authenticated with the heap file and their id, which stays the same
+/// when the run is compacted, so a record can't be read in place of another one.
+/// A record replaced by an older version of itself, taken from a copy of the file, isn't detected.
+fn aad(id: NonZero<usize>) -> Vec<u8> {
+    [b"heap.bin".as_slice(), &id.get().to_le_bytes()].concat()
+}
+
+/// The ids and lens of all the records, empty if there is no heap file.
+pub fn records(path: &BDPath) -> io::Result<Vec<(NonZero<usize>, usize)>> {
+    if !Path::new(path.heap_path().as_str()).exists() {
+        return Ok(Vec::new());
+    }
+    let mut heap = Heap::open(path, false)?;
+    let mut records = Vec::new();
+    let mut page = 1;
+    while page < heap.pages {
+        let run = heap.read_run(page)?;
+        for slot in 0..run.slots() {
+            if let Ok((_, len, _)) = run.slot(slot) {
+                records.push((slot_id(page, slot), len));
+            }
+        }
+        page += run.span().max(1);
+    }
+    Ok(records)
+}
+
+pub fn insert(path: &BDPath, data: &[u8]) -> io::Result<NonZero<usize>> {
+    Heap::open(path, true)?.insert(&encode(path, data)?, path.key)
+}
+
+/// Keeps the id if the record didn't change, else adds the new record, in the same run if it fits.
+///
+/// The records are never changed in place because the index files still reference the old one
+/// until they are updated, it is deleted by `Binary::delete_replaced`.
+pub fn write(path: &BDPath, id: NonZero<usize>, data: &[u8]) -> io::Result<NonZero<usize>> {
+    let (page, slot) = page_slot(id);
+    let mut heap = Heap::open(path, true)?;
+    let record = encode(path, data)?;
+    if page != 0 && page < heap.pages {
+        let mut run = heap.read_run(page)?;
+        if run
+            .record(slot, path.key)
+            .is_ok_and(|record| record == data)
+        {
+            return Ok(id);
+        }
+        if let Some(slot) = run.insert(&record, path.key)? {
+            heap.write_run(&run)?;
+            return Ok(slot_id(page, slot));
+        }
+    }
+    heap.insert(&record, path.key)
+}
+
+/// Frees the slot and zeroes the record, so no plaintext is left after a key rotation.
+pub fn delete(path: &BDPath, id: NonZero<usize>) -> io::Result<()> {
+    let (page, slot) = page_slot(id);
+    let mut heap = Heap::open(path, true)?;
+    let mut run = heap.read_run(page)?;
+    let (offset, len, _) = run.slot(slot)?;
+    run.data[offset..offset + len].fill(0);
+    run.set_slot(slot, 0, FREE as usize, false)?;
+    heap.write_run(&run)
+}
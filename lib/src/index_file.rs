@@ -1,12 +1,19 @@
 use std::{
     cmp::Ordering,
-    io::{self},
+    fs::{self, create_dir_all, read_dir, remove_file},
+    io::{self, Error, ErrorKind},
     marker::PhantomData,
+    ops::{Bound, Range, RangeBounds},
 };
 
 use crate::{
-    bd_path::BDPath, bin_file::BaseBinFile, binary::Binary, cached_bin_file::CachedBinFile,
+    bd_path::BDPath,
+    bin_file::{BaseBinFile, BinIter},
+    binary::Binary,
+    cached_bin_file::CachedBinFile,
+    dyn_binary::DynanicBinary,
     prelude::BinFile,
+    table::Table,
 };
 
 #[derive(Debug)]
@@ -24,162 +31,399 @@ impl<Row> From<io::Error> for IndexGet<Row> {
 }
 
 #[derive(Binary, Clone)]
-pub struct IndexRow<ColType: Binary + PartialOrd> {
+pub struct IndexRow<ColType: Binary + PartialOrd, ID: Binary + PartialOrd> {
     pub data: ColType,
-    pub index: usize,
+    /// The primary key of the row.
+    pub id: ID,
 }
 
-pub trait UnspecifiedIndex<Row: Binary> {
-    fn check_unique(&mut self, row: &mut Row) -> io::Result<Option<()>>;
-    fn insert(&mut self, index: usize, row: &mut Row) -> io::Result<()>;
-    fn remove(&mut self, index: usize) -> std::io::Result<()>;
+/// The layout of the `IndexRow`, written in the `index/layout` file of the tables.
+const LAYOUT: &str = "id";
+
+/// Removes the index files written before they referenced the rows by primary key,
+/// with the index of the row instead, so they are rebuilt from the main file.
+///
+/// They are told apart by the `layout` file that the newer tables have.
+pub(crate) fn check_layout(path: &BDPath) -> io::Result<()> {
+    let dir = path.index_path();
+    let layout = format!("{dir}/layout");
+    match fs::read_to_string(layout.as_str()) {
+        Ok(stored) if stored == LAYOUT => return Ok(()),
+        Ok(_) => {}
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+    create_dir_all(dir.as_str())?;
+    for file in read_dir(dir.as_str())? {
+        let file = file?.path();
+        if file.extension().is_some_and(|extension| extension == "bin") {
+            remove_file(file)?;
+        }
+    }
+    fs::write(layout, LAYOUT)
+}
+
+pub trait UnspecifiedIndex<Row: Table> {
+    fn name(&self) -> &'static str;
+    /// Returns the id of the row that already has the value of a unique field.
+    fn check_unique(&mut self, row: &mut Row) -> io::Result<Option<Row::ID>>;
+    fn check_unique_update(&mut self, old: &Row, new: &mut Row) -> io::Result<Option<Row::ID>>;
+    /// If the field is unique and both rows have the same value.
+    fn unique_eq(&self, a: &Row, b: &Row) -> bool;
+    fn insert(&mut self, row: &Row) -> io::Result<()>;
+    fn update(&mut self, old: &Row, new: &Row) -> io::Result<()>;
+    fn remove(&mut self, row: &Row) -> std::io::Result<()>;
+    /// Applies the `(old, new)` changes of many rows with a single rewrite of the index.
+    fn changes(&mut self, changes: &[(Option<&Row>, Option<&Row>)]) -> io::Result<()>;
+    fn rebuild(&mut self, rows: &[Row]) -> io::Result<()>;
+    fn len(&self) -> io::Result<usize>;
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
     fn clear(&mut self) -> io::Result<()>;
     fn clear_cache(&mut self);
+    /// Another handle on the same index, sharing the cache of this one.
+    fn share(&self) -> Box<dyn UnspecifiedIndex<Row>>;
 }
 
-pub type IndexFile<ColType, Row> = SpecificIndexFile<ColType, Row, BinFile<IndexRow<ColType>>>;
+/// The positions of `range` left after skipping `offset` of them and keeping at most `limit`.
+pub(crate) fn window(range: Range<usize>, offset: usize, limit: Option<usize>) -> Range<usize> {
+    let start = range.start.saturating_add(offset).min(range.end);
+    let end = limit.map_or(range.end, |limit| {
+        range.end.min(start.saturating_add(limit))
+    });
+    start..end
+}
+
+fn duplicate_entry(name: &str) -> Error {
+    Error::new(
+        ErrorKind::AlreadyExists,
+        format!("the index {name} already has the entry"),
+    )
+}
+
+pub type IndexFile<ColType, Row> =
+    SpecificIndexFile<ColType, Row, BinFile<IndexRow<ColType, <Row as Table>::ID>>>;
 pub type CachedIndexFile<ColType, Row> =
-    SpecificIndexFile<ColType, Row, CachedBinFile<IndexRow<ColType>>>;
+    SpecificIndexFile<ColType, Row, CachedBinFile<IndexRow<ColType, <Row as Table>::ID>>>;
 
+/// The entries are sorted by value then by id.
 pub struct SpecificIndexFile<
     ColType: Binary + PartialOrd,
-    Row: Binary,
-    BinFile: BaseBinFile<IndexRow<ColType>>,
+    Row: Table,
+    BinFile: BaseBinFile<IndexRow<ColType, Row::ID>>,
 > {
     bin: BinFile,
-    index: IdAsIndexFile<ColType, IndexRow<ColType>, BinFile>,
+    index: IdAsIndexFile<ColType, IndexRow<ColType, Row::ID>, BinFile>,
+    name: &'static str,
     extract: Box<fn(&Row) -> &ColType>,
     check_unique: bool,
 }
 
-impl<ColType: Binary + PartialOrd, Row: Binary, BinFile: BaseBinFile<IndexRow<ColType>>>
+impl<ColType: Binary + PartialOrd, Row: Table, BinFile: BaseBinFile<IndexRow<ColType, Row::ID>>>
     SpecificIndexFile<ColType, Row, BinFile>
 {
     pub fn new(
         path: BDPath,
+        name: &'static str,
         extract: Box<fn(&Row) -> &ColType>,
         check_unique: bool,
     ) -> io::Result<Self> {
         Ok(Self {
             bin: BinFile::new(path)?,
-            index: IdAsIndexFile::new(Box::new(|row: &IndexRow<ColType>, other: &ColType| {
-                row.data.partial_cmp(other)
-            }))?,
+            index: IdAsIndexFile::new(Box::new(
+                |row: &IndexRow<ColType, Row::ID>, other: &ColType| row.data.partial_cmp(other),
+            ))?,
+            name,
             extract,
             check_unique,
         })
     }
 
-    pub fn indx(&self, find: &ColType) -> IndexGet<IndexRow<ColType>> {
+    fn base_share(&self) -> Self {
+        Self {
+            bin: self.bin.share(),
+            index: self.index.share(),
+            name: self.name,
+            extract: self.extract.clone(),
+            check_unique: self.check_unique,
+        }
+    }
+
+    pub fn indx(&self, find: &ColType) -> IndexGet<IndexRow<ColType, Row::ID>> {
         self.index.indx(&self.bin, find)
     }
+
+    pub fn range(
+        &self,
+        range: &impl RangeBounds<ColType>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<IndexRow<ColType, Row::ID>>> {
+        self.index.range(&self.bin, range, offset, limit)
+    }
+
+    /// Streams the entries in `range`, skipping `offset` entries and returning at most `limit`.
+    pub fn iter_range(
+        &self,
+        range: &impl RangeBounds<ColType>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<BinIter<'_, IndexRow<ColType, Row::ID>, BinFile>> {
+        Ok(self.bin.iter_range(window(
+            self.index.positions(&self.bin, range)?,
+            offset,
+            limit,
+        )))
+    }
 }
-impl<ColType: Binary + PartialOrd + Clone, Row: Binary, BinFile: BaseBinFile<IndexRow<ColType>>>
-    SpecificIndexFile<ColType, Row, BinFile>
+impl<Row: Table, BinFile: BaseBinFile<IndexRow<DynanicBinary<String>, Row::ID>>>
+    SpecificIndexFile<DynanicBinary<String>, Row, BinFile>
 {
-    fn base_check_unique(&mut self, row: &mut Row) -> io::Result<Option<()>> {
-        Ok(if !self.check_unique {
-            Some(())
+    /// The entries whose value starts with `prefix`, skipping `offset` entries and returning at most `limit`.
+    pub fn prefix(
+        &self,
+        prefix: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<IndexRow<DynanicBinary<String>, Row::ID>>> {
+        let start = self
+            .index
+            .partition_point(&self.bin, |row| row.data.data().as_str() < prefix)?;
+        let end = self.index.partition_point(&self.bin, |row| {
+            row.data.data().as_str() < prefix || row.data.data().starts_with(prefix)
+        })?;
+        self.index.slice(&self.bin, start..end, offset, limit)
+    }
+}
+impl<
+    ColType: Binary + PartialOrd + Clone,
+    Row: Table,
+    BinFile: BaseBinFile<IndexRow<ColType, Row::ID>>,
+> SpecificIndexFile<ColType, Row, BinFile>
+{
+    fn base_check_unique(&mut self, row: &mut Row) -> io::Result<Option<Row::ID>> {
+        if !self.check_unique {
+            return Ok(None);
+        }
+        match self.indx((self.extract)(row)) {
+            IndexGet::Found(_, rows) => Ok(rows.into_iter().next().map(|row| row.id)),
+            IndexGet::NotFound(_) => Ok(None),
+            IndexGet::InternalError(e) => Err(Error::other(e)),
+            IndexGet::Err(e) => Err(e),
+        }
+    }
+    fn changed(&self, old: &Row, new: &Row) -> bool {
+        !matches!(
+            (self.extract)(old).partial_cmp((self.extract)(new)),
+            Some(Ordering::Equal)
+        )
+    }
+    fn base_unique_eq(&self, a: &Row, b: &Row) -> bool {
+        self.check_unique && !self.changed(a, b)
+    }
+    fn base_check_unique_update(
+        &mut self,
+        old: &Row,
+        new: &mut Row,
+    ) -> io::Result<Option<Row::ID>> {
+        if self.changed(old, new) {
+            self.base_check_unique(new)
         } else {
-            let data = (self.extract)(row).clone();
-            if self
-                .bin
-                .gets(0, None)?
-                .iter()
-                .any(|row| match row.data.partial_cmp(&data) {
-                    None | Some(Ordering::Equal) => true,
-                    Some(Ordering::Greater) | Some(Ordering::Less) => false,
-                })
-            {
-                None
-            } else {
-                Some(())
+            Ok(None)
+        }
+    }
+
+    fn entry(&self, row: &Row) -> IndexRow<ColType, Row::ID> {
+        IndexRow {
+            data: (self.extract)(row).clone(),
+            id: row.id().clone(),
+        }
+    }
+
+    fn entry_cmp(
+        a: &IndexRow<ColType, Row::ID>,
+        b: &IndexRow<ColType, Row::ID>,
+    ) -> Option<Ordering> {
+        match a.data.partial_cmp(&b.data) {
+            Some(Ordering::Equal) => a.id.partial_cmp(&b.id),
+            cmp => cmp,
+        }
+    }
+
+    /// `Ok` with the position of the entry or `Err` with the position where it would be inserted.
+    fn search(&self, entry: &IndexRow<ColType, Row::ID>) -> io::Result<Result<usize, usize>> {
+        let (mut from, mut to) = (0, self.bin.len()?);
+        while from < to {
+            let mid = from + (to - from) / 2;
+            match Self::entry_cmp(&self.bin.get(mid)?, entry) {
+                Some(Ordering::Less) => from = mid + 1,
+                Some(Ordering::Greater) => to = mid,
+                Some(Ordering::Equal) => return Ok(Ok(mid)),
+                None => return Err(Error::other("cmp error")),
             }
-        })
+        }
+        Ok(Err(from))
     }
-    fn base_insert(&mut self, index: usize, row: &mut Row) -> io::Result<()> {
-        let mut datas = self
-            .bin
-            .gets(0, None)?
-            .into_iter()
-            .map(|row| {
-                if row.index < index {
-                    row
-                } else {
-                    IndexRow {
-                        data: row.data,
-                        index: row.index + 1,
-                    }
-                }
-            })
-            .collect::<Vec<IndexRow<ColType>>>();
-        datas.insert(
-            index,
-            IndexRow {
-                data: (self.extract)(row).clone(),
-                index,
-            },
-        );
+
+    fn base_insert(&mut self, row: &Row) -> io::Result<()> {
+        let mut entry = self.entry(row);
+        match self.search(&entry)? {
+            Ok(_) => Err(duplicate_entry(self.name)),
+            Err(position) => self.bin.insert(position, &mut entry),
+        }
+    }
+
+    fn base_update(&mut self, old: &Row, new: &Row) -> io::Result<()> {
+        if !self.changed(old, new) {
+            return Ok(());
+        }
+        self.base_remove(old)?;
+        self.base_insert(new)
+    }
+
+    fn base_remove(&mut self, row: &Row) -> std::io::Result<()> {
+        match self.search(&self.entry(row))? {
+            Ok(position) => self.bin.remove(position, Some(1)),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn base_changes(&mut self, changes: &[(Option<&Row>, Option<&Row>)]) -> io::Result<()> {
+        let (mut removed, mut inserted) = (Vec::new(), Vec::new());
+        for (old, new) in changes {
+            if let (Some(old), Some(new)) = (old, new)
+                && !self.changed(old, new)
+            {
+                continue;
+            }
+            removed.extend(old.map(|row| self.entry(row)));
+            inserted.extend(new.map(|row| self.entry(row)));
+        }
+        if removed.is_empty() && inserted.is_empty() {
+            return Ok(());
+        }
+        removed.sort_by(|a, b| Self::entry_cmp(a, b).unwrap_or(Ordering::Equal));
+        inserted.sort_by(|a, b| Self::entry_cmp(a, b).unwrap_or(Ordering::Equal));
+        let mut removed = removed.into_iter().peekable();
+        let mut inserted = inserted.into_iter().peekable();
+        let mut datas = Vec::new();
+        for entry in self.bin.gets(0, None)? {
+            while removed
+                .next_if(|removed| Self::entry_cmp(removed, &entry) == Some(Ordering::Less))
+                .is_some()
+            {}
+            if removed
+                .next_if(|removed| Self::entry_cmp(removed, &entry) == Some(Ordering::Equal))
+                .is_some()
+            {
+                continue;
+            }
+            while let Some(new) =
+                inserted.next_if(|new| Self::entry_cmp(new, &entry) == Some(Ordering::Less))
+            {
+                datas.push(new);
+            }
+            datas.push(entry);
+        }
+        datas.extend(inserted);
+        if datas
+            .windows(2)
+            .any(|pair| Self::entry_cmp(&pair[0], &pair[1]) != Some(Ordering::Less))
+        {
+            return Err(duplicate_entry(self.name));
+        }
         self.bin.clear()?;
-        self.bin.inserts(0, &mut datas)?;
-        Ok(())
-    }
-
-    fn base_remove(&mut self, index: usize) -> std::io::Result<()> {
-        let mut datas = self
-            .bin
-            .gets(0, None)?
-            .into_iter()
-            .filter_map(|row| {
-                if row.index < index {
-                    Some(row)
-                } else if row.index == index {
-                    None
-                } else {
-                    Some(IndexRow {
-                        data: row.data,
-                        index: row.index - 1,
-                    })
-                }
-            })
-            .collect::<Vec<IndexRow<ColType>>>();
+        self.bin.inserts(0, &mut datas)
+    }
+
+    fn base_rebuild(&mut self, rows: &[Row]) -> io::Result<()> {
+        let mut datas = rows
+            .iter()
+            .map(|row| self.entry(row))
+            .collect::<Vec<IndexRow<ColType, Row::ID>>>();
+        datas.sort_by(|a, b| Self::entry_cmp(a, b).unwrap_or(Ordering::Equal));
         self.bin.clear()?;
-        self.bin.inserts(0, &mut datas)?;
-        Ok(())
+        self.bin.inserts(0, &mut datas)
     }
 
     fn base_clear(&mut self) -> io::Result<()> {
         self.bin.clear()
     }
 }
-impl<ColType: Binary + PartialOrd + Clone, Row: Binary> UnspecifiedIndex<Row>
-    for SpecificIndexFile<ColType, Row, BinFile<IndexRow<ColType>>>
+impl<ColType: Binary + PartialOrd + Clone + 'static, Row: Table + 'static> UnspecifiedIndex<Row>
+    for SpecificIndexFile<ColType, Row, BinFile<IndexRow<ColType, Row::ID>>>
 {
-    fn check_unique(&mut self, row: &mut Row) -> io::Result<Option<()>> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn check_unique(&mut self, row: &mut Row) -> io::Result<Option<Row::ID>> {
         self.base_check_unique(row)
     }
-    fn insert(&mut self, index: usize, row: &mut Row) -> io::Result<()> {
-        self.base_insert(index, row)
+    fn check_unique_update(&mut self, old: &Row, new: &mut Row) -> io::Result<Option<Row::ID>> {
+        self.base_check_unique_update(old, new)
+    }
+    fn unique_eq(&self, a: &Row, b: &Row) -> bool {
+        self.base_unique_eq(a, b)
+    }
+    fn insert(&mut self, row: &Row) -> io::Result<()> {
+        self.base_insert(row)
+    }
+    fn update(&mut self, old: &Row, new: &Row) -> io::Result<()> {
+        self.base_update(old, new)
+    }
+    fn remove(&mut self, row: &Row) -> std::io::Result<()> {
+        self.base_remove(row)
+    }
+    fn changes(&mut self, changes: &[(Option<&Row>, Option<&Row>)]) -> io::Result<()> {
+        self.base_changes(changes)
+    }
+    fn rebuild(&mut self, rows: &[Row]) -> io::Result<()> {
+        self.base_rebuild(rows)
     }
-    fn remove(&mut self, index: usize) -> std::io::Result<()> {
-        self.base_remove(index)
+    fn len(&self) -> io::Result<usize> {
+        self.bin.len()
     }
     fn clear(&mut self) -> io::Result<()> {
         self.base_clear()
     }
     fn clear_cache(&mut self) {}
+    fn share(&self) -> Box<dyn UnspecifiedIndex<Row>> {
+        Box::new(self.base_share())
+    }
 }
-impl<ColType: Binary + PartialOrd + Clone, Row: Binary> UnspecifiedIndex<Row>
-    for SpecificIndexFile<ColType, Row, CachedBinFile<IndexRow<ColType>>>
+impl<ColType: Binary + PartialOrd + Clone + 'static, Row: Table + 'static> UnspecifiedIndex<Row>
+    for SpecificIndexFile<ColType, Row, CachedBinFile<IndexRow<ColType, Row::ID>>>
 {
-    fn check_unique(&mut self, row: &mut Row) -> io::Result<Option<()>> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn check_unique(&mut self, row: &mut Row) -> io::Result<Option<Row::ID>> {
         self.base_check_unique(row)
     }
-    fn insert(&mut self, index: usize, row: &mut Row) -> io::Result<()> {
-        self.base_insert(index, row)
+    fn check_unique_update(&mut self, old: &Row, new: &mut Row) -> io::Result<Option<Row::ID>> {
+        self.base_check_unique_update(old, new)
+    }
+    fn unique_eq(&self, a: &Row, b: &Row) -> bool {
+        self.base_unique_eq(a, b)
+    }
+    fn insert(&mut self, row: &Row) -> io::Result<()> {
+        self.base_insert(row)
+    }
+    fn update(&mut self, old: &Row, new: &Row) -> io::Result<()> {
+        self.base_update(old, new)
+    }
+    fn remove(&mut self, row: &Row) -> std::io::Result<()> {
+        self.base_remove(row)
     }
-    fn remove(&mut self, index: usize) -> std::io::Result<()> {
-        self.base_remove(index)
+    fn changes(&mut self, changes: &[(Option<&Row>, Option<&Row>)]) -> io::Result<()> {
+        self.base_changes(changes)
+    }
+    fn rebuild(&mut self, rows: &[Row]) -> io::Result<()> {
+        self.base_rebuild(rows)
+    }
+    fn len(&self) -> io::Result<usize> {
+        self.bin.len()
     }
     fn clear(&mut self) -> io::Result<()> {
         self.base_clear()
@@ -187,6 +431,9 @@ impl<ColType: Binary + PartialOrd + Clone, Row: Binary> UnspecifiedIndex<Row>
     fn clear_cache(&mut self) {
         self.bin.clear_cache();
     }
+    fn share(&self) -> Box<dyn UnspecifiedIndex<Row>> {
+        Box::new(self.base_share())
+    }
 }
 
 pub struct IdAsIndexFile<ColType: Binary + PartialOrd, Row: Binary, BinFile: BaseBinFile<Row>> {
@@ -204,6 +451,81 @@ impl<ColType: Binary + PartialOrd, Row: Binary, BinFile: BaseBinFile<Row>>
         })
     }
 
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            row: PhantomData,
+            cmp: self.cmp.clone(),
+        }
+    }
+
+    /// The first index where `pred` is false, the rows where it is true must be first.
+    pub fn partition_point(&self, bin: &BinFile, pred: impl Fn(&Row) -> bool) -> io::Result<usize> {
+        let (mut from, mut to) = (0, bin.len()?);
+        while from < to {
+            let mid = from + (to - from) / 2;
+            if pred(&bin.get(mid)?) {
+                from = mid + 1;
+            } else {
+                to = mid;
+            }
+        }
+        Ok(from)
+    }
+
+    /// The indexes of the rows in `range`.
+    pub fn positions(
+        &self,
+        bin: &BinFile,
+        range: &impl RangeBounds<ColType>,
+    ) -> io::Result<Range<usize>> {
+        let lower = |row: &Row, bound: &ColType| (self.cmp)(row, bound) == Some(Ordering::Less);
+        let lower_or_equal = |row: &Row, bound: &ColType| {
+            matches!(
+                (self.cmp)(row, bound),
+                Some(Ordering::Less | Ordering::Equal)
+            )
+        };
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.partition_point(bin, |row| lower(row, start))?,
+            Bound::Excluded(start) => {
+                self.partition_point(bin, |row| lower_or_equal(row, start))?
+            }
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.partition_point(bin, |row| lower_or_equal(row, end))?,
+            Bound::Excluded(end) => self.partition_point(bin, |row| lower(row, end))?,
+            Bound::Unbounded => bin.len()?,
+        };
+        Ok(start..end.max(start))
+    }
+
+    /// The rows in `range`, in order, skipping `offset` rows and returning at most `limit`.
+    pub fn range(
+        &self,
+        bin: &BinFile,
+        range: &impl RangeBounds<ColType>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<Row>> {
+        self.slice(bin, self.positions(bin, range)?, offset, limit)
+    }
+
+    /// The rows from `range`, skipping `offset` rows and returning at most `limit`.
+    pub fn slice(
+        &self,
+        bin: &BinFile,
+        range: Range<usize>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<Row>> {
+        let range = window(range, offset, limit);
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        bin.gets(range.start, Some(range.len()))
+    }
+
     pub fn indx(&self, bin: &BinFile, find: &ColType) -> IndexGet<Row> {
         match bin.len() {
             Ok(0) => IndexGet::NotFound(0),
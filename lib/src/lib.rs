@@ -7,11 +7,24 @@ pub mod bin_file;
 pub mod binary;
 pub mod cache;
 pub mod cached_bin_file;
+pub mod compression;
+pub mod database;
+pub mod dedup;
 pub mod dyn_binary;
+pub mod encryption;
+pub mod fixed_string;
 pub mod foreign;
+pub mod heap_file;
 pub mod index_file;
+pub mod migration;
+pub mod page_bin_file;
 pub mod prelude;
+pub mod query;
+pub mod schema;
 pub mod table;
+pub mod transaction;
+pub mod vacuum;
+pub mod wal;
 
 const fn add_size(a: usize, b: isize) -> usize {
     if b < 0 {
@@ -51,8 +64,34 @@ mod test_big_table;
 #[cfg(test)]
 mod test_bin_file;
 #[cfg(test)]
+mod test_binary;
+#[cfg(test)]
 mod test_cache;
+#[cfg(all(test, feature = "compression"))]
+mod test_compression;
+#[cfg(test)]
+mod test_database;
+#[cfg(test)]
+mod test_dedup;
+#[cfg(all(test, feature = "encryption"))]
+mod test_encryption;
+#[cfg(test)]
+mod test_foreign;
+#[cfg(test)]
+mod test_heap_file;
 #[cfg(test)]
 mod test_index;
 #[cfg(test)]
+mod test_page_bin_file;
+#[cfg(test)]
+mod test_query;
+#[cfg(test)]
+mod test_schema;
+#[cfg(test)]
 mod test_table;
+#[cfg(test)]
+mod test_transaction;
+#[cfg(test)]
+mod test_vacuum;
+#[cfg(test)]
+mod test_wal;
@@ -0,0 +1,178 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::{remove_dir_all, remove_file, rename, write},
+    io::{self, Error},
+    num::NonZero,
+    path::Path,
+};
+
+use crate::{
+    bd_path::BDPath,
+    bin_file::BaseBinFile,
+    database::DatabaseTable,
+    dedup,
+    dyn_binary::{dyn_ids, remove_dyn},
+    encryption::{EncryptionKey, key_check},
+    schema::Schema,
+    table::{SpecificTableFile, Table},
+};
+
+/// Puts the files written by a migration in place once its `schema.migrate` file is written,
+/// so a migration interrupted after that point is finished the next time the table is opened.
+///
+/// An empty `key.migrate` file removes the `key` file, the table being no longer encrypted,
+/// and the `.mac` file of the main file.
+pub(crate) fn finish_migration(dir: &str) -> io::Result<()> {
+    let schema = format!("{dir}/schema.migrate");
+    if !Path::new(schema.as_str()).exists() {
+        return Ok(());
+    }
+    let main = format!("{dir}/main.migrate.bin");
+    if Path::new(main.as_str()).exists() {
+        let mac = format!("{main}.mac");
+        if Path::new(mac.as_str()).exists() {
+            rename(mac, format!("{dir}/main.bin.mac"))?;
+        } else if Path::new(format!("{dir}/key.migrate").as_str())
+            .metadata()
+            .is_ok_and(|key| key.len() == 0)
+            && Path::new(format!("{dir}/main.bin.mac").as_str()).exists()
+        {
+            remove_file(format!("{dir}/main.bin.mac"))?;
+        }
+        rename(main, format!("{dir}/main.bin"))?;
+    }
+    let index = format!("{dir}/index");
+    if Path::new(index.as_str()).exists() {
+        remove_dir_all(index)?;
+    }
+    let key = format!("{dir}/key.migrate");
+    if Path::new(key.as_str()).exists() {
+        if Path::new(key.as_str()).metadata()?.len() == 0 {
+            remove_file(key)?;
+            if Path::new(format!("{dir}/key").as_str()).exists() {
+                remove_file(format!("{dir}/key"))?;
+            }
+        } else {
+            rename(key, format!("{dir}/key"))?;
+        }
+    }
+    rename(schema, format!("{dir}/schema"))
+}
+
+/// Writes the rows in `main.migrate.bin` with the codec and the key of `main`,
+/// puts it in place with `schema`, then deletes the dynamic data no longer referenced.
+///
+/// The dynamic data keep their ids if they didn't change, the other ones are written under new ids,
+/// so the old ones stay readable until the migration is finished.
+fn rewrite<Row: Table, BinFile: BaseBinFile<Row>>(
+    main: &BDPath,
+    rows: &mut [Row],
+    old_ids: &HashMap<NonZero<usize>, usize>,
+    schema: &Schema,
+) -> io::Result<()> {
+    let dir = main.dir_path.as_str();
+    if Path::new(main.full().as_str()).exists() {
+        remove_file(main.full())?;
+    }
+    let mut bin = BinFile::new(main.clone())?;
+    bin.inserts(0, rows)?;
+    drop(bin);
+    let mut new_ids = HashMap::new();
+    for row in rows.iter_mut() {
+        dyn_ids(row, &mut new_ids);
+    }
+    write(format!("{dir}/key.migrate"), key_check(main.key)?)?;
+    schema.write(format!("{dir}/schema.migrate").as_str())?;
+    finish_migration(dir)?;
+
+    for id in old_ids.keys().filter(|id| !new_ids.contains_key(id)) {
+        remove_dyn(main, *id)?;
+    }
+    for (id, refs) in new_ids {
+        if dedup::is_dedup(id) {
+            dedup::set_refs(main, id, refs)?;
+        }
+    }
+    Ok(())
+}
+
+impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTableFile<Row, BinFile> {
+    /// Rewrites the rows of the table stored as `Old`, like `TableFile<OldRow>`, with `migrate`
+    /// then opens it. Does nothing if the table is already stored as `Row`.
+    ///
+    /// The indexes are rebuilt and the dynamic data of the rows are kept,
+    /// except the ones of the dropped columns which are deleted.
+    /// All the rows are in memory during the migration.
+    pub fn migrate<Old: DatabaseTable>(
+        path: String,
+        migrate: impl FnMut(Old::Row) -> Row,
+    ) -> io::Result<Self> {
+        Self::migrate_encrypted::<Old>(path, None, migrate)
+    }
+
+    /// Like `migrate`, for a table encrypted with `key`.
+    pub fn migrate_encrypted<Old: DatabaseTable>(
+        path: String,
+        key: Option<EncryptionKey>,
+        mut migrate: impl FnMut(Old::Row) -> Row,
+    ) -> io::Result<Self> {
+        finish_migration(path.as_str())?;
+        let schema = Row::schema();
+        let stored = Schema::read(format!("{path}/schema").as_str())?
+            .ok_or_else(|| Error::other(format!("{path} has no schema")))?;
+        if schema.diff(&stored).is_empty() {
+            return Self::open(path, key);
+        }
+        Old::Row::schema().check(&stored, path.as_str())?;
+
+        let old = SpecificTableFile::<Old::Row, Old::BinFile>::open(path.clone(), key)?;
+        let mut old_ids = HashMap::new();
+        let mut rows = Vec::with_capacity(old.len()?);
+        for row in old.iter()? {
+            let mut row = row?;
+            dyn_ids(&mut row, &mut old_ids);
+            rows.push(migrate(row));
+        }
+        drop(old);
+        rows.sort_by(|a, b| a.id().partial_cmp(b.id()).unwrap_or(Ordering::Equal));
+        if rows
+            .windows(2)
+            .any(|rows| rows[0].id_cmp(rows[1].id()) != Some(Ordering::Less))
+        {
+            return Err(Error::other("the migrated rows must have distinct ids"));
+        }
+
+        let main = BDPath {
+            rel_file_path: "main.migrate.bin".to_owned(),
+            ..BDPath::new_main(path.clone())
+                .with_codec(schema.compression)
+                .with_key(key)
+        };
+        rewrite::<Row, BinFile>(&main, &mut rows, &old_ids, &schema)?;
+        Self::open(path, key)
+    }
+
+    /// Rewrites the rows and their dynamic data encrypted with `key`, or in plaintext without one,
+    /// under new ids, then opens the table with it. The old files are replaced like in `migrate`.
+    ///
+    /// All the rows are in memory during the rotation.
+    pub fn rotate_key(self, key: Option<EncryptionKey>) -> io::Result<Self> {
+        let path = self.bin.path().dir_path.clone();
+        let mut old_ids = HashMap::new();
+        let mut rows = Vec::with_capacity(self.len()?);
+        for row in self.iter()? {
+            let mut row = row?;
+            dyn_ids(&mut row, &mut old_ids);
+            row.visit_dyn(&mut |id| *id = None);
+            rows.push(row);
+        }
+        let main = BDPath {
+            rel_file_path: "main.migrate.bin".to_owned(),
+            ..self.bin.path().with_key(key)
+        };
+        drop(self);
+        rewrite::<Row, BinFile>(&main, &mut rows, &old_ids, &Row::schema())?;
+        Self::open(path, key)
+    }
+}
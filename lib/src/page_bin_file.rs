@@ -0,0 +1,962 @@
+use crate::{
+    bd_path::BDPath,
+    bin_file::BaseBinFile,
+    binary::Binary,
+    compression::{Codec, compress, decompress},
+    dyn_binary::clear_dyn,
+    encryption::{self, EncryptionKey, OVERHEAD},
+    table::Table,
+};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs::{self, File, create_dir_all},
+    io::{self, Error, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::Path,
+    rc::Rc,
+};
+
+const PAGE_SIZE: usize = 4096;
+/// Root, len, pages, free and generation, as u64, followed by their checksum.
+const HEADER_SIZE: usize = 40;
+/// The pages holding the headers, alternately written by the commits.
+const HEADER_PAGES: u64 = 2;
+/// Kind (u8) and number of entries (u64).
+const NODE_HEADER: usize = 9;
+/// Next page (u64) and number of free pages (u64) of a page of the free list.
+const FREE_HEADER: usize = 16;
+
+const LEAF: u8 = 0;
+const INTERNAL: u8 = 1;
+/// A node too big for a page, compressed after its len (u64).
+const COMPRESSED: u8 = 2;
+/// The most pages of rows a compressed node holds, so a change doesn't compress too many rows.
+const COMPRESSED_PAGES: usize = 8;
+
+/// Stores the rows in the leaves of a B+tree of fixed-size pages.
+///
+/// The internal pages keep the number of rows under each child, so a row is found by its index
+/// in O(log n) pages and the rows stay in the order the table keeps them: sorted by id.
+/// They also keep the key of the first row under each child, the id for the rows of a table,
+/// so a row is found by its id in O(log n) pages too.
+/// The changed pages are written in unused pages and the header is written last,
+/// in the first two pages in turn, so a crash can only leak pages or tear the older header,
+/// and the valid header of the latest generation is the one read.
+/// The free pages are listed in pages of their own, the changed ones also rewritten in unused pages
+/// by each commit, so the pages the header on disk reaches are never overwritten.
+///
+/// The file stays open with its header and its free pages, shared by the handles of `share`.
+/// With a codec in the path, the nodes too big for a page are compressed, so the pages hold more rows.
+/// With a key, every page is encrypted and authenticated with its number.
+pub struct PageBinFile<Row>
+where
+    Row: Binary,
+{
+    path: BDPath,
+    /// The opened tree, opened again from the last commit after a failed change.
+    pages: Rc<RefCell<Option<Pages<Row>>>>,
+}
+
+#[derive(Clone, Copy)]
+struct Header {
+    /// 0 when there is no row.
+    root: u64,
+    len: u64,
+    pages: u64,
+    /// The last page of the free list, 0 when there is none.
+    free: u64,
+    /// The number of commits, its parity being the page of the header.
+    generation: u64,
+}
+
+/// A child of an internal node.
+struct Child {
+    page: u64,
+    /// The number of rows under it.
+    len: u64,
+    /// The key of the first row under it.
+    key: Vec<u8>,
+}
+
+enum Node {
+    Leaf(Vec<Vec<u8>>),
+    Internal(Vec<Child>),
+}
+
+impl Node {
+    /// The number of rows under the node.
+    fn len(&self) -> u64 {
+        match self {
+            Node::Leaf(rows) => rows.len() as u64,
+            Node::Internal(children) => children.iter().map(|child| child.len).sum(),
+        }
+    }
+
+    /// The key of the first row under the node.
+    fn key(&self, key_size: usize) -> Vec<u8> {
+        match self {
+            Node::Leaf(rows) => rows
+                .first()
+                .map_or_else(Vec::new, |row| row[..key_size].to_vec()),
+            Node::Internal(children) => children
+                .first()
+                .map_or_else(Vec::new, |child| child.key.clone()),
+        }
+    }
+
+    /// The kind, the number of entries and the entries.
+    fn bin(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        match self {
+            Node::Leaf(rows) => {
+                data.push(LEAF);
+                data.extend_from_slice(&(rows.len() as u64).to_le_bytes());
+                for row in rows {
+                    data.extend_from_slice(row);
+                }
+            }
+            Node::Internal(children) => {
+                data.push(INTERNAL);
+                data.extend_from_slice(&(children.len() as u64).to_le_bytes());
+                for child in children {
+                    data.extend_from_slice(&child.page.to_le_bytes());
+                    data.extend_from_slice(&child.len.to_le_bytes());
+                    data.extend_from_slice(&child.key);
+                }
+            }
+        }
+        data
+    }
+
+    fn split(self) -> io::Result<(Self, Self)> {
+        match self {
+            Node::Leaf(mut rows) if rows.len() > 1 => {
+                let right = rows.split_off(rows.len() / 2);
+                Ok((Node::Leaf(rows), Node::Leaf(right)))
+            }
+            Node::Internal(mut children) if children.len() > 1 => {
+                let right = children.split_off(children.len() / 2);
+                Ok((Node::Internal(children), Node::Internal(right)))
+            }
+            _ => Err(Error::other("the node doesn't fit in a page")),
+        }
+    }
+}
+
+/// An opened tree whose changes are only visible after `commit`.
+struct Pages<Row: Binary> {
+    file: File,
+    header: Header,
+    /// The free pages, the last ones used first.
+    free: Vec<u64>,
+    /// The pages of the free list on disk, the first one listing the first free pages.
+    list: Vec<u64>,
+    /// The fewest free pages since the commit, the pages of the free list listing them are kept.
+    low: usize,
+    /// The pages replaced by the changes, freed after the commit.
+    freed: Vec<u64>,
+    /// The pages written since the commit, free again as soon as they are replaced.
+    written: HashSet<u64>,
+    codec: Codec,
+    key: Option<EncryptionKey>,
+    row: PhantomData<Row>,
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[at..at + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Page (u64), number of rows (u64) and key of a child.
+fn child_size<Row: Binary>() -> usize {
+    16 + Row::key_size()
+}
+
+fn page_size<Row: Binary>() -> usize {
+    PAGE_SIZE.max(NODE_HEADER + 4 * Row::bin_size().max(child_size::<Row>()))
+}
+
+/// The bytes of a page left for the node once encrypted.
+fn capacity<Row: Binary>(key: Option<EncryptionKey>) -> usize {
+    page_size::<Row>() - key.map_or(0, |_| OVERHEAD)
+}
+
+/// The FNV-1a hash of the header, so a torn header is found without a key.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn header_bin<Row: Binary>(header: Header, key: Option<EncryptionKey>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(page_size::<Row>());
+    for value in [
+        header.root,
+        header.len,
+        header.pages,
+        header.free,
+        header.generation,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&checksum(&data).to_le_bytes());
+    data.resize(capacity::<Row>(key), 0);
+    data
+}
+
+/// The header, `None` if it is torn.
+fn header_from_bin(data: &[u8]) -> Option<Header> {
+    let header = data.get(..HEADER_SIZE + 8)?;
+    (read_u64(header, HEADER_SIZE) == checksum(&header[..HEADER_SIZE])).then(|| Header {
+        root: read_u64(header, 0),
+        len: read_u64(header, 8),
+        pages: read_u64(header, 16),
+        free: read_u64(header, 24),
+        generation: read_u64(header, 32),
+    })
+}
+
+/// The child containing `index` and the index in it.
+fn child(children: &[Child], mut index: usize, at_end: bool) -> io::Result<(usize, usize)> {
+    for (i, child) in children.iter().enumerate() {
+        let len = child.len as usize;
+        if index < len || (at_end && index == len) {
+            return Ok((i, index));
+        }
+        index -= len;
+    }
+    Err(Error::other("out of bound"))
+}
+
+impl<Row: Binary> Pages<Row> {
+    fn open(path: &BDPath) -> io::Result<Self> {
+        let file = File::options().read(true).write(true).open(path.full())?;
+        let mut pages = Self {
+            file,
+            header: Header {
+                root: 0,
+                len: 0,
+                pages: 0,
+                free: 0,
+                generation: 0,
+            },
+            free: Vec::new(),
+            list: Vec::new(),
+            low: 0,
+            freed: Vec::new(),
+            written: HashSet::new(),
+            codec: path.codec,
+            key: path.key,
+            row: PhantomData,
+        };
+        pages.header = (0..HEADER_PAGES)
+            .filter_map(|page| header_from_bin(&pages.read_page(page).ok()?))
+            .max_by_key(|header| header.generation)
+            .ok_or_else(|| Error::other(format!("{} has no valid header", path.full())))?;
+        pages.read_free()?;
+        Ok(pages)
+    }
+
+    /// Reads the free list from its last page, each page linking to the one before.
+    fn read_free(&mut self) -> io::Result<()> {
+        let mut page = self.header.free;
+        let mut listed = Vec::new();
+        while page != 0 {
+            let data = self.read_page(page)?;
+            let count = read_u64(&data, 8) as usize;
+            let free = data
+                .get(FREE_HEADER..FREE_HEADER + count * 8)
+                .ok_or_else(|| Error::other(format!("page {page} is truncated")))?;
+            listed.push(
+                (0..count)
+                    .map(|i| read_u64(free, i * 8))
+                    .collect::<Vec<u64>>(),
+            );
+            self.list.push(page);
+            page = read_u64(&data, 0);
+        }
+        self.list.reverse();
+        self.free = listed.into_iter().rev().flatten().collect();
+        self.low = self.free.len();
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        capacity::<Row>(self.key)
+    }
+
+    fn read_page(&mut self, page: u64) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; page_size::<Row>()];
+        self.file
+            .seek(SeekFrom::Start(page * page_size::<Row>() as u64))?;
+        self.file.read_exact(&mut data)?;
+        match self.key {
+            None => Ok(data),
+            Some(_) => encryption::decrypt(self.key, &page.to_le_bytes(), &data),
+        }
+    }
+
+    /// Writes `data`, `capacity` bytes, encrypted if the tree has a key.
+    fn write_page(&mut self, page: u64, data: &[u8]) -> io::Result<()> {
+        let data = encryption::encrypt(self.key, &page.to_le_bytes(), data)?;
+        self.file
+            .seek(SeekFrom::Start(page * page_size::<Row>() as u64))?;
+        self.file.write_all(&data)
+    }
+
+    fn read(&mut self, page: u64) -> io::Result<Node> {
+        let mut data = self.read_page(page)?;
+        if data[0] == COMPRESSED {
+            let len = read_u64(&data, 1) as usize;
+            data = decompress(
+                data.get(NODE_HEADER..NODE_HEADER + len)
+                    .ok_or_else(|| Error::other(format!("page {page} is truncated")))?,
+            )?;
+        }
+        let count = read_u64(&data, 1) as usize;
+        match data[0] {
+            LEAF => Ok(Node::Leaf(
+                (0..count)
+                    .map(|i| {
+                        let at = NODE_HEADER + i * Row::bin_size();
+                        data[at..at + Row::bin_size()].to_vec()
+                    })
+                    .collect(),
+            )),
+            INTERNAL => Ok(Node::Internal(
+                (0..count)
+                    .map(|i| {
+                        let at = NODE_HEADER + i * child_size::<Row>();
+                        Child {
+                            page: read_u64(&data, at),
+                            len: read_u64(&data, at + 8),
+                            key: data[at + 16..at + child_size::<Row>()].to_vec(),
+                        }
+                    })
+                    .collect(),
+            )),
+            kind => Err(Error::other(format!("unknown page kind {kind}"))),
+        }
+    }
+
+    /// The page of the node, compressed with the codec of the table if it doesn't fit otherwise,
+    /// `None` if it doesn't fit.
+    fn encode(&self, node: &Node) -> io::Result<Option<Vec<u8>>> {
+        let mut data = node.bin();
+        if data.len() > self.capacity() {
+            if self.codec == Codec::None || data.len() > COMPRESSED_PAGES * self.capacity() {
+                return Ok(None);
+            }
+            let compressed = compress(self.codec, &data)?;
+            if NODE_HEADER + compressed.len() > self.capacity() {
+                return Ok(None);
+            }
+            data = vec![COMPRESSED];
+            data.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+            data.extend_from_slice(&compressed);
+        }
+        data.resize(self.capacity(), 0);
+        Ok(Some(data))
+    }
+
+    fn write_encoded(&mut self, data: &[u8], node: &Node) -> io::Result<Child> {
+        let page = self.alloc();
+        self.write_page(page, data)?;
+        Ok(Child {
+            page,
+            len: node.len(),
+            key: node.key(Row::key_size()),
+        })
+    }
+
+    /// Writes the node in unused pages, split in halves until they fit,
+    /// and returns them with their number of rows.
+    fn write(&mut self, node: Node) -> io::Result<Vec<Child>> {
+        if let Some(data) = self.encode(&node)? {
+            return Ok(vec![self.write_encoded(&data, &node)?]);
+        }
+        let (left, right) = node.split()?;
+        let mut children = self.write(left)?;
+        children.extend(self.write(right)?);
+        Ok(children)
+    }
+
+    /// The page of the only node, or of the internal nodes written above them, 0 if there is none.
+    fn root(&mut self, mut children: Vec<Child>) -> io::Result<u64> {
+        while children.len() > 1 {
+            children = self.write(Node::Internal(children))?;
+        }
+        Ok(children.first().map_or(0, |child| child.page))
+    }
+
+    fn alloc(&mut self) -> u64 {
+        let page = match self.free.pop() {
+            Some(page) => {
+                self.low = self.low.min(self.free.len());
+                page
+            }
+            None => {
+                self.header.pages += 1;
+                self.header.pages - 1
+            }
+        };
+        self.written.insert(page);
+        page
+    }
+
+    /// Frees a page replaced by the changes, at once if it was written since the commit.
+    fn release(&mut self, page: u64) {
+        if self.written.remove(&page) {
+            self.free.push(page);
+        } else {
+            self.freed.push(page);
+        }
+    }
+
+    /// Rewrites the pages of the free list listing free pages that changed, in unused pages,
+    /// then writes the header over the older one once they and the new pages are on disk.
+    fn commit(&mut self) -> io::Result<()> {
+        let per_page = (self.capacity() - FREE_HEADER) / 8;
+        let mut first = self.low / per_page;
+        let mut pages = Vec::new();
+        loop {
+            if self.free.len() < first * per_page {
+                first -= 1;
+                continue;
+            }
+            let len = self.free.len() + self.freed.len() + self.list.len() - first;
+            if first + pages.len() >= len.div_ceil(per_page) {
+                break;
+            }
+            pages.push(self.alloc());
+        }
+        let replaced = self.list.split_off(first);
+        self.free.append(&mut self.freed);
+        self.free.extend(replaced);
+        for (i, page) in pages.into_iter().enumerate() {
+            let at = ((first + i) * per_page).min(self.free.len());
+            let listed = &self.free[at..(at + per_page).min(self.free.len())];
+            let mut data = self
+                .list
+                .last()
+                .copied()
+                .unwrap_or(0)
+                .to_le_bytes()
+                .to_vec();
+            data.extend_from_slice(&(listed.len() as u64).to_le_bytes());
+            for free in listed {
+                data.extend_from_slice(&free.to_le_bytes());
+            }
+            data.resize(self.capacity(), 0);
+            self.write_page(page, &data)?;
+            self.list.push(page);
+        }
+        self.header.free = self.list.last().copied().unwrap_or(0);
+        self.file.sync_all()?;
+        self.header.generation += 1;
+        let page = self.header.generation % HEADER_PAGES;
+        self.write_page(page, &header_bin::<Row>(self.header, self.key))?;
+        self.file.sync_all()?;
+        self.written.clear();
+        self.low = self.free.len();
+        Ok(())
+    }
+
+    fn get(&mut self, mut index: usize) -> io::Result<Vec<u8>> {
+        if index >= self.header.len as usize {
+            return Err(Error::other("out of bound"));
+        }
+        let mut page = self.header.root;
+        loop {
+            match self.read(page)? {
+                Node::Leaf(rows) => {
+                    return rows
+                        .into_iter()
+                        .nth(index)
+                        .ok_or_else(|| Error::other("out of bound"));
+                }
+                Node::Internal(children) => {
+                    let (i, child_index) = child(&children, index, false)?;
+                    page = children[i].page;
+                    index = child_index;
+                }
+            }
+        }
+    }
+
+    /// Pushes the `len` rows from `index` under `page`.
+    fn gets(
+        &mut self,
+        page: u64,
+        mut index: usize,
+        mut len: usize,
+        rows: &mut Vec<Vec<u8>>,
+    ) -> io::Result<()> {
+        match self.read(page)? {
+            Node::Leaf(leaf) => rows.extend(leaf.into_iter().skip(index).take(len)),
+            Node::Internal(children) => {
+                for child in children {
+                    let child_len = child.len as usize;
+                    if index >= child_len {
+                        index -= child_len;
+                        continue;
+                    }
+                    let taken = len.min(child_len - index);
+                    self.gets(child.page, index, taken, rows)?;
+                    len -= taken;
+                    index = 0;
+                    if len == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_root(&mut self, index: usize, row: &[u8]) -> io::Result<()> {
+        if index > self.header.len as usize {
+            return Err(Error::other("out of bound"));
+        }
+        let children = if self.header.root == 0 {
+            self.write(Node::Leaf(vec![row.to_vec()]))?
+        } else {
+            self.insert(self.header.root, index, row)?
+        };
+        self.header.root = self.root(children)?;
+        self.header.len += 1;
+        Ok(())
+    }
+
+    /// Returns the nodes replacing `page`, several if it was split.
+    fn insert(&mut self, page: u64, index: usize, row: &[u8]) -> io::Result<Vec<Child>> {
+        let node = match self.read(page)? {
+            Node::Leaf(mut rows) => {
+                rows.insert(index, row.to_vec());
+                Node::Leaf(rows)
+            }
+            Node::Internal(mut children) => {
+                let (i, index) = child(&children, index, true)?;
+                let new = self.insert(children[i].page, index, row)?;
+                children.splice(i..=i, new);
+                Node::Internal(children)
+            }
+        };
+        self.release(page);
+        self.write(node)
+    }
+
+    fn set_root(&mut self, index: usize, row: &[u8]) -> io::Result<()> {
+        if index >= self.header.len as usize {
+            return Err(Error::other("out of bound"));
+        }
+        let children = self.set(self.header.root, index, row)?;
+        self.header.root = self.root(children)?;
+        Ok(())
+    }
+
+    /// Returns the nodes replacing `page`, several if the row made it too big for a page.
+    fn set(&mut self, page: u64, index: usize, row: &[u8]) -> io::Result<Vec<Child>> {
+        let node = match self.read(page)? {
+            Node::Leaf(mut rows) => {
+                *rows
+                    .get_mut(index)
+                    .ok_or_else(|| Error::other("out of bound"))? = row.to_vec();
+                Node::Leaf(rows)
+            }
+            Node::Internal(mut children) => {
+                let (i, index) = child(&children, index, false)?;
+                let new = self.set(children[i].page, index, row)?;
+                children.splice(i..=i, new);
+                Node::Internal(children)
+            }
+        };
+        self.release(page);
+        self.write(node)
+    }
+
+    /// Removes the `len` rows from `index`, rewriting only the nodes at both ends of them.
+    fn remove_root(&mut self, index: usize, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        if self.header.root == 0 {
+            return Err(Error::other("out of bound"));
+        }
+        let children = self.remove(self.header.root, index, len)?;
+        self.header.root = self.root(children)?;
+        self.header.len -= len as u64;
+        while self.header.root != 0 {
+            match self.read(self.header.root)? {
+                Node::Internal(children) if children.len() == 1 => {
+                    self.release(self.header.root);
+                    self.header.root = children[0].page;
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the nodes replacing `page` once the `len` rows from `index` under it are removed,
+    /// none if it became empty.
+    fn remove(&mut self, page: u64, mut index: usize, mut len: usize) -> io::Result<Vec<Child>> {
+        let node = match self.read(page)? {
+            Node::Leaf(mut rows) => {
+                if index + len > rows.len() {
+                    return Err(Error::other("out of bound"));
+                }
+                rows.drain(index..index + len);
+                (!rows.is_empty()).then_some(Node::Leaf(rows))
+            }
+            Node::Internal(children) => {
+                let mut kept = Vec::with_capacity(children.len());
+                let mut cut = None;
+                for child in children {
+                    let child_len = child.len as usize;
+                    if len == 0 || index >= child_len {
+                        if len > 0 {
+                            index -= child_len;
+                        }
+                        kept.push(child);
+                        continue;
+                    }
+                    let taken = len.min(child_len - index);
+                    cut.get_or_insert(kept.len());
+                    if taken == child_len {
+                        self.release_tree(child.page)?;
+                    } else {
+                        kept.extend(self.remove(child.page, index, taken)?);
+                    }
+                    len -= taken;
+                    index = 0;
+                }
+                if len > 0 {
+                    return Err(Error::other("out of bound"));
+                }
+                if let Some(i) = cut
+                    && let Some(last) = kept.len().checked_sub(1)
+                {
+                    self.merge(&mut kept, i.min(last))?;
+                }
+                (!kept.is_empty()).then_some(Node::Internal(kept))
+            }
+        };
+        self.release(page);
+        match node {
+            Some(node) => self.write(node),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Frees the pages under `page` and it.
+    fn release_tree(&mut self, page: u64) -> io::Result<()> {
+        if let Node::Internal(children) = self.read(page)? {
+            for child in children {
+                self.release_tree(child.page)?;
+            }
+        }
+        self.release(page);
+        Ok(())
+    }
+
+    /// Merges the child with a neighbour if they fit in one page.
+    fn merge(&mut self, children: &mut Vec<Child>, i: usize) -> io::Result<()> {
+        let i = if i + 1 < children.len() {
+            i
+        } else if i > 0 {
+            i - 1
+        } else {
+            return Ok(());
+        };
+        let merged = match (
+            self.read(children[i].page)?,
+            self.read(children[i + 1].page)?,
+        ) {
+            (Node::Leaf(mut left), Node::Leaf(right)) => {
+                left.extend(right);
+                Node::Leaf(left)
+            }
+            (Node::Internal(mut left), Node::Internal(right)) => {
+                left.extend(right);
+                Node::Internal(left)
+            }
+            _ => return Ok(()),
+        };
+        let Some(data) = self.encode(&merged)? else {
+            return Ok(());
+        };
+        self.release(children[i].page);
+        self.release(children[i + 1].page);
+        let new = self.write_encoded(&data, &merged)?;
+        children.splice(i..=i + 1, [new]);
+        Ok(())
+    }
+}
+
+impl<Row: Table> Pages<Row> {
+    /// The first index where `pred` is false for the id of the row, the rows where it is true being first,
+    /// only reading the pages on the path to it.
+    fn partition_point_by_id(
+        &mut self,
+        path: &BDPath,
+        pred: &dyn Fn(&Row::ID) -> bool,
+    ) -> io::Result<usize> {
+        let id = |key: &[u8]| {
+            <Row::ID as Binary>::from_bin(
+                key.get(..<Row::ID as Binary>::bin_size())
+                    .ok_or_else(|| Error::other("the pages don't keep the ids"))?,
+                path,
+            )
+        };
+        let (mut page, mut index) = (self.header.root, 0);
+        while page != 0 {
+            match self.read(page)? {
+                Node::Leaf(rows) => {
+                    let (mut from, mut to) = (0, rows.len());
+                    while from < to {
+                        let mid = from + (to - from) / 2;
+                        if pred(&id(&rows[mid])?) {
+                            from = mid + 1;
+                        } else {
+                            to = mid;
+                        }
+                    }
+                    return Ok(index + from);
+                }
+                Node::Internal(children) => {
+                    // The last child whose first row is before the point contains it.
+                    let (mut from, mut to) = (1, children.len());
+                    while from < to {
+                        let mid = from + (to - from) / 2;
+                        if pred(&id(&children[mid].key)?) {
+                            from = mid + 1;
+                        } else {
+                            to = mid;
+                        }
+                    }
+                    let i = from - 1;
+                    index += children[..i]
+                        .iter()
+                        .map(|child| child.len as usize)
+                        .sum::<usize>();
+                    page = children[i].page;
+                }
+            }
+        }
+        Ok(index)
+    }
+}
+
+impl<Row: Binary> PageBinFile<Row> {
+    pub const fn path(&self) -> &BDPath {
+        &self.path
+    }
+
+    /// Runs `read` on the tree, opened by the first call.
+    fn read<T>(&self, read: impl FnOnce(&mut Pages<Row>) -> io::Result<T>) -> io::Result<T> {
+        let mut opened = self.pages.borrow_mut();
+        let pages = match opened.take() {
+            Some(pages) => pages,
+            None => Pages::open(&self.path)?,
+        };
+        read(opened.insert(pages))
+    }
+
+    /// Runs `change` on the tree then commits it, the tree is opened again if it fails.
+    fn write<T>(&self, change: impl FnOnce(&mut Pages<Row>) -> io::Result<T>) -> io::Result<T> {
+        let mut opened = self.pages.borrow_mut();
+        let mut pages = match opened.take() {
+            Some(pages) => pages,
+            None => Pages::open(&self.path)?,
+        };
+        let value = change(&mut pages)?;
+        pages.commit()?;
+        *opened = Some(pages);
+        Ok(value)
+    }
+
+    fn temp_path(&self) -> BDPath {
+        BDPath {
+            dir_path: self.path.dir_path.clone(),
+            rel_file_path: self.path.rel_file_path.replace(".", ".temp."),
+            dyn_storage: self.path.dyn_storage,
+            codec: self.path.codec,
+            key: self.path.key,
+        }
+    }
+
+    /// Replaces the file by a tree without rows.
+    fn create_empty(&self) -> io::Result<()> {
+        let temp = self.temp_path();
+        {
+            let mut file = File::create(temp.full())?;
+            let header = Header {
+                root: 0,
+                len: 0,
+                pages: HEADER_PAGES,
+                free: 0,
+                generation: 0,
+            };
+            for page in 0..HEADER_PAGES {
+                file.write_all(&encryption::encrypt(
+                    self.path.key,
+                    &page.to_le_bytes(),
+                    &header_bin::<Row>(header, self.path.key),
+                )?)?;
+            }
+            file.sync_all()?;
+        }
+        *self.pages.borrow_mut() = None;
+        fs::rename(temp.full(), self.path.full())
+    }
+}
+
+impl<Row: Binary> BaseBinFile<Row> for PageBinFile<Row> {
+    fn new(path: BDPath) -> io::Result<Self> {
+        let file = PageBinFile {
+            path,
+            pages: Rc::default(),
+        };
+        if !Path::new(file.path.full().as_str()).exists() {
+            create_dir_all(file.path.folder())?;
+            create_dir_all(file.path.dyn_path())?;
+            file.create_empty()?;
+        } else if !Path::new(file.path.dyn_path().as_str()).exists() {
+            create_dir_all(file.path.dyn_path())?;
+        }
+        Ok(file)
+    }
+
+    fn share(&self) -> Self {
+        PageBinFile {
+            path: self.path.clone(),
+            pages: Rc::clone(&self.pages),
+        }
+    }
+
+    fn path(&self) -> &BDPath {
+        &self.path
+    }
+
+    fn get(&self, index: usize) -> io::Result<Row> {
+        Row::from_bin(&self.read(|pages| pages.get(index))?, &self.path)
+    }
+
+    fn gets(&self, index: usize, len: Option<usize>) -> io::Result<Vec<Row>> {
+        let rows = self.read(|pages| {
+            let total = pages.header.len as usize;
+            if index == total {
+                return Ok(Vec::new());
+            }
+            if index > total {
+                return Err(Error::other("out of bound"));
+            }
+            let len = match len {
+                Some(0) => return Err(Error::other("must read at least 1 element")),
+                Some(len) if index + len > total => {
+                    return Err(Error::other(format!(
+                        "index:{} > len:{}",
+                        index + len,
+                        total
+                    )));
+                }
+                Some(len) => len,
+                None => total - index,
+            };
+
+            let mut rows = Vec::with_capacity(len);
+            let root = pages.header.root;
+            pages.gets(root, index, len, &mut rows)?;
+            Ok(rows)
+        })?;
+        rows.iter()
+            .map(|row| Row::from_bin(row, &self.path))
+            .collect()
+    }
+
+    fn partition_point_by_id(&self, pred: &dyn Fn(&Row::ID) -> bool) -> io::Result<usize>
+    where
+        Row: Table,
+    {
+        self.read(|pages| pages.partition_point_by_id(&self.path, pred))
+    }
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn len(&self) -> io::Result<usize> {
+        self.read(|pages| Ok(pages.header.len as usize))
+    }
+
+    fn insert(&mut self, index: usize, data: &mut Row) -> io::Result<()> {
+        self.insert_bin(index, &data.as_bin(&self.path)?)
+    }
+
+    fn inserts(&mut self, index: usize, datas: &mut [Row]) -> io::Result<()> {
+        let mut bin = Vec::with_capacity(datas.len() * Row::bin_size());
+        for data in datas {
+            bin.extend(data.as_bin(&self.path)?);
+        }
+        self.insert_bin(index, &bin)
+    }
+
+    fn insert_bin(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        self.write(|pages| {
+            for (i, row) in data.chunks(Row::bin_size()).enumerate() {
+                pages.insert_root(index + i, row)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn set(&mut self, index: usize, data: &mut Row) -> io::Result<()> {
+        self.set_bin(index, &data.as_bin(&self.path)?)
+    }
+
+    fn set_bin(&mut self, index: usize, data: &[u8]) -> io::Result<()> {
+        self.write(|pages| pages.set_root(index, data))
+    }
+
+    fn remove(&mut self, index: usize, len: Option<usize>) -> io::Result<()> {
+        let removed = self.write(|pages| {
+            let total = pages.header.len as usize;
+            let len = len.unwrap_or_else(|| total.saturating_sub(index));
+            let mut removed = Vec::new();
+            if self.path.is_main() && len > 0 && index + len <= total {
+                let root = pages.header.root;
+                pages.gets(root, index, len, &mut removed)?;
+            }
+            pages.remove_root(index, len)?;
+            Ok(removed)
+        })?;
+
+        for to_delete in removed {
+            Row::from_bin(&to_delete, &self.path)?.delete(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.create_empty()?;
+        if self.path.is_main() {
+            clear_dyn(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the pages written after the last commit.
+    fn repair(&mut self) -> io::Result<()> {
+        self.read(|pages| {
+            let len = pages.header.pages * page_size::<Row>() as u64;
+            if pages.file.metadata()?.len() > len {
+                pages.file.set_len(len)?;
+                pages.file.sync_all()?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -2,13 +2,24 @@ pub use std::io;
 pub use std::path::Path;
 
 pub use crate::bd_path::BDPath;
-pub use crate::bin_file::{BaseBinFile, BinFile};
-pub use crate::binary::Binary;
+pub use crate::bin_file::{BaseBinFile, BinFile, BinIter};
+pub use crate::binary::{Binary, Latin1Char};
 pub use crate::cache::Cache;
 pub use crate::cached_bin_file::CachedBinFile;
-pub use crate::dyn_binary::DynanicBinary;
-pub use crate::foreign::Foreign;
+pub use crate::compression::Codec;
+pub use crate::database::{CatalogEntry, Database, DatabaseTable};
+pub use crate::dyn_binary::{DynStorage, DynanicBinary};
+pub use crate::encryption::EncryptionKey;
+pub use crate::fixed_string::FixedString;
+pub use crate::foreign::{Foreign, OnDelete, References, ReferencingTable};
 pub use crate::index_file::{
     CachedIndexFile, IndexFile, IndexGet, IndexRow, SpecificIndexFile, UnspecifiedIndex,
 };
-pub use crate::table::{CachedTableFile, SpecificTableFile, Table, TableFile, TableGet};
+pub use crate::page_bin_file::PageBinFile;
+pub use crate::query::{Join, Op, Query, SortedBy};
+pub use crate::schema::Schema;
+pub use crate::table::{
+    CachedTableFile, PageTableFile, SpecificTableFile, Table, TableFile, TableGet, TableInsert,
+};
+pub use crate::transaction::{Transaction, TransactionTable};
+pub use crate::vacuum::DynVacuum;
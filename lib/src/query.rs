@@ -0,0 +1,273 @@
+use std::{cmp::Ordering, io, ops::Bound};
+
+use crate::{
+    bin_file::BaseBinFile,
+    table::{SpecificTableFile, Table},
+};
+
+/// The comparison of a column with a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    /// If a column comparing as `ordering` to the value matches.
+    pub const fn matches(self, ordering: Option<Ordering>) -> bool {
+        matches!(
+            (self, ordering),
+            (Op::Eq | Op::Le | Op::Ge, Some(Ordering::Equal))
+                | (Op::Ne, Some(Ordering::Less | Ordering::Greater))
+                | (Op::Lt | Op::Le, Some(Ordering::Less))
+                | (Op::Gt | Op::Ge, Some(Ordering::Greater))
+        )
+    }
+
+    /// The range of the matching columns, `None` for `Ne`.
+    pub fn bounds<T: Clone>(self, value: T) -> Option<(Bound<T>, Bound<T>)> {
+        match self {
+            Op::Eq => Some((Bound::Included(value.clone()), Bound::Included(value))),
+            Op::Ne => None,
+            Op::Lt => Some((Bound::Unbounded, Bound::Excluded(value))),
+            Op::Le => Some((Bound::Unbounded, Bound::Included(value))),
+            Op::Gt => Some((Bound::Excluded(value), Bound::Unbounded)),
+            Op::Ge => Some((Bound::Included(value), Bound::Unbounded)),
+        }
+    }
+}
+
+/// The column the rows are read in the order of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortedBy {
+    Id,
+    Index(&'static str),
+}
+
+type Filter<'a, Row> = Box<dyn Fn(&Row) -> bool + 'a>;
+type Cmp<'a, Row> = Box<dyn Fn(&Row, &Row) -> Ordering + 'a>;
+type Rows<'a, Row> = Box<dyn Iterator<Item = io::Result<Row>> + 'a>;
+type Source<'a, Row, BinFile> = Box<
+    dyn FnOnce(
+            &'a SpecificTableFile<Row, BinFile>,
+            usize,
+            Option<usize>,
+        ) -> io::Result<Rows<'a, Row>>
+        + 'a,
+>;
+
+struct Order<'a, Row> {
+    by: Option<SortedBy>,
+    desc: bool,
+    cmp: Cmp<'a, Row>,
+}
+
+/// Reads the rows matching all the filters.
+///
+/// The `where_` methods generated by the `Table` derive read the rows from the id or an index
+/// when they can, otherwise all the rows are streamed and filtered.
+pub struct Query<'a, Row: Table, BinFile: BaseBinFile<Row>> {
+    table: &'a SpecificTableFile<Row, BinFile>,
+    sorted_by: SortedBy,
+    source: Option<Source<'a, Row, BinFile>>,
+    filters: Vec<Filter<'a, Row>>,
+    order: Option<Order<'a, Row>>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl<'a, Row: Table, BinFile: BaseBinFile<Row>> Query<'a, Row, BinFile> {
+    pub fn new(table: &'a SpecificTableFile<Row, BinFile>) -> Self {
+        Self {
+            table,
+            sorted_by: SortedBy::Id,
+            source: None,
+            filters: Vec::new(),
+            order: None,
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    pub fn filter(mut self, filter: impl Fn(&Row) -> bool + 'a) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Reads the rows with `rows` unless an other source was already chosen.
+    /// The rows must still go through the filters.
+    ///
+    /// `rows` skips an offset of rows and returns at most a limit of them, given by `run`
+    /// when the filter added with the source is the only one and the rows are read in order.
+    pub fn source<Iter: Iterator<Item = io::Result<Row>> + 'a>(
+        mut self,
+        sorted_by: SortedBy,
+        rows: impl FnOnce(&'a SpecificTableFile<Row, BinFile>, usize, Option<usize>) -> io::Result<Iter>
+        + 'a,
+    ) -> Self {
+        if self.source.is_none() {
+            self.sorted_by = sorted_by;
+            self.source = Some(Box::new(move |table, offset, limit| {
+                Ok(Box::new(rows(table, offset, limit)?))
+            }));
+        }
+        self
+    }
+
+    pub fn order_by(self, cmp: impl Fn(&Row, &Row) -> Ordering + 'a) -> Self {
+        self.order_by_column(None, false, cmp)
+    }
+
+    /// The rows aren't sorted again if they are already read in the order of `by`.
+    pub fn order_by_column(
+        mut self,
+        by: Option<SortedBy>,
+        desc: bool,
+        cmp: impl Fn(&Row, &Row) -> Ordering + 'a,
+    ) -> Self {
+        self.order = Some(Order {
+            by,
+            desc,
+            cmp: Box::new(cmp),
+        });
+        self
+    }
+
+    pub const fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub const fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Pairs each row with the row of `parents` it references, dropping the rows without one.
+    pub fn join<Parent: Table, ParentBinFile: BaseBinFile<Parent>>(
+        self,
+        parents: &'a SpecificTableFile<Parent, ParentBinFile>,
+        foreign: impl Fn(&Row) -> Option<&Parent::ID> + 'a,
+    ) -> Join<'a, Row, BinFile, Parent, ParentBinFile> {
+        Join {
+            query: self,
+            parents,
+            foreign: Box::new(foreign),
+            inner: true,
+        }
+    }
+
+    /// Pairs each row with the row of `parents` it references, if there is one.
+    pub fn left_join<Parent: Table, ParentBinFile: BaseBinFile<Parent>>(
+        self,
+        parents: &'a SpecificTableFile<Parent, ParentBinFile>,
+        foreign: impl Fn(&Row) -> Option<&Parent::ID> + 'a,
+    ) -> Join<'a, Row, BinFile, Parent, ParentBinFile> {
+        Join {
+            inner: false,
+            ..self.join(parents, foreign)
+        }
+    }
+
+    pub fn run(mut self) -> io::Result<Vec<Row>> {
+        let sorted = self
+            .order
+            .as_ref()
+            .is_none_or(|order| !order.desc && order.by == Some(self.sorted_by));
+        let rows: Rows<'a, Row> = match self.source {
+            Some(source) if sorted && self.filters.len() == 1 => {
+                let rows = source(self.table, self.offset, self.limit)?;
+                (self.offset, self.limit) = (0, None);
+                rows
+            }
+            Some(source) => source(self.table, 0, None)?,
+            None => Box::new(self.table.iter()?),
+        };
+        let filters = self.filters;
+        let rows = rows.filter(move |row| match row {
+            Ok(row) => filters.iter().all(|filter| filter(row)),
+            Err(_) => true,
+        });
+        let limit = self.limit.unwrap_or(usize::MAX);
+
+        match self.order {
+            Some(order) if order.desc || order.by != Some(self.sorted_by) => {
+                let mut rows = rows.collect::<io::Result<Vec<Row>>>()?;
+                if order.desc {
+                    rows.sort_by(|a, b| (order.cmp)(b, a));
+                } else {
+                    rows.sort_by(|a, b| (order.cmp)(a, b));
+                }
+                Ok(rows.into_iter().skip(self.offset).take(limit).collect())
+            }
+            _ => rows.skip(self.offset).take(limit).collect(),
+        }
+    }
+}
+
+type ForeignId<'a, Row, Parent> = Box<dyn Fn(&Row) -> Option<&<Parent as Table>::ID> + 'a>;
+
+/// A query whose rows are paired with the rows they reference in an other table.
+///
+/// The referenced ids are read in one batch with `SpecificTableFile::get_sorted`,
+/// they are only sorted if the rows aren't already in the order of the `Foreign` column.
+pub struct Join<
+    'a,
+    Row: Table,
+    BinFile: BaseBinFile<Row>,
+    Parent: Table,
+    ParentBinFile: BaseBinFile<Parent>,
+> {
+    query: Query<'a, Row, BinFile>,
+    parents: &'a SpecificTableFile<Parent, ParentBinFile>,
+    foreign: ForeignId<'a, Row, Parent>,
+    inner: bool,
+}
+
+impl<'a, Row: Table, BinFile: BaseBinFile<Row>, Parent: Table, ParentBinFile: BaseBinFile<Parent>>
+    Join<'a, Row, BinFile, Parent, ParentBinFile>
+{
+    /// The `offset` and `limit` of the query apply to the joined rows.
+    pub fn run(mut self) -> io::Result<Vec<(Row, Option<Parent>)>> {
+        let (offset, limit) = (self.query.offset, self.query.limit);
+        if self.inner {
+            self.query.offset = 0;
+            self.query.limit = None;
+        }
+        let rows = self.query.run()?;
+
+        let mut ids: Vec<Parent::ID> = rows
+            .iter()
+            .filter_map(|row| (self.foreign)(row).cloned())
+            .collect();
+        let cmp = |a: &Parent::ID, b: &Parent::ID| a.partial_cmp(b).unwrap_or(Ordering::Equal);
+        if !ids.is_sorted_by(|a, b| cmp(a, b).is_le()) {
+            ids.sort_by(cmp);
+        }
+        ids.dedup_by(|a, b| cmp(a, b).is_eq());
+        let parents = self.parents.get_sorted(&ids)?;
+
+        let joined = rows.into_iter().filter_map(|row| {
+            let parent = (self.foreign)(&row)
+                .and_then(|id| {
+                    parents
+                        .binary_search_by(|parent| parent.id_cmp(id).unwrap_or(Ordering::Equal))
+                        .ok()
+                })
+                .map(|index| parents[index].clone());
+            (!self.inner || parent.is_some()).then_some((row, parent))
+        });
+        if self.inner {
+            Ok(joined
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .collect())
+        } else {
+            Ok(joined.collect())
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs::{read_to_string, rename, write},
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+use crate::compression::Codec;
+
+/// A column of a `Schema`, `ty` is the type as written in the struct without spaces.
+/// The types are compared without their paths, so `std::string::String` is the same as `String`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub ty: String,
+    pub size: usize,
+}
+
+/// An index of a `Schema`, named after its column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaIndex {
+    pub name: String,
+    pub unique: bool,
+}
+
+/// The layout of the rows of a table, generated by the `Table` derive.
+/// The columns are in the order of the bytes of a row, starting with the primary key.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Schema {
+    /// Set with `#[Version(..)]` on the struct, 0 by default.
+    pub version: u32,
+    /// Set with `#[Compression(..)]` on the struct, the codec of the new pages of a `PageBinFile`.
+    /// The pages start with their codec, so changing it doesn't make the stored rows unreadable.
+    pub compression: Codec,
+    pub columns: Vec<Column>,
+    pub indexes: Vec<SchemaIndex>,
+}
+
+/// The type without the path of each segment, like `Option<String>` for `Option<std::string::String>`.
+fn unqualified(ty: &str) -> String {
+    let mut unqualified = String::new();
+    let mut rest = ty;
+    while let Some(position) = rest.find("::") {
+        let before = &rest[..position];
+        let start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |start| start + 1);
+        unqualified += &before[..start];
+        rest = &rest[position + 2..];
+    }
+    unqualified + rest
+}
+
+impl Schema {
+    pub const fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub const fn compression(mut self, compression: Codec) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn column(mut self, name: &str, ty: &str, size: usize) -> Self {
+        self.columns.push(Column {
+            name: name.to_owned(),
+            ty: ty.to_owned(),
+            size,
+        });
+        self
+    }
+
+    pub fn index(mut self, name: &str, unique: bool) -> Self {
+        self.indexes.push(SchemaIndex {
+            name: name.to_owned(),
+            unique,
+        });
+        self
+    }
+
+    /// The changes of the version and columns since `stored`, that make the stored rows unreadable.
+    pub fn diff(&self, stored: &Self) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.version != stored.version {
+            changes.push(format!(
+                "version changed from {} to {}",
+                stored.version, self.version
+            ));
+        }
+        for (position, old) in stored.columns.iter().enumerate() {
+            let Some(new_position) = self.columns.iter().position(|new| new.name == old.name)
+            else {
+                changes.push(format!("column {} ({}) was removed", old.name, old.ty));
+                continue;
+            };
+            let new = &self.columns[new_position];
+            if new_position != position {
+                changes.push(format!(
+                    "column {} moved from position {position} to {new_position}",
+                    old.name
+                ));
+            }
+            if unqualified(&new.ty) != unqualified(&old.ty) {
+                changes.push(format!(
+                    "column {} changed type from {} to {}",
+                    old.name, old.ty, new.ty
+                ));
+            }
+            if new.size != old.size {
+                changes.push(format!(
+                    "column {} changed size from {} to {} bytes",
+                    old.name, old.size, new.size
+                ));
+            }
+        }
+        for new in &self.columns {
+            if !stored.columns.iter().any(|old| old.name == new.name) {
+                changes.push(format!("column {} ({}) was added", new.name, new.ty));
+            }
+        }
+        changes
+    }
+
+    /// `None` if the file doesn't exist.
+    pub fn read(path: &str) -> io::Result<Option<Self>> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+        let mut schema = Self::default();
+        for line in read_to_string(path)?.lines() {
+            schema = match line.split('\t').collect::<Vec<&str>>()[..] {
+                ["column", name, ty, size] => {
+                    schema.column(name, ty, size.parse().map_err(Error::other)?)
+                }
+                ["index", name, unique] => schema.index(name, unique == "unique"),
+                ["version", version] => schema.version(version.parse().map_err(Error::other)?),
+                ["compression", codec] => schema.compression(Codec::from_name(codec)?),
+                _ => return Err(Error::other(format!("invalid schema line: {line}"))),
+            };
+        }
+        Ok(Some(schema))
+    }
+
+    /// The schema is written to a temporary file first so it is never left half written.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut text = format!("version\t{}\n", self.version);
+        if self.compression != Codec::None {
+            text += &format!("compression\t{}\n", self.compression.name());
+        }
+        for column in &self.columns {
+            text += &format!("column\t{}\t{}\t{}\n", column.name, column.ty, column.size);
+        }
+        for index in &self.indexes {
+            let unique = if index.unique { "unique" } else { "index" };
+            text += &format!("index\t{}\t{unique}\n", index.name);
+        }
+        let temporary = format!("{path}.tmp");
+        write(temporary.as_str(), text)?;
+        rename(temporary, path)
+    }
+
+    /// Fails with the diff if the version or the columns of the stored schema are different.
+    pub fn check(&self, stored: &Self, table: &str) -> io::Result<()> {
+        let changes = self.diff(stored);
+        if changes.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the schema of {table} changed: {}", changes.join(", ")),
+            ))
+        }
+    }
+}
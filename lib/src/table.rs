@@ -1,20 +1,31 @@
 use crate::{
     bd_path::BDPath,
-    bin_file::BaseBinFile,
+    bin_file::{BaseBinFile, BinIter},
     binary::Binary,
-    index_file::{IdAsIndexFile, IndexGet, IndexRow, SpecificIndexFile, UnspecifiedIndex},
-    prelude::{BinFile, CachedBinFile},
+    dyn_binary::{clear_dyn, reference_dyn},
+    encryption::{EncryptionKey, check_key},
+    foreign::Relation,
+    index_file::{IndexRow, SpecificIndexFile, UnspecifiedIndex, check_layout, window},
+    migration::finish_migration,
+    prelude::{BinFile, CachedBinFile, PageBinFile},
+    query::Query,
+    schema::Schema,
+    transaction,
+    wal::{Wal, WalOp},
 };
 pub use rust_db_macro::Table;
 use std::{
+    cell::RefCell,
     cmp::Ordering,
-    fs::{create_dir, remove_dir_all},
     io::{self, Error},
+    ops::{Bound, Range, RangeBounds},
+    path::Path,
+    rc::Rc,
 };
 
 pub trait Table: Binary + Clone
 where
-    Self::ID: Binary + PartialOrd<Self::ID>,
+    Self::ID: Binary + PartialOrd<Self::ID> + Clone,
 {
     type ID;
 
@@ -22,7 +33,9 @@ where
     fn id_cmp(&self, other: &Self::ID) -> Option<Ordering> {
         self.id().partial_cmp(other)
     }
-    fn get_indexes(path: String) -> io::Result<Vec<Box<dyn UnspecifiedIndex<Self>>>>;
+    /// The index files of the table of `path`, encrypted with its key.
+    fn get_indexes(path: &BDPath) -> io::Result<Vec<Box<dyn UnspecifiedIndex<Self>>>>;
+    fn schema() -> Schema;
 }
 
 #[derive(Debug)]
@@ -33,26 +46,191 @@ pub enum TableGet<Ok> {
     Err(io::Error),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableInsert<ID> {
+    Inserted,
+    Updated,
+    Replaced,
+    DuplicateId,
+    /// The name of the unique field that already has the value and the id of the row that has it.
+    DuplicateUnique(&'static str, ID),
+    IdChanged,
+    /// The name of the `Foreign` column referencing a row that doesn't exist.
+    MissingReference(&'static str),
+}
+
 pub type TableFile<Row> = SpecificTableFile<Row, BinFile<Row>>;
 pub type CachedTableFile<Row> = SpecificTableFile<Row, CachedBinFile<Row>>;
+pub type PageTableFile<Row> = SpecificTableFile<Row, PageBinFile<Row>>;
 
 pub struct SpecificTableFile<Row: Table, RowBinFile: BaseBinFile<Row>> {
-    bin: RowBinFile,
-    id_index: IdAsIndexFile<Row::ID, Row, RowBinFile>,
-    other_index: Vec<Box<dyn UnspecifiedIndex<Row>>>,
+    pub(crate) bin: RowBinFile,
+    pub(crate) other_index: Vec<Box<dyn UnspecifiedIndex<Row>>>,
+    pub(crate) wal: Wal,
+    /// The `References` to and from the other tables, added by `add_references`
+    /// and shared by the handles given by `share`.
+    pub(crate) relations: Rc<RefCell<Vec<Box<dyn Relation<Row>>>>>,
 }
 
 impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTableFile<Row, BinFile> {
+    /// Replays the changes that were logged in the wal but not completely applied.
+    ///
+    /// Fails without touching the files if the version or the columns changed
+    /// since the `schema` file was written, `migrate` converts the rows instead.
     pub fn new(path: String) -> io::Result<Self> {
-        let path = BDPath {
-            dir_path: path,
-            rel_file_path: "main.bin".to_owned(),
-        };
-        Ok(Self {
-            other_index: Table::get_indexes(path.dir_path.clone())?,
+        Self::open(path, None)
+    }
+
+    /// Like `new`, for a table whose files and dynamic data are encrypted with `key`,
+    /// a new table is encrypted with it.
+    ///
+    /// Fails if the table is encrypted with another key or isn't encrypted,
+    /// `rotate_key` changes the key of a table.
+    pub fn new_encrypted(path: String, key: EncryptionKey) -> io::Result<Self> {
+        Self::open(path, Some(key))
+    }
+
+    pub(crate) fn open(path: String, key: Option<EncryptionKey>) -> io::Result<Self> {
+        finish_migration(path.as_str())?;
+        let schema_path = format!("{path}/schema");
+        let stored = Schema::read(schema_path.as_str())?;
+        let schema = Row::schema();
+        if let Some(stored) = &stored {
+            schema.check(stored, path.as_str())?;
+        }
+        let path = BDPath::new_main(path)
+            .with_codec(schema.compression)
+            .with_key(key);
+        check_key(&path)?;
+        check_layout(&path)?;
+        let mut table = Self {
+            other_index: Table::get_indexes(&path)?,
             bin: BinFile::new(path.clone())?,
-            id_index: IdAsIndexFile::new(Box::new(Row::id_cmp))?,
-        })
+            wal: Wal::new(&path)?,
+            relations: Rc::default(),
+        };
+        table.recover()?;
+        table.build_new_indexes()?;
+        if stored.as_ref() != Some(&schema) {
+            schema.write(schema_path.as_str())?;
+        }
+        Ok(table)
+    }
+
+    /// Another handle on the table, sharing the caches, the wal and the relations of this one,
+    /// without touching the files.
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            other_index: self
+                .other_index
+                .iter()
+                .map(|index_file| index_file.share())
+                .collect(),
+            bin: self.bin.share(),
+            wal: self.wal.share(),
+            relations: Rc::clone(&self.relations),
+        }
+    }
+
+    /// Builds the indexes that don't have an entry per row, like the ones added to an existing table.
+    fn build_new_indexes(&mut self) -> io::Result<()> {
+        let len = self.bin.len()?;
+        if self
+            .other_index
+            .iter()
+            .all(|index_file| index_file.len().is_ok_and(|l| l == len))
+        {
+            return Ok(());
+        }
+        let rows = self.bin.gets(0, None)?;
+        for index_file in &mut self.other_index {
+            if index_file.len()? != len {
+                index_file.rebuild(&rows)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the entries left in the write-ahead log, then deletes the dynamic data
+    /// that no row references, written or replaced by the interrupted writes.
+    pub(crate) fn recover(&mut self) -> io::Result<()> {
+        let entries = self.wal.entries()?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.bin.repair()?;
+        let mut commits = Vec::new();
+        for (_, ops) in &entries {
+            match ops.split_first() {
+                Some((WalOp::Transaction(commit), ops)) => {
+                    let commit = String::from_utf8_lossy(commit).into_owned();
+                    if Path::new(commit.as_str()).exists() {
+                        for op in ops {
+                            self.replay(op)?;
+                        }
+                        commits.push(commit);
+                    }
+                }
+                _ => {
+                    for op in ops {
+                        self.replay(op)?;
+                    }
+                }
+            }
+        }
+        let rows = self.bin.gets(0, None)?;
+        for index_file in &mut self.other_index {
+            index_file.rebuild(&rows)?;
+        }
+        self.vacuum_dyn(false)?;
+        for (entry, _) in entries {
+            self.wal.done(entry)?;
+        }
+        for commit in commits {
+            transaction::release_commit(&commit)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the op to the main file only if it wasn't already.
+    /// The dynamic data it replaced is deleted by `recover` with the other unreferenced data.
+    fn replay(&mut self, op: &WalOp) -> io::Result<()> {
+        match op {
+            WalOp::Insert(bin) => {
+                let row = Row::from_bin(bin, self.bin.path())?;
+                if let Err(index) = self.search(row.id())? {
+                    self.bin.insert_bin(index, bin)?;
+                }
+            }
+            WalOp::Update(bin) => {
+                let row = Row::from_bin(bin, self.bin.path())?;
+                if let Ok(index) = self.search(row.id())? {
+                    self.bin.set_bin(index, bin)?;
+                }
+            }
+            WalOp::Remove(id) => {
+                let id = Row::ID::from_bin(id, self.bin.path())?;
+                if let Ok(index) = self.search(&id)? {
+                    self.bin.remove(index, Some(1))?;
+                }
+            }
+            WalOp::Clear => self.base_clear()?,
+            WalOp::Transaction(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Writes the ops in the wal before applying them.
+    /// If the apply fails, the ops will be replayed the next time the table is opened.
+    fn logged<T>(
+        &mut self,
+        ops: &[WalOp],
+        apply: impl FnOnce(&mut Self) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let entry = self.wal.write(ops)?;
+        let result = apply(self)?;
+        self.wal.done(entry)?;
+        Ok(result)
     }
 
     pub fn get_by_index(&self, index: usize) -> io::Result<Row> {
@@ -60,19 +238,102 @@ impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTableFile<Row, BinFile> {
     }
 
     pub fn get_by_id(&self, id: &Row::ID) -> TableGet<Row> {
-        match &match self.id_index.indx(&self.bin, id) {
-            IndexGet::Found(_, index) => index,
-            IndexGet::NotFound(_) => return TableGet::NotFound,
-            IndexGet::InternalError(e) => return TableGet::InternalError(e),
-            IndexGet::Err(e) => return TableGet::Err(e),
-        }[..]
-        {
-            [] => TableGet::InternalError("index returned an empty array".to_owned()),
-            [data] => TableGet::Found(data.clone()),
-            _ => TableGet::InternalError("multiple with the same id".to_owned()),
+        match self.lookup(id) {
+            Ok(Ok((_, row))) => TableGet::Found(row),
+            Ok(Err(_)) => TableGet::NotFound,
+            Err(e) => TableGet::Err(e),
         }
     }
 
+    /// The rows whose id is in `range`, skipping `offset` rows and returning at most `limit`.
+    pub fn range_by_id(
+        &self,
+        range: impl RangeBounds<Row::ID>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<Row>> {
+        let range = window(self.positions(&range)?, offset, limit);
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.bin.gets(range.start, Some(range.len()))
+    }
+
+    pub fn iter(&self) -> io::Result<BinIter<'_, Row, BinFile>> {
+        self.bin.iter()
+    }
+
+    /// Streams the rows whose id is in `range`, skipping `offset` rows and returning at most `limit`.
+    pub fn iter_range(
+        &self,
+        range: impl RangeBounds<Row::ID>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<BinIter<'_, Row, BinFile>> {
+        Ok(self
+            .bin
+            .iter_range(window(self.positions(&range)?, offset, limit)))
+    }
+
+    /// Streams the rows of `ids`, failing on a missing one.
+    pub fn iter_ids<'b>(
+        &'b self,
+        ids: impl Iterator<Item = io::Result<Row::ID>> + 'b,
+    ) -> impl Iterator<Item = io::Result<Row>> + 'b {
+        ids.map(|id| Ok(self.find(&id?, "id not found")?.1))
+    }
+
+    pub fn query(&self) -> Query<'_, Row, BinFile> {
+        Query::new(self)
+    }
+
+    pub fn get_by_ids<'b>(&self, ids: impl IntoIterator<Item = &'b Row::ID>) -> io::Result<Vec<Row>>
+    where
+        Row::ID: 'b,
+    {
+        ids.into_iter()
+            .map(|id| Ok(self.find(id, "id not found")?.1))
+            .collect()
+    }
+
+    /// The rows of the sorted and deduplicated `ids`, skipping the missing ones.
+    /// The rows between the first and last id are streamed and merged with the ids
+    /// unless a binary search per id reads fewer rows.
+    pub fn get_sorted(&self, ids: &[Row::ID]) -> io::Result<Vec<Row>> {
+        let (Some(first), Some(last)) = (ids.first(), ids.last()) else {
+            return Ok(Vec::new());
+        };
+        let positions = self.positions(&(first.clone()..=last.clone()))?;
+        let mut rows = Vec::with_capacity(ids.len());
+        let searches = ids.len() * (usize::BITS - positions.len().leading_zeros()) as usize;
+        if searches < positions.len() {
+            for id in ids {
+                if let Ok(index) = self.search(id)? {
+                    rows.push(self.bin.get(index)?);
+                }
+            }
+            return Ok(rows);
+        }
+
+        let mut ids = ids.iter().peekable();
+        for row in self.bin.iter_range(positions) {
+            let row = row?;
+            while ids
+                .next_if(|id| row.id_cmp(id) == Some(Ordering::Greater))
+                .is_some()
+            {}
+            match ids.peek() {
+                Some(id) if row.id_cmp(id) == Some(Ordering::Equal) => {
+                    rows.push(row);
+                    ids.next();
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Ok(rows)
+    }
+
     pub fn get_all(&self) -> io::Result<Vec<Row>> {
         self.bin.gets(0, None)
     }
@@ -85,51 +346,240 @@ impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTableFile<Row, BinFile> {
         self.bin.len()
     }
 
-    pub fn insert(&mut self, data: &mut Row) -> std::io::Result<bool> {
+    pub fn insert(&mut self, data: &mut Row) -> std::io::Result<TableInsert<Row::ID>> {
+        if let Some(column) = self.missing_parent(data)? {
+            return Ok(TableInsert::MissingReference(column));
+        }
+        let Err(index) = self.search(data.id())? else {
+            return Ok(TableInsert::DuplicateId);
+        };
+
         for index_file in &mut self.other_index {
-            if index_file.check_unique(data)?.is_none() {
-                return Ok(false);
+            if let Some(id) = index_file.check_unique(data)? {
+                return Ok(TableInsert::DuplicateUnique(index_file.name(), id));
             }
         }
 
-        let index = match self.id_index.indx(&self.bin, data.id()) {
-            IndexGet::Found(_, _) => return Ok(false),
-            IndexGet::NotFound(i) => i,
-            IndexGet::InternalError(e) => return Err(Error::other(e)),
-            IndexGet::Err(e) => return Err(e),
-        };
+        let bin = data.as_bin(self.bin.path())?;
+        self.logged(&[WalOp::Insert(bin.clone())], |table| {
+            reference_dyn(data, table.bin.path())?;
+            table.base_insert(index, &bin, data)
+        })?;
+        Ok(TableInsert::Inserted)
+    }
 
-        self.bin.insert(index, data)?;
+    fn base_insert(&mut self, index: usize, bin: &[u8], data: &mut Row) -> std::io::Result<()> {
+        self.bin.insert_bin(index, bin)?;
         for index_file in &mut self.other_index {
-            index_file.insert(index, data)?;
+            index_file.insert(data)?;
         }
-        Ok(true)
+        Ok(())
     }
 
-    pub fn remove(&mut self, id: &Row::ID) -> std::io::Result<()> {
-        let (index, datas) = match self.id_index.indx(&self.bin, id) {
-            IndexGet::Found(index, datas) => (index, datas),
-            IndexGet::NotFound(_) => return Err(Error::other("remove not found")),
-            IndexGet::InternalError(e) => return Err(Error::other(e)),
-            IndexGet::Err(e) => return Err(e),
-        };
-        match &datas[..] {
-            [] => Err(Error::other("index returned an empty array")),
-            [_] => {
-                for index_file in &mut self.other_index {
-                    index_file.remove(index)?;
+    pub fn upsert(&mut self, data: &mut Row) -> std::io::Result<TableInsert<Row::ID>> {
+        match self.search(data.id())? {
+            Ok(_) => self.update(data),
+            Err(_) => self.insert(data),
+        }
+    }
+
+    /// Removes the row with the same id and the rows with the same unique values before inserting.
+    pub fn insert_or_replace(&mut self, data: &mut Row) -> std::io::Result<TableInsert<Row::ID>> {
+        if let Some(column) = self.missing_parent(data)? {
+            return Ok(TableInsert::MissingReference(column));
+        }
+        let found = self.search(data.id())?.is_ok();
+        let mut conflicts: Vec<Row::ID> = Vec::new();
+        for index_file in &mut self.other_index {
+            if let Some(id) = index_file.check_unique(data)?
+                && data.id_cmp(&id) != Some(Ordering::Equal)
+                && !conflicts
+                    .iter()
+                    .any(|conflict| conflict.partial_cmp(&id) == Some(Ordering::Equal))
+            {
+                conflicts.push(id);
+            }
+        }
+
+        for id in &conflicts {
+            self.check_unreferenced(id)?;
+        }
+        let mut ops = conflicts
+            .iter()
+            .map(|id| Ok(WalOp::Remove(id.clone().as_bin(self.bin.path())?)))
+            .collect::<io::Result<Vec<WalOp>>>()?;
+        let bin = data.as_bin(self.bin.path())?;
+        ops.push(if found {
+            WalOp::Update(bin.clone())
+        } else {
+            WalOp::Insert(bin.clone())
+        });
+
+        self.logged(&ops, |table| {
+            reference_dyn(data, table.bin.path())?;
+            for id in &conflicts {
+                let (index, _) = table.find(id, "remove not found")?;
+                table.base_remove(index)?;
+            }
+            match table.search(data.id())? {
+                Ok(index) => {
+                    let old = table.bin.get(index)?;
+                    table.base_set(index, &bin, &old, data)
                 }
-                self.bin.remove(index, Some(1))?;
-                Ok(())
+                Err(index) => table.base_insert(index, &bin, data),
             }
-            _ => Err(Error::other("multiple with the same id")),
+        })?;
+        Ok(if found || !conflicts.is_empty() {
+            TableInsert::Replaced
+        } else {
+            TableInsert::Inserted
+        })
+    }
+
+    pub fn update(&mut self, data: &mut Row) -> std::io::Result<TableInsert<Row::ID>> {
+        let (index, old) = self.find(data.id(), "update not found")?;
+        self.base_update(index, &old, data)
+    }
+
+    pub fn update_with(
+        &mut self,
+        id: &Row::ID,
+        update: impl FnOnce(&mut Row),
+    ) -> std::io::Result<TableInsert<Row::ID>> {
+        let (index, old) = self.find(id, "update not found")?;
+        let mut data = old.clone();
+        update(&mut data);
+        if data.id_cmp(id) != Some(Ordering::Equal) {
+            return Ok(TableInsert::IdChanged);
         }
+        self.base_update(index, &old, &mut data)
     }
 
+    fn base_update(
+        &mut self,
+        index: usize,
+        old: &Row,
+        data: &mut Row,
+    ) -> std::io::Result<TableInsert<Row::ID>> {
+        if let Some(column) = self.missing_parent(data)? {
+            return Ok(TableInsert::MissingReference(column));
+        }
+        for index_file in &mut self.other_index {
+            if let Some(id) = index_file.check_unique_update(old, data)? {
+                return Ok(TableInsert::DuplicateUnique(index_file.name(), id));
+            }
+        }
+
+        let bin = data.as_bin(self.bin.path())?;
+        self.logged(&[WalOp::Update(bin.clone())], |table| {
+            reference_dyn(data, table.bin.path())?;
+            table.base_set(index, &bin, old, data)
+        })?;
+        Ok(TableInsert::Updated)
+    }
+
+    fn base_set(
+        &mut self,
+        index: usize,
+        bin: &[u8],
+        old: &Row,
+        data: &mut Row,
+    ) -> std::io::Result<()> {
+        self.bin.set_bin(index, bin)?;
+        for index_file in &mut self.other_index {
+            index_file.update(old, data)?;
+        }
+        old.delete_replaced(data, self.bin.path())
+    }
+
+    /// The indexes of the rows whose id is in `range`.
+    fn positions(&self, range: &impl RangeBounds<Row::ID>) -> io::Result<Range<usize>> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.bin.partition_point_by_id(&|id| id < start)?,
+            Bound::Excluded(start) => self.bin.partition_point_by_id(&|id| id <= start)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.bin.partition_point_by_id(&|id| id <= end)?,
+            Bound::Excluded(end) => self.bin.partition_point_by_id(&|id| id < end)?,
+            Bound::Unbounded => self.bin.len()?,
+        };
+        Ok(start..end.max(start))
+    }
+
+    /// `Ok` with the index and the row of `id` or `Err` with the index where it would be inserted.
+    fn lookup(&self, id: &Row::ID) -> io::Result<Result<(usize, Row), usize>> {
+        let index = self.bin.partition_point_by_id(&|row| row < id)?;
+        if index == self.bin.len()? {
+            return Ok(Err(index));
+        }
+        let row = self.bin.get(index)?;
+        Ok(match row.id_cmp(id) {
+            Some(Ordering::Equal) => Ok((index, row)),
+            _ => Err(index),
+        })
+    }
+
+    /// `Ok` with the index of the row or `Err` with the index where it would be inserted.
+    pub(crate) fn search(&self, id: &Row::ID) -> std::io::Result<Result<usize, usize>> {
+        Ok(self.lookup(id)?.map(|(index, _)| index))
+    }
+
+    fn find(&self, id: &Row::ID, not_found: &str) -> std::io::Result<(usize, Row)> {
+        self.lookup(id)?.map_err(|_| Error::other(not_found))
+    }
+
+    /// Removes the row without checking the tables referencing it.
+    pub(crate) fn remove_row(&mut self, id: &Row::ID) -> std::io::Result<()> {
+        let (index, _) = self.find(id, "remove not found")?;
+        let op = WalOp::Remove(id.clone().as_bin(self.bin.path())?);
+        self.logged(&[op], |table| table.base_remove(index))
+    }
+
+    fn base_remove(&mut self, index: usize) -> std::io::Result<()> {
+        let row = self.bin.get(index)?;
+        for index_file in &mut self.other_index {
+            index_file.remove(&row)?;
+        }
+        self.bin.remove(index, Some(1))
+    }
+
+    /// Fails if a row is referenced by a table added with `add_references`.
     pub fn clear(&mut self) -> io::Result<()> {
+        for relation in self.relations.borrow().iter() {
+            if let Some(column) = relation.referenced()? {
+                return Err(Error::other(format!(
+                    "a row is still referenced by {column}"
+                )));
+            }
+        }
+        self.logged(&[WalOp::Clear], Self::base_clear)
+    }
+
+    /// The first column of `data` referencing a row missing from a table added with `add_references`.
+    fn missing_parent(&self, data: &Row) -> io::Result<Option<&'static str>> {
+        for relation in self.relations.borrow().iter() {
+            if let Some(column) = relation.missing_parent(data, &|_, _| None)? {
+                return Ok(Some(column));
+            }
+        }
+        Ok(None)
+    }
+
+    fn check_unreferenced(&self, id: &Row::ID) -> io::Result<()> {
+        for relation in self.relations.borrow().iter() {
+            if let Some(column) = relation.referenced_by(id, &|_, _| None)? {
+                return Err(Error::other(format!(
+                    "the row is still referenced by {column}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn base_clear(&mut self) -> io::Result<()> {
         self.bin.clear()?;
-        remove_dir_all(self.bin.path().dyn_path())?;
-        create_dir(self.bin.path().dyn_path())?;
+        clear_dyn(self.bin.path())?;
         for index_file in &mut self.other_index {
             index_file.clear()?;
         }
@@ -141,7 +591,7 @@ impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTableFile<Row, BinFile> {
     /// It is used by the Table macro.
     pub unsafe fn get_index_file<
         ColType: Binary + PartialOrd,
-        BinFile2: BaseBinFile<IndexRow<ColType>>,
+        BinFile2: BaseBinFile<IndexRow<ColType, Row::ID>>,
     >(
         &self,
         index: usize,
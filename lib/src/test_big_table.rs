@@ -17,11 +17,12 @@ pub fn test_index() {
     let mut table = CachedTableFile::new(PATH.to_owned()).expect("failed to create table_clients");
 
     for i in 0..300 {
-        while !table
+        while table
             .insert(&mut A {
                 id: rand::random::<u64>() as usize,
             })
             .expect("filled")
+            != TableInsert::Inserted
         {}
         println!("inserted {i}");
     }
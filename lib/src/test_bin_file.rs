@@ -144,3 +144,55 @@ pub fn base_test2<BinFile: BaseBinFile<DynanicBinary<u8>>>(
     assert_eq!(0, table.len().expect("failed len"));
     assert_eq!(0, nb_dyns(&path).expect("nb_dyns"));
 }
+
+#[test]
+pub fn test_iter() {
+    base_test_iter(BDPath::new_main_str("test/iter"), |path| {
+        BinFile::new(path).expect("failed to new")
+    });
+}
+
+pub fn base_test_iter<BinFile: BaseBinFile<u64>>(path: BDPath, new: impl Fn(BDPath) -> BinFile) {
+    let mut table = new(path);
+    table.clear().expect("failed to clear");
+    assert_eq!(0, table.iter().expect("iter").count());
+
+    let mut rows = (0..600).collect::<Vec<u64>>();
+    table.inserts(0, &mut rows).expect("failed inserts");
+
+    let mut iter = table.iter().expect("iter");
+    assert_eq!(600, iter.len());
+    assert_eq!(Some(0), iter.next().transpose().expect("next"));
+    assert_eq!(Some(599), iter.next_back().transpose().expect("next_back"));
+    assert_eq!(598, iter.len());
+    assert_eq!(
+        rows[1..599],
+        iter.collect::<std::io::Result<Vec<u64>>>()
+            .expect("collect")
+    );
+
+    assert_eq!(
+        rows.iter().rev().copied().collect::<Vec<u64>>(),
+        table
+            .iter()
+            .expect("iter")
+            .rev()
+            .collect::<std::io::Result<Vec<u64>>>()
+            .expect("collect")
+    );
+
+    let mut iter = table.iter_range(100..400);
+    let mut front = Vec::new();
+    let mut back = Vec::new();
+    while let Some(row) = iter.next() {
+        front.push(row.expect("next"));
+        if let Some(row) = iter.next_back() {
+            back.push(row.expect("next_back"));
+        }
+    }
+    back.reverse();
+    front.extend(back);
+    assert_eq!(rows[100..400], front);
+
+    assert!(table.iter_range(500..700).any(|row| row.is_err()));
+}
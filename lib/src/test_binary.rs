@@ -0,0 +1,302 @@
+use std::fs::{read_dir, read_to_string, remove_dir_all, write};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Binary)]
+pub enum Status {
+    Active,
+    Suspended(u16, bool),
+    Named {
+        nom: DynanicBinary<String>,
+        level: u8,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Binary)]
+pub enum Single {
+    Only(u8, DynanicBinary<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Account {
+    #[PrimaryKey]
+    pub id: u32,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Contact {
+    #[PrimaryKey]
+    pub id: (u16, FixedString<8>),
+    pub phone: Option<(u16, DynanicBinary<String>)>,
+    pub tags: [DynanicBinary<String>; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct LegacyInitial {
+    #[PrimaryKey]
+    pub id: u32,
+    pub initial: Latin1Char,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Initial {
+    #[PrimaryKey]
+    pub id: u32,
+    pub initial: char,
+}
+
+fn dyn_files(path: &str) -> usize {
+    read_dir(format!("{path}/dyn"))
+        .expect("failed to read dyn")
+        .count()
+}
+
+#[test]
+fn test_binary_enum() {
+    const PATH: &str = "test/binary_enum";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    assert_eq!(1 + 9, Status::bin_size());
+    assert!(Status::from_bin(&[3; 10], &BDPath::new_main(PATH.to_owned())).is_err());
+    assert_eq!(1 + 1 + 8, Single::bin_size());
+
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut accounts = [
+        Account {
+            id: 1,
+            status: Status::Active,
+        },
+        Account {
+            id: 2,
+            status: Status::Suspended(300, true),
+        },
+        Account {
+            id: 3,
+            status: Status::Named {
+                nom: DynanicBinary::new("vip".to_owned()),
+                level: 4,
+            },
+        },
+        Account {
+            id: 4,
+            status: Status::Named {
+                nom: DynanicBinary::new("staff".to_owned()),
+                level: 1,
+            },
+        },
+    ];
+    for account in &mut accounts {
+        table.insert(account).expect("failed to insert");
+    }
+    assert_eq!(accounts.to_vec(), table.get_all().expect("failed to read"));
+    assert_eq!(2, dyn_files(PATH));
+
+    accounts[2].status = Status::Active;
+    table.update(&mut accounts[2]).expect("failed to update");
+    assert_eq!(1, dyn_files(PATH));
+    if let Status::Named { level, .. } = &mut accounts[3].status {
+        *level = 2;
+    }
+    table.update(&mut accounts[3]).expect("failed to update");
+    assert_eq!(1, dyn_files(PATH));
+    table.remove(&4).expect("failed to remove");
+    assert_eq!(0, dyn_files(PATH));
+    assert_eq!(
+        accounts[..3].to_vec(),
+        table.get_all().expect("failed to read")
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+fn contact(id: u16, phone: Option<&str>) -> Contact {
+    Contact {
+        id: (id, FixedString::new(format!("c{id}")).expect("too long")),
+        phone: phone.map(|phone| (id, DynanicBinary::new(phone.to_owned()))),
+        tags: [
+            DynanicBinary::new(format!("tag {id}")),
+            DynanicBinary::new("shared".to_owned()),
+        ],
+    }
+}
+
+#[test]
+fn test_binary_types() {
+    const PATH: &str = "test/binary_types";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let path = BDPath::new_main(PATH.to_owned());
+    assert_eq!(1 + 8, FixedString::<8>::bin_size());
+    assert_eq!(2 + 300, FixedString::<300>::bin_size());
+    assert!(FixedString::<2>::new("abc").is_err());
+    assert!(FixedString::<4>::from_bin(&[5, 0, 0, 0, 0], &path).is_err());
+    assert!(FixedString::<4>::from_bin(&[2, 0xff, 0xfe, 0, 0], &path).is_err());
+    let mut text = FixedString::<5>::new("été").expect("too long");
+    let bin = text.as_bin(&path).expect("failed to convert");
+    assert_eq!(vec![5, 195, 169, 116, 195, 169], bin);
+    assert_eq!(
+        text,
+        FixedString::from_bin(&bin, &path).expect("failed to read")
+    );
+
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut contacts = [
+        contact(1, Some("0123")),
+        contact(2, None),
+        contact(3, Some("4567")),
+    ];
+    for contact in &mut contacts {
+        table.insert(contact).expect("failed to insert");
+    }
+    assert_eq!(contacts.to_vec(), table.get_all().expect("failed to read"));
+    assert_eq!(8, dyn_files(PATH));
+
+    contacts[0].phone = None;
+    table.update(&mut contacts[0]).expect("failed to update");
+    assert_eq!(7, dyn_files(PATH));
+    table.remove(&contacts[2].id).expect("failed to remove");
+    assert_eq!(4, dyn_files(PATH));
+    assert_eq!(
+        contacts[..2].to_vec(),
+        table.get_all().expect("failed to read")
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+#[test]
+fn test_binary_char() {
+    const PATH: &str = "test/binary_char";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let path = BDPath::new_main(PATH.to_owned());
+    for mut value in ['a', 'é', 'Œ', '🦀'] {
+        let bin = value.as_bin(&path).expect("failed to convert");
+        assert_eq!(value, char::from_bin(&bin, &path).expect("failed to read"));
+    }
+    assert!(char::from_bin(&0xd800u32.to_le_bytes(), &path).is_err());
+    assert!(char::from_bin(&0x110000u32.to_le_bytes(), &path).is_err());
+    assert!(Latin1Char::new('Œ').is_err());
+
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    for (id, initial) in [(1, 'A'), (2, 'é'), (3, 'ÿ')] {
+        table
+            .insert(&mut LegacyInitial {
+                id,
+                initial: Latin1Char::new(initial).expect("not latin-1"),
+            })
+            .expect("failed to insert");
+    }
+    drop(table);
+    assert!(TableFile::<Initial>::new(PATH.to_owned()).is_err());
+
+    let mut table =
+        TableFile::<Initial>::migrate::<TableFile<LegacyInitial>>(PATH.to_owned(), |old| Initial {
+            id: old.id,
+            initial: old.initial.into(),
+        })
+        .expect("failed to migrate");
+    table
+        .insert(&mut Initial {
+            id: 4,
+            initial: '🦀',
+        })
+        .expect("failed to insert");
+    assert_eq!(
+        vec!['A', 'é', 'ÿ', '🦀'],
+        table
+            .get_all()
+            .expect("failed to read")
+            .iter()
+            .map(|row| row.initial)
+            .collect::<Vec<char>>()
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+fn named(id: u32, nom: &str) -> Account {
+    Account {
+        id,
+        status: Status::Named {
+            nom: DynanicBinary::new(nom.to_owned()),
+            level: 0,
+        },
+    }
+}
+
+fn dyn_names(path: &str) -> Vec<String> {
+    let mut names = read_dir(format!("{path}/dyn"))
+        .expect("failed to read dyn")
+        .map(|entry| {
+            entry
+                .expect("failed to read dyn")
+                .file_name()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect::<Vec<String>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_dyn_ids() {
+    const PATHS: [&str; 2] = ["test/dyn_ids_a", "test/dyn_ids_b"];
+    for path in PATHS {
+        if Path::new(path).exists() {
+            remove_dir_all(path).expect("PATH already exists");
+        }
+        let mut table = TableFile::new(path.to_owned()).expect("failed to create the table");
+        for id in 1..=3 {
+            table
+                .insert(&mut named(id, &format!("account {id}")))
+                .expect("failed to insert");
+        }
+        table.remove(&2).expect("failed to remove");
+        table
+            .insert(&mut named(4, "account 4"))
+            .expect("failed to insert");
+    }
+    assert_eq!(vec!["1.bin", "3.bin", "4.bin"], dyn_names(PATHS[0]));
+    assert_eq!(dyn_names(PATHS[0]), dyn_names(PATHS[1]));
+    for name in dyn_names(PATHS[0]) {
+        assert_eq!(
+            read_to_string(format!("{}/dyn/{name}", PATHS[0])).expect("failed to read"),
+            read_to_string(format!("{}/dyn/{name}", PATHS[1])).expect("failed to read")
+        );
+    }
+
+    write(format!("{}/dyn/5.bin", PATHS[0]), "taken").expect("failed to write");
+    let mut table = TableFile::new(PATHS[0].to_owned()).expect("failed to open the table");
+    table
+        .insert(&mut named(5, "account 5"))
+        .expect("failed to insert");
+    assert_eq!(
+        "taken",
+        read_to_string(format!("{}/dyn/5.bin", PATHS[0])).expect("failed to read")
+    );
+    assert_eq!(
+        "7\n",
+        read_to_string(format!("{}/dyn.next", PATHS[0])).expect("failed to read")
+    );
+
+    let last = (1_usize << (usize::BITS - 3)) - 1;
+    write(format!("{}/dyn.next", PATHS[0]), format!("{last}\n")).expect("failed to write");
+    table
+        .insert(&mut named(6, "account 6"))
+        .expect("failed to insert");
+    assert!(Path::new(&format!("{}/dyn/{last}.bin", PATHS[0])).exists());
+    let error = table
+        .insert(&mut named(7, "account 7"))
+        .expect_err("inserted past the last id");
+    assert_eq!("no dynamic id left", error.to_string());
+
+    for path in PATHS {
+        remove_dir_all(path).expect("failed to remove the test table");
+    }
+}
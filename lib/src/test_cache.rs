@@ -76,3 +76,14 @@ pub fn test2() {
         CachedBinFile::new(path.clone()).expect("failed to new")
     });
 }
+
+#[test]
+pub fn test_cache_iter() {
+    let path = BDPath::new_main_str("test/test_cache_iter");
+    test_bin_file::base_test_iter(path.clone(), |path| {
+        CachedBinFile::new(path).expect("failed to new")
+    });
+    let table = CachedBinFile::<u64>::new(path).expect("failed to new");
+    assert_eq!(600, table.iter().expect("iter").count());
+    assert_eq!(0, table.cache_len());
+}
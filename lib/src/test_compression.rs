@@ -0,0 +1,179 @@
+use std::fs::{metadata, read_dir, read_to_string, remove_dir_all};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Table)]
+#[Compression(Lz4)]
+pub struct Article {
+    #[PrimaryKey]
+    pub id: u64,
+    pub title: FixedString<200>,
+    #[Index]
+    pub body: DynanicBinary<String>,
+    #[DynStorage(Heap)]
+    pub summary: DynanicBinary<String>,
+    #[DynStorage(Dedup)]
+    pub category: DynanicBinary<String>,
+    #[Compression(None)]
+    pub raw: DynanicBinary<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct PlainArticle {
+    #[PrimaryKey]
+    pub id: u64,
+    pub title: FixedString<200>,
+    #[Index]
+    pub body: DynanicBinary<String>,
+    #[DynStorage(Heap)]
+    pub summary: DynanicBinary<String>,
+    #[DynStorage(Dedup)]
+    pub category: DynanicBinary<String>,
+    pub raw: DynanicBinary<String>,
+}
+
+fn article(id: u64) -> Article {
+    Article {
+        id,
+        title: FixedString::new(format!("article {id}")).expect("too long"),
+        body: DynanicBinary::new(format!("{id} ").repeat(500)),
+        summary: DynanicBinary::new("summary ".repeat(50)),
+        category: DynanicBinary::new("news ".repeat(50)),
+        raw: DynanicBinary::new("raw ".repeat(50)),
+    }
+}
+
+fn plain(article: Article) -> PlainArticle {
+    PlainArticle {
+        id: article.id,
+        title: article.title,
+        body: article.body,
+        summary: article.summary,
+        category: article.category,
+        raw: article.raw,
+    }
+}
+
+fn dyn_sizes(path: &str) -> Vec<u64> {
+    let mut sizes = read_dir(format!("{path}/dyn"))
+        .expect("failed to read dyn")
+        .map(|entry| {
+            entry
+                .expect("failed to read dyn")
+                .metadata()
+                .expect("no metadata")
+        })
+        .map(|metadata| metadata.len())
+        .collect::<Vec<u64>>();
+    sizes.sort_unstable();
+    sizes
+}
+
+#[test]
+fn test_compression() {
+    const PATH: &str = "test/compression";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = PageTableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut articles = (0..200).map(article).collect::<Vec<Article>>();
+    for article in &mut articles {
+        table.insert(article).expect("failed to insert");
+    }
+    assert!(
+        metadata(format!("{PATH}/main.bin"))
+            .expect("no main.bin")
+            .len()
+            < 200 * Article::bin_size() as u64
+    );
+    let sizes = dyn_sizes(PATH);
+    assert_eq!(Some(&200), sizes.last());
+    assert_eq!(200, sizes.iter().filter(|size| **size == 200).count());
+    assert!(metadata(format!("{PATH}/heap.bin")).expect("no heap").len() < 4 * 4096);
+    let refs = articles[0].category.id().expect("no id");
+    assert_eq!(
+        "200\tcompressed\n",
+        read_to_string(format!("{PATH}/dyn/{refs}.refs")).expect("failed to read")
+    );
+    assert!(
+        read_to_string(format!("{PATH}/schema"))
+            .expect("failed to read")
+            .contains("compression\tlz4\n")
+    );
+    assert!(table.vacuum_dyn(true).expect("failed to vacuum").is_empty());
+    drop(table);
+
+    let table = PageTableFile::<Article>::new(PATH.to_owned()).expect("failed to open");
+    assert_eq!(articles, table.get_all().expect("failed to read"));
+    assert_eq!(
+        vec![articles[7].clone()],
+        table
+            .range_by_body(articles[7].body.clone()..=articles[7].body.clone(), 0, None)
+            .expect("failed to read")
+    );
+    drop(table);
+
+    let mut table =
+        PageTableFile::<PlainArticle>::new(PATH.to_owned()).expect("failed to open uncompressed");
+    let mut plains = articles
+        .into_iter()
+        .map(plain)
+        .collect::<Vec<PlainArticle>>();
+    assert_eq!(plains, table.get_all().expect("failed to read"));
+    let compressed = plains[3].body.id().expect("no id");
+    *plains[3].body.mut_data() = "plain".to_owned();
+    table.update(&mut plains[3]).expect("failed to update");
+    assert_ne!(Some(compressed), plains[3].body.id());
+    assert!(!Path::new(&format!("{PATH}/dyn/{compressed}.bin")).exists());
+    assert_eq!(
+        "plain",
+        read_to_string(format!(
+            "{PATH}/dyn/{}.bin",
+            plains[3].body.id().expect("no id")
+        ))
+        .expect("failed to read")
+    );
+    for plain in &mut plains[100..] {
+        plain.title = FixedString::new(String::new()).expect("too long");
+        table.update(plain).expect("failed to update");
+    }
+    assert_eq!(plains, table.get_all().expect("failed to read"));
+    table.clear().expect("failed to clear");
+    assert!(dyn_sizes(PATH).is_empty());
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+#[test]
+fn test_compressed_pages() {
+    let path = BDPath::new_main_str("test/compressed_pages").with_codec(Codec::Lz4);
+    let mut file = PageBinFile::<(u64, [u64; 60])>::new(path.clone()).expect("failed to new");
+    file.clear().expect("failed to clear");
+    let mut expected = Vec::new();
+    let mut seed: usize = 11;
+    for i in 0..1_000 {
+        seed = (seed * 1_103_515_245 + 12_345) % 2_147_483_648;
+        let index = seed % (expected.len() + 1);
+        let mut row = (i, [i % 7; 60]);
+        file.insert(index, &mut row).expect("failed to insert");
+        expected.insert(index, row);
+    }
+    let len = metadata(path.full()).expect("metadata").len();
+    assert!(len < 1_000 * 488 / 4);
+    assert_eq!(expected, file.gets(0, None).expect("gets"));
+
+    file.set(42, &mut (7, [u64::MAX; 60]))
+        .expect("failed to set");
+    expected[42] = (7, [u64::MAX; 60]);
+    for _ in 0..700 {
+        seed = (seed * 1_103_515_245 + 12_345) % 2_147_483_648;
+        let index = seed % expected.len();
+        file.remove(index, Some(1)).expect("failed to remove");
+        expected.remove(index);
+    }
+    let file =
+        PageBinFile::<(u64, [u64; 60])>::new(path.with_codec(Codec::None)).expect("failed to open");
+    assert_eq!(expected, file.gets(0, None).expect("gets"));
+
+    remove_dir_all(path.dir_path).expect("failed to remove the test file");
+}
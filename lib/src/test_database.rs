@@ -0,0 +1,246 @@
+use std::fs::{read_to_string, remove_dir_all, write};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Shop {
+    #[PrimaryKey]
+    pub id: u32,
+    #[Unique]
+    pub nom: DynanicBinary<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Order {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(Cascade)]
+    pub shop: Foreign<Shop>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Line {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(Cascade)]
+    pub order: Foreign<Order>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Refund {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(Restrict)]
+    pub line: Foreign<Line>,
+}
+
+fn open(path: &str) -> Database {
+    let mut database = Database::open(path.to_owned()).expect("failed to open the database");
+    database
+        .register::<TableFile<Shop>>("shops")
+        .expect("failed to register");
+    database
+        .register::<CachedTableFile<Order>>("orders")
+        .expect("failed to register");
+    database
+        .register::<TableFile<Line>>("lines")
+        .expect("failed to register");
+    database
+        .register::<TableFile<Refund>>("refunds")
+        .expect("failed to register");
+    database
+        .references::<CachedTableFile<Order>, TableFile<Shop>>()
+        .expect("failed to register the references");
+    database
+        .references::<TableFile<Line>, CachedTableFile<Order>>()
+        .expect("failed to register the references");
+    database
+        .references::<TableFile<Refund>, TableFile<Line>>()
+        .expect("failed to register the references");
+    database
+}
+
+fn catalog() -> Vec<CatalogEntry> {
+    vec![
+        CatalogEntry {
+            name: "shops".to_owned(),
+            row: std::any::type_name::<Shop>().to_owned(),
+            row_size: Shop::bin_size(),
+            version: 0,
+            indexes: vec!["nom".to_owned()],
+        },
+        CatalogEntry {
+            name: "orders".to_owned(),
+            row: std::any::type_name::<Order>().to_owned(),
+            row_size: Order::bin_size(),
+            version: 0,
+            indexes: vec!["shop".to_owned()],
+        },
+        CatalogEntry {
+            name: "lines".to_owned(),
+            row: std::any::type_name::<Line>().to_owned(),
+            row_size: Line::bin_size(),
+            version: 0,
+            indexes: vec!["order".to_owned()],
+        },
+        CatalogEntry {
+            name: "refunds".to_owned(),
+            row: std::any::type_name::<Refund>().to_owned(),
+            row_size: Refund::bin_size(),
+            version: 0,
+            indexes: vec!["line".to_owned()],
+        },
+    ]
+}
+
+#[test]
+#[expect(clippy::too_many_lines)]
+fn test_database() {
+    const PATH: &str = "test/database";
+    const BACKUP: &str = "test/database_backup";
+    for path in [PATH, BACKUP] {
+        if Path::new(path).exists() {
+            remove_dir_all(path).expect("failed to remove the test database");
+        }
+    }
+    let mut database = open(PATH);
+    assert!(Database::open(PATH.to_owned()).is_err());
+    assert!(database.register::<TableFile<Shop>>("shops2").is_err());
+    assert!(database.table::<TableFile<Order>>().is_err());
+
+    for id in 1..=2 {
+        database
+            .insert::<TableFile<Shop>>(&mut Shop {
+                id,
+                nom: DynanicBinary::new(format!("shop {id}")),
+            })
+            .expect("failed to insert");
+    }
+    assert_eq!(
+        TableInsert::MissingReference("shop"),
+        database
+            .insert::<CachedTableFile<Order>>(&mut Order {
+                id: 1,
+                shop: Foreign::new(3),
+            })
+            .expect("failed to insert")
+    );
+    for (id, shop) in [(1, 1), (2, 2), (3, 1)] {
+        assert_eq!(
+            TableInsert::Inserted,
+            database
+                .insert::<CachedTableFile<Order>>(&mut Order {
+                    id,
+                    shop: Foreign::new(shop),
+                })
+                .expect("failed to insert")
+        );
+    }
+
+    {
+        let mut transaction = database.transaction().expect("failed to start");
+        let shops = transaction.table_of::<Shop>().expect("shops are missing");
+        let orders = transaction.table_of::<Order>().expect("orders are missing");
+        transaction
+            .insert(
+                shops,
+                &mut Shop {
+                    id: 3,
+                    nom: DynanicBinary::new("shop 3".to_owned()),
+                },
+            )
+            .expect("failed to insert");
+        transaction
+            .insert(
+                orders,
+                &mut Order {
+                    id: 4,
+                    shop: Foreign::new(3),
+                },
+            )
+            .expect("failed to insert");
+        transaction.commit().expect("failed to commit");
+    }
+
+    for (id, order) in [(1, 1), (2, 3), (3, 2)] {
+        database
+            .insert::<TableFile<Line>>(&mut Line {
+                id,
+                order: Foreign::new(order),
+            })
+            .expect("failed to insert");
+    }
+    database
+        .insert::<TableFile<Refund>>(&mut Refund {
+            id: 1,
+            line: Foreign::new(2),
+        })
+        .expect("failed to insert");
+    assert!(database.remove::<TableFile<Shop>>(&1).is_err());
+    assert_eq!(
+        4,
+        database
+            .table::<CachedTableFile<Order>>()
+            .expect("orders")
+            .len()
+            .expect("failed to read")
+    );
+    database
+        .remove::<TableFile<Refund>>(&1)
+        .expect("failed to remove");
+
+    database
+        .remove::<TableFile<Shop>>(&1)
+        .expect("failed to remove");
+    let lines = database.table::<TableFile<Line>>().expect("lines");
+    assert_eq!(
+        vec![3],
+        lines
+            .iter()
+            .expect("failed to read")
+            .map(|line| line.expect("failed to read").id)
+            .collect::<Vec<u32>>()
+    );
+    let orders = database.table::<CachedTableFile<Order>>().expect("orders");
+    assert_eq!(
+        vec![2, 4],
+        orders
+            .iter()
+            .expect("failed to read")
+            .map(|order| order.expect("failed to read").id)
+            .collect::<Vec<u32>>()
+    );
+
+    database.backup(BACKUP).expect("failed to backup");
+    assert!(database.backup(BACKUP).is_err());
+    drop(database);
+
+    let backup = Database::open(BACKUP.to_owned()).expect("failed to open the backup");
+    assert_eq!(catalog(), backup.catalog());
+    drop(backup);
+    let catalog_path = format!("{BACKUP}/catalog");
+    let text = read_to_string(catalog_path.as_str()).expect("failed to read the catalog");
+    write(catalog_path.as_str(), text.replacen("\t0\t", "\t1\t", 1))
+        .expect("failed to write the catalog");
+    let error = Database::open(BACKUP.to_owned())
+        .err()
+        .expect("opened with another version");
+    assert!(
+        error
+            .to_string()
+            .contains("changed since it was registered")
+    );
+    write(catalog_path.as_str(), text).expect("failed to write the catalog");
+    let backup = open(BACKUP);
+    assert_eq!(
+        2,
+        backup
+            .table::<TableFile<Shop>>()
+            .expect("shops")
+            .len()
+            .expect("failed to read")
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test database");
+    remove_dir_all(BACKUP).expect("failed to remove the backup");
+}
@@ -0,0 +1,165 @@
+use std::fs::{read_dir, read_to_string, remove_dir_all, write};
+
+use crate::{
+    dedup,
+    prelude::*,
+    wal::{Wal, WalOp},
+};
+
+#[derive(Debug, Clone, PartialEq, Table)]
+#[DynStorage(Dedup)]
+pub struct Company {
+    #[PrimaryKey]
+    pub id: u32,
+    pub name: DynanicBinary<String>,
+    pub tags: DynanicBinary<Vec<u16>>,
+}
+
+fn company(id: u32, name: &str) -> Company {
+    Company {
+        id,
+        name: DynanicBinary::new(name.to_owned()),
+        tags: DynanicBinary::new(vec![1, 2]),
+    }
+}
+
+fn blobs(path: &str) -> usize {
+    read_dir(format!("{path}/dyn"))
+        .expect("failed to read dyn")
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .is_ok_and(|entry| entry.file_name().to_string_lossy().ends_with(".bin"))
+        })
+        .count()
+}
+
+#[test]
+fn test_dedup() {
+    const PATH: &str = "test/dedup";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let path = BDPath::new_main_str(PATH);
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut companies = vec![
+        company(1, "Acme"),
+        company(2, "Acme"),
+        company(3, "Other"),
+        company(4, "Acme"),
+    ];
+    for company in &mut companies {
+        table.insert(company).expect("failed to insert");
+    }
+    assert_eq!(3, blobs(PATH));
+    let acme = companies[0].name.id().expect("no id");
+    let other = companies[2].name.id().expect("no id");
+    assert_eq!(Some(acme), companies[1].name.id());
+    assert_eq!(3, dedup::refs(&path, acme).expect("failed to read"));
+    assert_eq!(
+        4,
+        dedup::refs(&path, companies[0].tags.id().expect("no id")).expect("failed to read")
+    );
+
+    *companies[0].name.mut_data() = "Other".to_owned();
+    table.update(&mut companies[0]).expect("failed to update");
+    assert_eq!(Some(other), companies[0].name.id());
+    assert_eq!(2, dedup::refs(&path, acme).expect("failed to read"));
+    assert_eq!(2, dedup::refs(&path, other).expect("failed to read"));
+    assert_eq!(companies, table.get_all().expect("failed to read"));
+
+    let mut cloned = Company {
+        id: 6,
+        ..companies[1].clone()
+    };
+    table.insert(&mut cloned).expect("failed to insert");
+    assert_eq!(Some(acme), cloned.name.id());
+    assert_eq!(3, dedup::refs(&path, acme).expect("failed to read"));
+    table.remove(&6).expect("failed to remove");
+    assert_eq!(2, dedup::refs(&path, acme).expect("failed to read"));
+
+    companies[2].name = DynanicBinary::new("Other".to_owned());
+    table.update(&mut companies[2]).expect("failed to update");
+    assert_eq!(Some(other), companies[2].name.id());
+    assert_eq!(2, dedup::refs(&path, other).expect("failed to read"));
+    table.update(&mut companies[2]).expect("failed to update");
+    assert_eq!(2, dedup::refs(&path, other).expect("failed to read"));
+
+    for id in [2, 4] {
+        table.remove(&id).expect("failed to remove");
+    }
+    assert_eq!(2, blobs(PATH));
+    assert!(!Path::new(&format!("{PATH}/dyn/{acme}.bin")).exists());
+
+    write(format!("{PATH}/dyn/{acme}.bin"), "not Acme").expect("failed to write");
+    let mut acme_again = company(5, "Acme");
+    table.insert(&mut acme_again).expect("failed to insert");
+    assert_ne!(Some(acme), acme_again.name.id());
+    let acme_again_id = acme_again.name.id().expect("no id");
+    assert_eq!(
+        "not Acme",
+        read_to_string(format!("{PATH}/dyn/{acme}.bin")).expect("failed to read")
+    );
+    assert_eq!(
+        vec![acme_again],
+        table.range_by_id(5..=5, 0, None).expect("failed to read")
+    );
+
+    dedup::set_refs(&path, other, 7).expect("failed to write");
+    let vacuum = table.vacuum_dyn(false).expect("failed to vacuum");
+    assert_eq!(vec![acme], vacuum.files);
+    assert_eq!(vec![other], vacuum.recounted);
+    assert_eq!(2, dedup::refs(&path, other).expect("failed to read"));
+
+    let mut acme_third = company(6, "Acme");
+    table.insert(&mut acme_third).expect("failed to insert");
+    assert_eq!(Some(acme_again_id), acme_third.name.id());
+    assert_eq!(
+        2,
+        dedup::refs(&path, acme_again_id).expect("failed to read")
+    );
+
+    for id in [1, 3, 5, 6] {
+        table.remove(&id).expect("failed to remove");
+    }
+    assert_eq!(0, blobs(PATH));
+    assert_eq!(0, read_dir(format!("{PATH}/dyn")).expect("dyn").count());
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+#[test]
+fn test_dedup_replay() {
+    const PATH: &str = "test/dedup_replay";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let path = BDPath::new_main_str(PATH);
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut companies = vec![company(1, "Acme"), company(2, "Acme")];
+    for company in &mut companies {
+        table.insert(company).expect("failed to insert");
+    }
+    drop(table);
+    let acme = companies[0].name.id().expect("no id");
+    let tags = companies[0].tags.id().expect("no id");
+
+    *companies[0].name.mut_data() = "New".to_owned();
+    let bin = companies[0].as_bin(&path).expect("failed to write");
+    Wal::new(&path)
+        .expect("failed to open the wal")
+        .write(&[WalOp::Update(bin)])
+        .expect("failed to write the wal");
+    let new = companies[0].name.id().expect("no id");
+    assert_eq!(0, dedup::refs(&path, new).expect("failed to read"));
+    assert_eq!(2, dedup::refs(&path, tags).expect("failed to read"));
+
+    let table = TableFile::<Company>::new(PATH.to_owned()).expect("failed to recover");
+    assert_eq!(companies, table.get_all().expect("failed to read"));
+    assert_eq!(1, dedup::refs(&path, acme).expect("failed to read"));
+    assert_eq!(1, dedup::refs(&path, new).expect("failed to read"));
+    assert_eq!(2, dedup::refs(&path, tags).expect("failed to read"));
+    assert_eq!(3, blobs(PATH));
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
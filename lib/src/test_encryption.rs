@@ -0,0 +1,207 @@
+use std::fs::{self, read_dir, remove_dir_all};
+
+use crate::{prelude::*, wal::WalOp};
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Account {
+    #[PrimaryKey]
+    pub id: u64,
+    #[Index]
+    pub owner: FixedString<50>,
+    pub note: DynanicBinary<String>,
+    #[DynStorage(Heap)]
+    pub summary: DynanicBinary<String>,
+    #[DynStorage(Dedup)]
+    pub bank: DynanicBinary<String>,
+}
+
+fn account(id: u64) -> Account {
+    Account {
+        id,
+        owner: FixedString::new(format!("holder {id:03}")).expect("too long"),
+        note: DynanicBinary::new(format!("secret note {id}")),
+        summary: DynanicBinary::new(format!("secret summary {id}")),
+        bank: DynanicBinary::new("secret bank".to_owned()),
+    }
+}
+
+/// Whether a file of the table, the `key` file aside, contains `text`.
+fn contains(dir: &str, text: &str) -> bool {
+    read_dir(dir).expect("failed to read").any(|entry| {
+        let path = entry.expect("failed to read").path();
+        if path.is_dir() {
+            contains(path.to_str().expect("not utf-8"), text)
+        } else {
+            fs::read(path)
+                .expect("failed to read")
+                .windows(text.len())
+                .any(|window| window == text.as_bytes())
+        }
+    })
+}
+
+#[test]
+#[expect(clippy::too_many_lines)]
+fn test_encryption() {
+    const PATH: &str = "test/encryption";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let key = EncryptionKey::random();
+    let mut table =
+        TableFile::new_encrypted(PATH.to_owned(), key).expect("failed to create the table");
+    let mut accounts = (0..20).map(account).collect::<Vec<Account>>();
+    for account in &mut accounts {
+        table.insert(account).expect("failed to insert");
+    }
+    *accounts[3].note.mut_data() = "secret changed".to_owned();
+    table.update(&mut accounts[3]).expect("failed to update");
+    table.remove(&19).expect("failed to remove");
+    accounts.pop();
+    let row = accounts[5]
+        .as_bin(table.bin.path())
+        .expect("failed to write");
+    let entry = table
+        .wal
+        .write(&[WalOp::Update(row)])
+        .expect("failed to log");
+    assert!(!contains(PATH, "secret"));
+    assert!(!contains(PATH, "holder"));
+    assert_eq!(accounts, table.get_all().expect("failed to read"));
+    drop(table);
+
+    let error = TableFile::<Account>::new(PATH.to_owned())
+        .err()
+        .expect("opened without the key");
+    assert!(error.to_string().contains("is encrypted"));
+    let error = TableFile::<Account>::new_encrypted(PATH.to_owned(), EncryptionKey::random())
+        .err()
+        .expect("opened with another key");
+    assert!(error.to_string().contains("another key"));
+
+    let table = TableFile::new_encrypted(PATH.to_owned(), key).expect("failed to open");
+    assert!(table.wal.entries().expect("failed to read").is_empty());
+    assert!(!Path::new(&format!("{PATH}/wal/{entry}.bin")).exists());
+    assert_eq!(accounts, table.get_all().expect("failed to read"));
+    assert_eq!(
+        vec![accounts[7].clone()],
+        table
+            .range_by_owner(
+                accounts[7].owner.clone()..=accounts[7].owner.clone(),
+                0,
+                None
+            )
+            .expect("failed to read")
+    );
+
+    let main = format!("{PATH}/main.bin");
+    let original = fs::read(main.as_str()).expect("failed to read");
+    let mut tampered = original.clone();
+    tampered[200] ^= 1;
+    fs::write(main.as_str(), tampered).expect("failed to write");
+    assert!(table.get_by_index(0).is_ok());
+    assert!(table.get_by_index(1).is_err());
+    assert!(table.get_all().is_err());
+    fs::write(main.as_str(), original).expect("failed to write");
+    let note = format!("{PATH}/dyn/{}.bin", accounts[0].note.id().expect("no id"));
+    let original = fs::read(note.as_str()).expect("failed to read");
+    let other = format!("{PATH}/dyn/{}.bin", accounts[1].note.id().expect("no id"));
+    fs::copy(other.as_str(), note.as_str()).expect("failed to copy");
+    assert!(table.get_by_index(0).is_err());
+    fs::write(note.as_str(), original).expect("failed to write");
+    let heap = format!("{PATH}/heap.bin");
+    let original = fs::read(heap.as_str()).expect("failed to read");
+    let record = |slot: usize| {
+        let at = 4096 + 12 + slot * 8;
+        let offset = u32::from_le_bytes(original[at..at + 4].try_into().expect("no offset"));
+        let len = u32::from_le_bytes(original[at + 4..at + 8].try_into().expect("no len"));
+        4096 + offset as usize..4096 + (offset + len) as usize
+    };
+    let mut swapped = original.clone();
+    swapped[record(0)].copy_from_slice(&original[record(1)]);
+    fs::write(heap.as_str(), swapped).expect("failed to write");
+    assert!(table.get_by_index(0).is_err());
+    fs::write(heap.as_str(), original).expect("failed to write");
+    let original = fs::read(main.as_str()).expect("failed to read");
+    let size = original.len() / accounts.len();
+    let mut swapped = original.clone();
+    swapped[..size].copy_from_slice(&original[size..2 * size]);
+    swapped[size..2 * size].copy_from_slice(&original[..size]);
+    fs::write(main.as_str(), swapped).expect("failed to write");
+    assert!(table.get_by_index(0).is_err());
+    assert!(TableFile::<Account>::new_encrypted(PATH.to_owned(), key).is_err());
+    fs::write(main.as_str(), &original[..original.len() - size]).expect("failed to write");
+    assert!(TableFile::<Account>::new_encrypted(PATH.to_owned(), key).is_err());
+    fs::write(main.as_str(), original).expect("failed to write");
+    assert!(TableFile::<Account>::new_encrypted(PATH.to_owned(), key).is_ok());
+
+    let key2 = EncryptionKey::random();
+    let mut table = table.rotate_key(Some(key2)).expect("failed to rotate");
+    assert_eq!(accounts, table.get_all().expect("failed to read"));
+    assert!(table.vacuum_dyn(true).expect("failed to vacuum").is_empty());
+    assert!(!contains(PATH, "secret"));
+    drop(table);
+    assert!(TableFile::<Account>::new_encrypted(PATH.to_owned(), key).is_err());
+
+    let table = TableFile::<Account>::new_encrypted(PATH.to_owned(), key2)
+        .expect("failed to open")
+        .rotate_key(None)
+        .expect("failed to rotate");
+    assert!(contains(PATH, "secret"));
+    assert!(!Path::new(&format!("{PATH}/key")).exists());
+    drop(table);
+    let error = TableFile::<Account>::new_encrypted(PATH.to_owned(), key)
+        .err()
+        .expect("opened a plaintext table with a key");
+    assert!(error.to_string().contains("rotate_key"));
+
+    let mut table = TableFile::<Account>::new(PATH.to_owned())
+        .expect("failed to open")
+        .rotate_key(Some(key))
+        .expect("failed to rotate");
+    assert!(!contains(PATH, "secret"));
+    assert!(table.vacuum_dyn(true).expect("failed to vacuum").is_empty());
+    drop(table);
+    let table = TableFile::<Account>::new_encrypted(PATH.to_owned(), key).expect("failed to open");
+    assert_eq!(accounts, table.get_all().expect("failed to read"));
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+#[test]
+fn test_encrypted_pages() {
+    const PATH: &str = "test/encrypted_pages";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let key = EncryptionKey::random();
+    let mut table =
+        PageTableFile::new_encrypted(PATH.to_owned(), key).expect("failed to create the table");
+    let mut accounts = (0..60).map(account).collect::<Vec<Account>>();
+    for account in &mut accounts {
+        table.insert(account).expect("failed to insert");
+    }
+    for id in (0..60).step_by(3) {
+        table.remove(&id).expect("failed to remove");
+    }
+    accounts.retain(|account| account.id % 3 != 0);
+    assert!(!contains(PATH, "secret"));
+    assert!(!contains(PATH, "holder"));
+    drop(table);
+
+    let table = PageTableFile::new_encrypted(PATH.to_owned(), key).expect("failed to open");
+    assert_eq!(accounts, table.get_all().expect("failed to read"));
+    let main = format!("{PATH}/main.bin");
+    let original = fs::read(main.as_str()).expect("failed to read");
+    let mut moved = original.clone();
+    moved.copy_within(8192..original.len() - 4096, 12288);
+    fs::write(main.as_str(), moved).expect("failed to write");
+    assert!(table.get_all().is_err());
+    fs::write(main.as_str(), original).expect("failed to write");
+
+    let table = table.rotate_key(None).expect("failed to rotate");
+    assert_eq!(accounts, table.get_all().expect("failed to read"));
+    assert!(contains(PATH, "holder"));
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
@@ -0,0 +1,478 @@
+use std::fs::{remove_dir_all, remove_file};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Company {
+    #[PrimaryKey]
+    pub id: u32,
+    pub nom: DynanicBinary<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Client {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(Cascade)]
+    pub company: Foreign<Company>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Employee {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(SetNull)]
+    pub company: Option<Foreign<Company>>,
+    #[References(SetNull)]
+    pub previous: Option<Foreign<Company>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Contract {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(Restrict)]
+    pub company: Foreign<Company>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Account {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(Cascade)]
+    pub client: Foreign<Client>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Payment {
+    #[PrimaryKey]
+    pub id: u32,
+    #[References(Restrict)]
+    pub account: Foreign<Account>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Transfer {
+    #[PrimaryKey]
+    pub id: u32,
+    pub from: Foreign<Company>,
+    pub to: Foreign<Company>,
+}
+
+fn open<Row: Table>(path: &str) -> TableFile<Row> {
+    if Path::new(path).exists() {
+        remove_dir_all(path).expect("failed to remove the test table");
+    }
+    TableFile::new(path.to_owned()).expect("failed to create the table")
+}
+
+fn get<Row: Table>(table: &TableFile<Row>, id: &Row::ID) -> Option<Row> {
+    match table.get_by_id(id) {
+        TableGet::Found(row) => Some(row),
+        TableGet::NotFound => None,
+        TableGet::InternalError(e) => panic!("{e}"),
+        TableGet::Err(e) => panic!("{e}"),
+    }
+}
+
+type Tables = (
+    TableFile<Company>,
+    TableFile<Client>,
+    TableFile<Employee>,
+    TableFile<Contract>,
+);
+
+fn tables(path: &str) -> Tables {
+    let mut companies = open::<Company>(&format!("{path}/companies"));
+    for id in 1..=3 {
+        companies
+            .insert(&mut Company {
+                id,
+                nom: DynanicBinary::new(format!("company {id}")),
+            })
+            .expect("failed to insert");
+    }
+    let clients = open::<Client>(&format!("{path}/clients"));
+    let employees = open::<Employee>(&format!("{path}/employees"));
+    let contracts = open::<Contract>(&format!("{path}/contracts"));
+    (companies, clients, employees, contracts)
+}
+
+#[test]
+fn test_foreign_insert() {
+    const PATH: &str = "test/foreign_insert";
+    let (companies, mut clients, mut employees, _) = tables(PATH);
+
+    let mut client = Client {
+        id: 1,
+        company: Foreign::new(4),
+    };
+    assert_eq!(
+        TableInsert::MissingReference("company"),
+        clients
+            .insert_referencing(&mut client, &companies)
+            .expect("failed to insert")
+    );
+    client.company = Foreign::new(1);
+    assert_eq!(
+        TableInsert::Inserted,
+        clients
+            .insert_referencing(&mut client, &companies)
+            .expect("failed to insert")
+    );
+
+    let mut employee = Employee {
+        id: 1,
+        company: Some(Foreign::new(1)),
+        previous: Some(Foreign::new(9)),
+    };
+    assert_eq!(
+        TableInsert::MissingReference("previous"),
+        employees
+            .insert_referencing(&mut employee, &companies)
+            .expect("failed to insert")
+    );
+    employee.previous = None;
+    assert_eq!(
+        TableInsert::Inserted,
+        employees
+            .insert_referencing(&mut employee, &companies)
+            .expect("failed to insert")
+    );
+    employee.previous = Some(Foreign::new(9));
+    assert_eq!(
+        TableInsert::MissingReference("previous"),
+        employees
+            .update_referencing(&mut employee, &companies)
+            .expect("failed to update")
+    );
+    assert_eq!(
+        Some(Employee {
+            id: 1,
+            company: Some(Foreign::new(1)),
+            previous: None,
+        }),
+        get(&employees, &1)
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test tables");
+}
+
+#[test]
+fn test_foreign_remove() {
+    const PATH: &str = "test/foreign_remove";
+    let (mut companies, mut clients, mut employees, mut contracts) = tables(PATH);
+    for (id, company) in [(1, 1), (2, 2)] {
+        clients
+            .insert(&mut Client {
+                id,
+                company: Foreign::new(company),
+            })
+            .expect("failed to insert");
+    }
+    for (id, company, previous) in [(1, 1, None), (2, 2, Some(1))] {
+        employees
+            .insert(&mut Employee {
+                id,
+                company: Some(Foreign::new(company)),
+                previous: previous.map(Foreign::new),
+            })
+            .expect("failed to insert");
+    }
+    contracts
+        .insert(&mut Contract {
+            id: 1,
+            company: Foreign::new(3),
+        })
+        .expect("failed to insert");
+
+    assert!(
+        companies
+            .remove_referenced(&3, &mut [&mut clients, &mut employees, &mut contracts])
+            .is_err()
+    );
+    assert_eq!(3, companies.iter().expect("failed to read").len());
+
+    companies
+        .remove_referenced(&1, &mut [&mut clients, &mut employees, &mut contracts])
+        .expect("failed to remove");
+    assert_eq!(None, get(&companies, &1));
+    assert_eq!(None, get(&clients, &1));
+    assert_eq!(
+        Some(Client {
+            id: 2,
+            company: Foreign::new(2),
+        }),
+        get(&clients, &2)
+    );
+    assert_eq!(
+        Some(Employee {
+            id: 1,
+            company: None,
+            previous: None,
+        }),
+        get(&employees, &1)
+    );
+    assert_eq!(
+        Some(Employee {
+            id: 2,
+            company: Some(Foreign::new(2)),
+            previous: None,
+        }),
+        get(&employees, &2)
+    );
+
+    companies
+        .remove_referenced(&2, &mut [&mut clients, &mut employees])
+        .expect("failed to remove");
+    assert_eq!(0, clients.iter().expect("failed to read").len());
+
+    remove_dir_all(PATH).expect("failed to remove the test tables");
+}
+
+#[test]
+fn test_foreign_add_references() {
+    const PATH: &str = "test/foreign_add_references";
+    let (mut companies, mut clients, _, mut contracts) = tables(PATH);
+    clients.add_references(&mut companies);
+    contracts.add_references(&mut companies);
+
+    let mut client = Client {
+        id: 1,
+        company: Foreign::new(4),
+    };
+    assert_eq!(
+        TableInsert::MissingReference("company"),
+        clients.insert(&mut client).expect("failed to insert")
+    );
+    assert_eq!(
+        TableInsert::MissingReference("company"),
+        clients
+            .insert_or_replace(&mut client)
+            .expect("failed to insert")
+    );
+    client.company = Foreign::new(1);
+    assert_eq!(
+        TableInsert::Inserted,
+        clients.insert(&mut client).expect("failed to insert")
+    );
+    assert_eq!(
+        TableInsert::MissingReference("company"),
+        clients
+            .update_with(&1, |client| client.company = Foreign::new(9))
+            .expect("failed to update")
+    );
+    contracts
+        .insert(&mut Contract {
+            id: 1,
+            company: Foreign::new(3),
+        })
+        .expect("failed to insert");
+
+    assert!(companies.remove(&3).is_err());
+    assert!(companies.clear().is_err());
+    assert_eq!(3, companies.len().expect("failed to read"));
+    companies.remove(&2).expect("failed to remove");
+    companies.remove(&1).expect("failed to remove");
+    assert_eq!(None, get(&clients, &1));
+    assert_eq!(1, companies.len().expect("failed to read"));
+    contracts.remove(&1).expect("failed to remove");
+    companies.clear().expect("failed to clear");
+
+    remove_dir_all(PATH).expect("failed to remove the test tables");
+}
+
+#[test]
+fn test_foreign_cascade_chain() {
+    const PATH: &str = "test/foreign_cascade_chain";
+    let (mut companies, mut clients, _, _) = tables(PATH);
+    let mut accounts = open::<Account>(&format!("{PATH}/accounts"));
+    let mut payments = open::<Payment>(&format!("{PATH}/payments"));
+    clients.add_references(&mut companies);
+    accounts.add_references(&mut clients);
+    payments.add_references(&mut accounts);
+    for (id, company) in [(1, 1), (2, 2)] {
+        clients
+            .insert(&mut Client {
+                id,
+                company: Foreign::new(company),
+            })
+            .expect("failed to insert");
+    }
+    for (id, client) in [(1, 1), (2, 1), (3, 2)] {
+        accounts
+            .insert(&mut Account {
+                id,
+                client: Foreign::new(client),
+            })
+            .expect("failed to insert");
+    }
+    payments
+        .insert(&mut Payment {
+            id: 1,
+            account: Foreign::new(3),
+        })
+        .expect("failed to insert");
+
+    assert!(companies.remove(&2).is_err());
+    assert!(get(&companies, &2).is_some());
+    assert!(get(&clients, &2).is_some());
+    assert!(get(&accounts, &3).is_some());
+
+    companies.remove(&1).expect("failed to remove");
+    assert_eq!(None, get(&clients, &1));
+    assert_eq!(
+        vec![3],
+        ids(&accounts
+            .iter()
+            .expect("failed to read")
+            .collect::<io::Result<Vec<_>>>()
+            .expect("failed to read"))
+    );
+
+    payments.remove(&1).expect("failed to remove");
+    companies
+        .remove_referenced(&2, &mut [])
+        .expect("failed to remove");
+    assert_eq!(0, clients.len().expect("failed to read"));
+    assert_eq!(0, accounts.len().expect("failed to read"));
+    assert_eq!(1, companies.len().expect("failed to read"));
+
+    remove_dir_all(PATH).expect("failed to remove the test tables");
+}
+
+#[test]
+fn test_foreign_transaction() {
+    const PATH: &str = "test/foreign_transaction";
+    let (mut companies, mut clients, _, _) = tables(PATH);
+    clients.add_references(&mut companies);
+    clients
+        .insert(&mut Client {
+            id: 1,
+            company: Foreign::new(1),
+        })
+        .expect("failed to insert");
+
+    let mut transaction =
+        Transaction::new(format!("{PATH}/transaction")).expect("failed to create");
+    let company_table = transaction.table(&mut companies);
+    let client_table = transaction.table(&mut clients);
+    let mut client = Client {
+        id: 2,
+        company: Foreign::new(4),
+    };
+    assert_eq!(
+        TableInsert::MissingReference("company"),
+        transaction
+            .insert(client_table, &mut client)
+            .expect("failed to insert")
+    );
+    let mut company = Company {
+        id: 4,
+        nom: DynanicBinary::new("company 4".to_owned()),
+    };
+    transaction
+        .insert(company_table, &mut company)
+        .expect("failed to insert");
+    assert_eq!(
+        TableInsert::Inserted,
+        transaction
+            .insert(client_table, &mut client)
+            .expect("failed to insert")
+    );
+    transaction
+        .remove(company_table, &1)
+        .expect("failed to remove");
+    assert!(transaction.commit().is_err());
+    assert_eq!(3, companies.len().expect("failed to read"));
+    assert_eq!(1, clients.len().expect("failed to read"));
+
+    let mut transaction =
+        Transaction::new(format!("{PATH}/transaction")).expect("failed to create");
+    let company_table = transaction.table(&mut companies);
+    let client_table = transaction.table(&mut clients);
+    transaction
+        .insert(company_table, &mut company)
+        .expect("failed to insert");
+    transaction
+        .insert(client_table, &mut client)
+        .expect("failed to insert");
+    transaction
+        .remove(client_table, &1)
+        .expect("failed to remove");
+    transaction
+        .remove(company_table, &1)
+        .expect("failed to remove");
+    transaction.commit().expect("failed to commit");
+    assert_eq!(None, get(&companies, &1));
+    assert_eq!(
+        vec![2],
+        ids(&clients
+            .iter()
+            .expect("failed to read")
+            .collect::<io::Result<Vec<_>>>()
+            .expect("failed to read"))
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test tables");
+}
+
+fn ids<Row: Table<ID = u32>>(rows: &[Row]) -> Vec<u32> {
+    rows.iter().map(Table::id).copied().collect()
+}
+
+#[test]
+fn test_foreign_children() {
+    const PATH: &str = "test/foreign_children";
+    let (companies, mut clients, _, _) = tables(PATH);
+    let mut transfers = open::<Transfer>(&format!("{PATH}/transfers"));
+    for (id, company) in [(1, 2), (2, 1), (3, 2)] {
+        clients
+            .insert(&mut Client {
+                id,
+                company: Foreign::new(company),
+            })
+            .expect("failed to insert");
+    }
+    for (id, from, to) in [(1, 1, 2), (2, 2, 1), (3, 1, 3)] {
+        transfers
+            .insert(&mut Transfer {
+                id,
+                from: Foreign::new(from),
+                to: Foreign::new(to),
+            })
+            .expect("failed to insert");
+    }
+    let company = get(&companies, &2).expect("company 2 is missing");
+
+    assert_eq!(
+        vec![1, 3],
+        ids(&companies
+            .clients_of(&company, &clients)
+            .expect("failed to read"))
+    );
+    assert_eq!(
+        vec![2],
+        ids(&companies
+            .transfers_of_from(&company, &transfers)
+            .expect("failed to read"))
+    );
+    assert_eq!(
+        vec![1],
+        ids(&companies
+            .transfers_of_to(&company, &transfers)
+            .expect("failed to read"))
+    );
+
+    drop(clients);
+    remove_file(format!("{PATH}/clients/index/company.bin")).expect("failed to remove the index");
+    let clients = TableFile::<Client>::new(format!("{PATH}/clients")).expect("failed to open");
+    assert_eq!(
+        vec![1, 3],
+        ids(&companies
+            .clients_of(&company, &clients)
+            .expect("failed to read"))
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test tables");
+}
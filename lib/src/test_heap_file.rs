@@ -0,0 +1,159 @@
+use std::fs::{metadata, read, read_dir, remove_dir_all, write};
+
+use crate::{heap_file, prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Table)]
+#[DynStorage(Heap)]
+pub struct Note {
+    #[PrimaryKey]
+    pub id: u32,
+    #[Index]
+    pub text: DynanicBinary<String>,
+    #[DynStorage(Files)]
+    pub attachment: DynanicBinary<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Tagged {
+    #[PrimaryKey]
+    pub id: u32,
+    #[DynStorage(Heap)]
+    pub tag: DynanicBinary<String>,
+    pub note: DynanicBinary<String>,
+}
+
+fn note(id: u32, text: String) -> Note {
+    Note {
+        id,
+        text: DynanicBinary::new(text),
+        attachment: DynanicBinary::new(vec![id as u8; 3]),
+    }
+}
+
+fn dyn_files(path: &str) -> usize {
+    read_dir(format!("{path}/dyn"))
+        .expect("failed to read dyn")
+        .count()
+}
+
+fn heap_len(path: &str) -> u64 {
+    metadata(format!("{path}/heap.bin"))
+        .expect("failed to read the heap file")
+        .len()
+}
+
+#[test]
+fn test_heap_table() {
+    const PATH: &str = "test/heap_table";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut notes = (0..300)
+        .map(|id| note(id, format!("note {id}")))
+        .collect::<Vec<Note>>();
+    notes.push(note(300, "big ".repeat(3000)));
+    for note in &mut notes {
+        table.insert(note).expect("failed to insert");
+    }
+    assert_eq!(notes.len(), dyn_files(PATH));
+    assert!(heap_len(PATH) < 8 * 4096);
+    assert_eq!(notes, table.get_all().expect("failed to read"));
+
+    *notes[5].text.mut_data() = "a longer note than before".to_owned();
+    *notes[6].text.mut_data() = "short".to_owned();
+    *notes[300].text.mut_data() = "not so big".to_owned();
+    for id in [5, 6, 300] {
+        table.update(&mut notes[id]).expect("failed to update");
+    }
+    for id in (0..300).step_by(2) {
+        table.remove(&id).expect("failed to remove");
+    }
+    notes.retain(|note| note.id % 2 == 1 || note.id == 300);
+    assert_eq!(notes.len(), dyn_files(PATH));
+    drop(table);
+
+    let table = TableFile::<Note>::new(PATH.to_owned()).expect("failed to open the table");
+    assert_eq!(notes, table.get_all().expect("failed to read"));
+    assert_eq!(
+        vec![5],
+        table
+            .range_by_text(
+                DynanicBinary::new("a".to_owned())..DynanicBinary::new("b".to_owned()),
+                0,
+                None
+            )
+            .expect("failed to read")
+            .iter()
+            .map(|note| note.id)
+            .collect::<Vec<u32>>()
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+#[test]
+fn test_heap_field() {
+    const PATH: &str = "test/heap_field";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut tagged = Tagged {
+        id: 1,
+        tag: DynanicBinary::new("tag".to_owned()),
+        note: DynanicBinary::new("note".to_owned()),
+    };
+    table.insert(&mut tagged).expect("failed to insert");
+    assert_eq!(1, dyn_files(PATH));
+    let len = heap_len(PATH);
+    table.remove(&1).expect("failed to remove");
+    for _ in 0..100 {
+        table.insert(&mut tagged.clone()).expect("failed to insert");
+        table.remove(&1).expect("failed to remove");
+    }
+    assert_eq!(len, heap_len(PATH));
+    assert_eq!(0, dyn_files(PATH));
+
+    table.insert(&mut tagged).expect("failed to insert");
+    table.clear().expect("failed to clear");
+    assert!(!Path::new(&format!("{PATH}/heap.bin")).exists());
+    assert_eq!(0, table.len().expect("failed to read"));
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+#[test]
+fn test_heap_journal() {
+    const PATH: &str = "test/heap_journal";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let path = BDPath::new_main_str(PATH);
+    let journal = format!("{PATH}/heap.bin.journal");
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    for id in 1..=2 {
+        table
+            .insert(&mut Tagged {
+                id,
+                tag: DynanicBinary::new(format!("tag {id}")),
+                note: DynanicBinary::new("note".to_owned()),
+            })
+            .expect("failed to insert");
+    }
+    let run = read(format!("{PATH}/heap.bin")).expect("failed to read the heap file")[4096..8192]
+        .to_vec();
+    table.remove(&2).expect("failed to remove");
+    assert!(!Path::new(&journal).exists());
+    assert_eq!(1, heap_file::records(&path).expect("failed to read").len());
+
+    write(&journal, [1_u64.to_le_bytes().to_vec(), run].concat()).expect("failed to write");
+    assert_eq!(2, heap_file::records(&path).expect("failed to read").len());
+    assert!(!Path::new(&journal).exists());
+    let TableGet::Found(tagged) = table.get_by_id(&1) else {
+        panic!("failed to read");
+    };
+    assert_eq!("tag 1", tagged.tag.data());
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
@@ -1,4 +1,4 @@
-use std::fs::remove_dir_all;
+use std::fs::{remove_dir_all, remove_file};
 
 use crate::prelude::*;
 
@@ -36,7 +36,8 @@ pub fn test_index() {
     let mut table_b =
         CachedTableFile::new(PATH_B.to_owned()).expect("failed to create table_clients");
 
-    assert!(
+    assert_eq!(
+        TableInsert::Inserted,
         table_a
             .insert(&mut A {
                 id: 1,
@@ -45,8 +46,9 @@ pub fn test_index() {
             .expect("OK")
     );
 
-    assert!(
-        !table_a
+    assert_eq!(
+        TableInsert::DuplicateUnique("rel", 1),
+        table_a
             .insert(&mut A {
                 id: 2,
                 rel: Foreign::new(1),
@@ -54,7 +56,8 @@ pub fn test_index() {
             .expect("OK")
     );
 
-    assert!(
+    assert_eq!(
+        TableInsert::Inserted,
         table_b
             .insert(&mut B {
                 id: 1,
@@ -63,7 +66,8 @@ pub fn test_index() {
             .expect("OK")
     );
 
-    assert!(
+    assert_eq!(
+        TableInsert::Inserted,
         table_b
             .insert(&mut B {
                 id: 2,
@@ -72,6 +76,33 @@ pub fn test_index() {
             .expect("allowed duplicate foreign")
     );
 
+    assert_eq!(
+        TableInsert::Inserted,
+        table_a
+            .insert(&mut A {
+                id: 2,
+                rel: Foreign::new(2),
+            })
+            .expect("OK")
+    );
+    assert_eq!(
+        TableInsert::DuplicateUnique("rel", 1),
+        table_a
+            .update_with(&2, |a| a.rel = Foreign::new(1))
+            .expect("unique on update")
+    );
+    assert_eq!(
+        TableInsert::Updated,
+        table_a
+            .update_with(&2, |a| a.rel = Foreign::new(3))
+            .expect("OK")
+    );
+    let TableGet::Found(Some(a2)) = table_a.get_by_rel(&Foreign::new(3)) else {
+        panic!("OK")
+    };
+    assert_eq!(2, a2.id);
+    table_a.remove(&2).expect("OK");
+
     assert_eq!(1, table_a.len().expect("OK"));
     assert_eq!(2, table_b.len().expect("OK"));
 
@@ -101,3 +132,220 @@ pub fn test_index() {
     };
     assert_eq!(2, bs.len());
 }
+
+#[test]
+pub fn test_index_replace() {
+    const PATH: &str = "test/index_replace";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = CachedTableFile::new(PATH.to_owned()).expect("failed to create table");
+    let a = |id, rel| A {
+        id,
+        rel: Foreign::new(rel),
+    };
+
+    assert_eq!(
+        TableInsert::Inserted,
+        table.insert(&mut a(1, 1)).expect("OK")
+    );
+    assert_eq!(
+        TableInsert::Inserted,
+        table.insert(&mut a(2, 2)).expect("OK")
+    );
+    assert_eq!(
+        TableInsert::DuplicateId,
+        table.insert(&mut a(1, 5)).expect("OK")
+    );
+    assert_eq!(
+        TableInsert::DuplicateUnique("rel", 2),
+        table.insert(&mut a(5, 2)).expect("OK")
+    );
+
+    assert_eq!(
+        TableInsert::Inserted,
+        table.upsert(&mut a(3, 3)).expect("OK")
+    );
+    assert_eq!(
+        TableInsert::DuplicateUnique("rel", 1),
+        table.upsert(&mut a(3, 1)).expect("OK")
+    );
+    assert_eq!(
+        TableInsert::Updated,
+        table.upsert(&mut a(3, 4)).expect("OK")
+    );
+    assert_eq!(3, table.len().expect("OK"));
+
+    assert_eq!(
+        TableInsert::Replaced,
+        table.insert_or_replace(&mut a(1, 2)).expect("OK")
+    );
+    assert_eq!(2, table.len().expect("OK"));
+    assert!(matches!(table.get_by_id(&2), TableGet::NotFound));
+    let TableGet::Found(Some(a1)) = table.get_by_rel(&Foreign::new(2)) else {
+        panic!("OK")
+    };
+    assert_eq!(1, a1.id);
+    let TableGet::Found(Some(a3)) = table.get_by_rel(&Foreign::new(4)) else {
+        panic!("OK")
+    };
+    assert_eq!(3, a3.id);
+
+    assert_eq!(
+        TableInsert::Inserted,
+        table.insert_or_replace(&mut a(5, 6)).expect("OK")
+    );
+    assert_eq!(3, table.len().expect("OK"));
+}
+
+#[derive(Clone, PartialEq, Table)]
+pub struct C {
+    #[PrimaryKey]
+    pub id: usize,
+    #[Index]
+    pub group: usize,
+}
+
+#[test]
+pub fn test_index_order() {
+    const PATH: &str = "test/index_order";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create table");
+
+    for id in (0..40).rev() {
+        assert_eq!(
+            TableInsert::Inserted,
+            table
+                .insert(&mut C {
+                    id,
+                    group: (id * 7) % 5,
+                })
+                .expect("insert")
+        );
+    }
+    table.remove(&12).expect("remove");
+    table
+        .update_with(&13, |c| c.group = 4)
+        .expect("update_with");
+
+    let index = BinFile::<IndexRow<usize, usize>>::new(BDPath::new_index(
+        PATH.to_owned(),
+        "group".to_owned(),
+    ))
+    .expect("index")
+    .gets(0, None)
+    .expect("gets");
+    assert!(index.is_sorted_by_key(|entry| (entry.data, entry.id)));
+
+    for group in 0..5 {
+        let TableGet::Found(found) = table.get_by_group(&group) else {
+            panic!("group {group} suppossed to be found")
+        };
+        let expected = (0..40)
+            .filter(|id| *id != 12 && (if *id == 13 { 4 } else { (id * 7) % 5 }) == group)
+            .collect::<Vec<usize>>();
+        assert_eq!(expected, found.iter().map(|c| c.id).collect::<Vec<usize>>());
+    }
+}
+
+#[test]
+pub fn test_index_old_layout() {
+    const PATH: &str = "test/index_old_layout";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create table");
+    for id in 0..20 {
+        table
+            .insert(&mut C {
+                id: id * 3,
+                group: id % 4,
+            })
+            .expect("insert");
+    }
+    drop(table);
+
+    // The entries held the index of the row instead of its id.
+    let path = BDPath::new_index(PATH.to_owned(), "group".to_owned());
+    let mut index = BinFile::<IndexRow<usize, usize>>::new(path).expect("index");
+    index.clear().expect("clear");
+    let mut entries = (0..20)
+        .map(|position| IndexRow {
+            data: position % 4,
+            id: position,
+        })
+        .collect::<Vec<IndexRow<usize, usize>>>();
+    entries.sort_by_key(|entry| (entry.data, entry.id));
+    index.inserts(0, &mut entries).expect("inserts");
+    remove_file(format!("{PATH}/index/layout")).expect("remove layout");
+
+    let table = TableFile::<C>::new(PATH.to_owned()).expect("failed to open");
+    let TableGet::Found(found) = table.get_by_group(&1) else {
+        panic!("group 1 suppossed to be found")
+    };
+    assert_eq!(
+        vec![3, 15, 27, 39, 51],
+        found.iter().map(|c| c.id).collect::<Vec<usize>>()
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+#[test]
+pub fn test_index_entries() {
+    const PATH: &str = "test/index_entries";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut index = IndexFile::<Foreign<A>, B>::new(
+        BDPath::new_index(PATH.to_owned(), "rel".to_owned()),
+        "rel",
+        Box::new(|row: &B| &row.rel),
+        false,
+    )
+    .expect("index");
+    let b = |id, rel| B {
+        id,
+        rel: Foreign::new(rel),
+    };
+
+    index.insert(&b(1, 1)).expect("insert");
+    let error = index.insert(&b(1, 1)).expect_err("duplicate entry");
+    assert_eq!(io::ErrorKind::AlreadyExists, error.kind());
+    assert_eq!(1, index.len().expect("len"));
+
+    index
+        .changes(&[
+            (None, Some(&b(3, 1))),
+            (None, Some(&b(2, 2))),
+            (Some(&b(1, 1)), Some(&b(1, 3))),
+        ])
+        .expect("changes");
+    let rows = |index: &IndexFile<Foreign<A>, B>| {
+        index
+            .range(&(..), 0, None)
+            .expect("range")
+            .iter()
+            .map(|row| (*row.data.id(), row.id))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec![(1, 3), (2, 2), (3, 1)], rows(&index));
+
+    let error = index
+        .changes(&[(None, Some(&b(4, 1))), (None, Some(&b(4, 1)))])
+        .expect_err("duplicate entry");
+    assert_eq!(io::ErrorKind::AlreadyExists, error.kind());
+    let error = index
+        .changes(&[(None, Some(&b(2, 2)))])
+        .expect_err("duplicate entry");
+    assert_eq!(io::ErrorKind::AlreadyExists, error.kind());
+    assert_eq!(vec![(1, 3), (2, 2), (3, 1)], rows(&index));
+
+    index
+        .changes(&[(Some(&b(2, 2)), None), (Some(&b(3, 1)), Some(&b(3, 1)))])
+        .expect("changes");
+    assert_eq!(vec![(1, 3), (3, 1)], rows(&index));
+    remove_dir_all(PATH).expect("remove");
+}
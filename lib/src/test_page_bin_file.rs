@@ -0,0 +1,186 @@
+use std::fs::{File, remove_dir_all};
+
+use crate::{prelude::*, test_bin_file};
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Big {
+    #[PrimaryKey]
+    id: u64,
+    data: [u64; 63],
+}
+
+fn big(id: u64) -> Big {
+    Big { id, data: [id; 63] }
+}
+
+#[test]
+pub fn test_page1() {
+    test_bin_file::base_test1(BDPath::new_main_str("test/page1"), |path| {
+        PageBinFile::new(path).expect("failed to new")
+    });
+}
+
+#[test]
+pub fn test_page2() {
+    test_bin_file::base_test2(BDPath::new_main_str("test/page2"), |path| {
+        PageBinFile::new(path).expect("failed to new")
+    });
+}
+
+#[test]
+pub fn test_page_tree() {
+    let path = BDPath::new_main_str("test/page_tree");
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to new");
+    file.clear().expect("failed to clear");
+    let mut expected: Vec<Big> = Vec::new();
+
+    let mut seed: usize = 7;
+    for i in 0..600 {
+        seed = (seed * 1_103_515_245 + 12_345) % 2_147_483_648;
+        let index = seed % (expected.len() + 1);
+        file.insert(index, &mut big(i)).expect("failed to insert");
+        expected.insert(index, big(i));
+    }
+    assert_eq!(expected.len(), file.len().expect("len"));
+    assert_eq!(expected, file.gets(0, None).expect("gets"));
+    assert_eq!(expected[123..200], file.gets(123, Some(77)).expect("gets"));
+    assert_eq!(expected[321], file.get(321).expect("get"));
+
+    file.set(42, &mut big(1_000)).expect("failed to set");
+    expected[42] = big(1_000);
+    file.remove(100, Some(150)).expect("failed to remove");
+    expected.drain(100..250);
+    for _ in 0..300 {
+        seed = (seed * 1_103_515_245 + 12_345) % 2_147_483_648;
+        let index = seed % expected.len();
+        file.remove(index, Some(1)).expect("failed to remove");
+        expected.remove(index);
+    }
+
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to open");
+    assert_eq!(expected, file.gets(0, None).expect("gets"));
+    file.remove(0, None).expect("failed to remove");
+    assert!(file.is_empty().expect("is_empty"));
+    assert!(file.get(0).is_err());
+}
+
+#[test]
+pub fn test_page_repair() {
+    let path = BDPath::new_main_str("test/page_repair");
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to new");
+    file.clear().expect("failed to clear");
+    file.inserts(0, &mut [big(1), big(2)]).expect("inserts");
+    let len = std::fs::metadata(path.full()).expect("metadata").len();
+
+    File::options()
+        .append(true)
+        .open(path.full())
+        .expect("open")
+        .set_len(len + 100)
+        .expect("set_len");
+    file.repair().expect("repair");
+    assert_eq!(len, std::fs::metadata(path.full()).expect("metadata").len());
+    assert_eq!(vec![big(1), big(2)], file.gets(0, None).expect("gets"));
+}
+
+#[test]
+pub fn test_page_remove_range() {
+    let path = BDPath::new_main_str("test/page_remove_range");
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to new");
+    file.clear().expect("failed to clear");
+    let mut expected = (0..600).map(big).collect::<Vec<Big>>();
+    file.inserts(0, &mut expected.clone()).expect("inserts");
+    let len = std::fs::metadata(path.full()).expect("metadata").len();
+
+    // Only the pages at both ends of the rows are rewritten.
+    file.remove(100, Some(400)).expect("failed to remove");
+    expected.drain(100..500);
+    assert!(std::fs::metadata(path.full()).expect("metadata").len() <= len + 8 * 4096);
+    assert_eq!(expected, file.gets(0, None).expect("gets"));
+    assert_eq!(expected[150], file.get(150).expect("get"));
+}
+
+#[test]
+pub fn test_page_torn_header() {
+    let path = BDPath::new_main_str("test/page_torn_header");
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to new");
+    file.clear().expect("failed to clear");
+    file.inserts(0, &mut [big(1), big(2)]).expect("inserts");
+    file.insert(2, &mut big(3)).expect("insert");
+
+    // Tears the header of the last commit, in the first page after the other.
+    let mut data = std::fs::read(path.full()).expect("read");
+    data[8] ^= 1;
+    std::fs::write(path.full(), data).expect("write");
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to open");
+    assert_eq!(vec![big(1), big(2)], file.gets(0, None).expect("gets"));
+    file.insert(2, &mut big(4)).expect("insert");
+    assert_eq!(
+        vec![big(1), big(2), big(4)],
+        file.gets(0, None).expect("gets")
+    );
+}
+
+#[test]
+pub fn test_page_free_uncommited() {
+    let path = BDPath::new_main_str("test/page_free_uncommited");
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to new");
+    file.clear().expect("failed to clear");
+    let mut expected = (0..100).map(big).collect::<Vec<Big>>();
+    file.inserts(0, &mut expected.clone()).expect("inserts");
+    file.remove(10, Some(40)).expect("failed to remove");
+    expected.drain(10..50);
+
+    // Rewrites the leaves in free pages, then fails before the commit.
+    assert!(file.remove(0, Some(expected.len() + 1)).is_err());
+    drop(file);
+
+    let mut file = PageBinFile::<Big>::new(path.clone()).expect("failed to open");
+    assert_eq!(expected, file.gets(0, None).expect("gets"));
+    for i in 100..200 {
+        file.insert(expected.len(), &mut big(i))
+            .expect("failed to insert");
+        expected.push(big(i));
+    }
+    assert_eq!(expected, file.gets(0, None).expect("gets"));
+}
+
+#[test]
+pub fn test_page_table() {
+    const PATH: &str = "test/page_table";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = PageTableFile::new(PATH.to_owned()).expect("failed to create table");
+
+    let mut ids = Vec::new();
+    while ids.len() < 300 {
+        let id = rand::random::<u64>();
+        if table.insert(&mut big(id)).expect("insert") == TableInsert::Inserted {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    assert_eq!(
+        ids,
+        table
+            .get_all()
+            .expect("get_all")
+            .iter()
+            .map(|row| row.id)
+            .collect::<Vec<u64>>()
+    );
+    for id in &ids[..150] {
+        table.remove(id).expect("remove");
+    }
+    assert!(matches!(table.get_by_id(&ids[0]), TableGet::NotFound));
+    assert!(matches!(table.get_by_id(&ids[200]), TableGet::Found(row) if row == big(ids[200])));
+    assert_eq!(150, table.len().expect("len"));
+}
+
+#[test]
+pub fn test_page_iter() {
+    test_bin_file::base_test_iter(BDPath::new_main_str("test/page_iter"), |path| {
+        PageBinFile::new(path).expect("failed to new")
+    });
+}
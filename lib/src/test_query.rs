@@ -0,0 +1,292 @@
+use std::fs::remove_dir_all;
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Table)]
+struct Player {
+    #[PrimaryKey]
+    id: u32,
+    #[Index]
+    team: u8,
+    score: i64,
+    nom: DynanicBinary<String>,
+}
+
+fn ids(players: Vec<Player>) -> Vec<u32> {
+    players.iter().map(|player| player.id).collect()
+}
+
+#[expect(clippy::too_many_lines)]
+#[test]
+pub fn test_query() {
+    const PATH: &str = "test/query";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create table");
+    for id in 0..20 {
+        table
+            .insert(&mut Player {
+                id,
+                team: (id % 4) as u8,
+                score: (i64::from(id) * 7) % 11,
+                nom: DynanicBinary::new(format!("player{id}")),
+            })
+            .expect("insert");
+    }
+
+    assert_eq!(
+        vec![5, 6, 7],
+        ids(table
+            .query()
+            .where_id(Op::Ge, 5)
+            .where_id(Op::Lt, 8)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![2, 6, 10, 14, 18],
+        ids(table.query().where_team(Op::Eq, 2).run().expect("run"))
+    );
+    assert_eq!(
+        vec![10, 18],
+        ids(table
+            .query()
+            .where_team(Op::Eq, 2)
+            .where_id(Op::Gt, 6)
+            .where_score(Op::Lt, 10)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![1, 2, 3],
+        ids(table
+            .query()
+            .where_team(Op::Ne, 0)
+            .limit(3)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![7, 11, 15, 19],
+        ids(table
+            .query()
+            .where_team(Op::Ge, 2)
+            .order_by_team_desc()
+            .offset(1)
+            .limit(4)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![2, 6, 10, 14, 18, 3],
+        ids(table
+            .query()
+            .where_team(Op::Ge, 2)
+            .order_by_team()
+            .limit(6)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![14, 3, 17, 6, 9, 19],
+        ids(table
+            .query()
+            .filter(|player| player.nom.data().ends_with('9') || player.score > 7)
+            .order_by(|a, b| b.score.cmp(&a.score).then(b.id.cmp(&a.id)))
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![5, 13, 1, 9, 17],
+        ids(table
+            .query()
+            .where_team(Op::Eq, 1)
+            .order_by_score()
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![17, 9],
+        ids(table
+            .query()
+            .where_team(Op::Eq, 1)
+            .order_by_score_desc()
+            .limit(2)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![1, 13, 17, 5, 9],
+        ids(table
+            .query()
+            .where_team(Op::Eq, 1)
+            .order_by_nom()
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![14, 18, 3, 7],
+        ids(table
+            .query()
+            .where_team(Op::Ge, 2)
+            .offset(3)
+            .limit(4)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![7, 8],
+        ids(table
+            .query()
+            .where_id(Op::Ge, 5)
+            .offset(2)
+            .limit(2)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![9, 13],
+        ids(table
+            .query()
+            .where_team(Op::Eq, 1)
+            .where_id(Op::Gt, 4)
+            .offset(1)
+            .limit(2)
+            .run()
+            .expect("run"))
+    );
+    assert_eq!(
+        vec![6, 10],
+        ids(table
+            .iter_by_team(2..=2, 1, Some(2))
+            .expect("iter")
+            .collect::<io::Result<Vec<_>>>()
+            .expect("iter"))
+    );
+    assert!(
+        table
+            .query()
+            .where_score(Op::Gt, 10)
+            .run()
+            .expect("run")
+            .is_empty()
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+struct Team {
+    #[PrimaryKey]
+    id: u8,
+    nom: DynanicBinary<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+struct Member {
+    #[PrimaryKey]
+    id: u32,
+    team: Foreign<Team>,
+    coach: Option<Foreign<Team>>,
+}
+
+type Joined = Vec<(Member, Option<Team>)>;
+
+fn joined_ids(rows: Joined) -> Vec<(u32, Option<u8>)> {
+    rows.iter()
+        .map(|(member, team)| (member.id, team.as_ref().map(|team| team.id)))
+        .collect()
+}
+
+#[test]
+pub fn test_join() {
+    const PATH: &str = "test/join";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut teams = TableFile::new(format!("{PATH}/teams")).expect("failed to create table");
+    for id in 0..100 {
+        teams
+            .insert(&mut Team {
+                id,
+                nom: DynanicBinary::new(format!("team{id}")),
+            })
+            .expect("insert");
+    }
+    let mut members = TableFile::new(format!("{PATH}/members")).expect("failed to create table");
+    for (id, team, coach) in [
+        (1, 2, None),
+        (2, 200, Some(5)),
+        (3, 0, Some(1)),
+        (4, 2, None),
+        (5, 7, Some(201)),
+    ] {
+        members
+            .insert(&mut Member {
+                id,
+                team: Foreign::new(team),
+                coach: coach.map(Foreign::new),
+            })
+            .expect("insert");
+    }
+
+    assert_eq!(
+        vec![(1, Some(2)), (3, Some(0)), (4, Some(2)), (5, Some(7))],
+        joined_ids(members.query().join_team(&teams).run().expect("run"))
+    );
+    assert_eq!(
+        vec![(3, Some(0)), (4, Some(2))],
+        joined_ids(
+            members
+                .query()
+                .offset(1)
+                .limit(2)
+                .join_team(&teams)
+                .run()
+                .expect("run")
+        )
+    );
+    assert_eq!(
+        vec![(1, None), (2, Some(5)), (3, Some(1)), (4, None), (5, None)],
+        joined_ids(members.query().left_join_coach(&teams).run().expect("run"))
+    );
+    assert_eq!(
+        vec![
+            (3, Some(0)),
+            (1, Some(2)),
+            (4, Some(2)),
+            (5, Some(7)),
+            (2, None)
+        ],
+        joined_ids(
+            members
+                .query()
+                .where_team(Op::Ge, Foreign::new(0))
+                .left_join_team(&teams)
+                .run()
+                .expect("run")
+        )
+    );
+
+    let sparse = [0, 99, 150];
+    assert_eq!(
+        vec![0, 99],
+        teams
+            .get_sorted(&sparse)
+            .expect("get")
+            .iter()
+            .map(|team| team.id)
+            .collect::<Vec<u8>>()
+    );
+    let dense: Vec<u8> = (10..60).step_by(2).chain([120]).collect();
+    assert_eq!(
+        dense[..25],
+        teams
+            .get_sorted(&dense)
+            .expect("get")
+            .iter()
+            .map(|team| team.id)
+            .collect::<Vec<u8>>()
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test tables");
+}
@@ -0,0 +1,207 @@
+use std::fs::{copy, read, read_dir, read_to_string, remove_dir_all};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct V1 {
+    #[PrimaryKey]
+    pub id: u32,
+    pub a: u16,
+    pub b: DynanicBinary<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct V1Qualified {
+    #[PrimaryKey]
+    pub id: u32,
+    pub a: core::primitive::u16,
+    pub b: crate::dyn_binary::DynanicBinary<std::string::String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct V2 {
+    #[PrimaryKey]
+    pub id: u32,
+    pub b: DynanicBinary<String>,
+    pub a: u32,
+    pub c: [u8; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct V1Indexed {
+    #[PrimaryKey]
+    pub id: u32,
+    #[Index]
+    pub a: u16,
+    pub b: DynanicBinary<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+#[Version(1)]
+pub struct V3 {
+    #[PrimaryKey]
+    pub id: u32,
+    #[Index]
+    pub a: u32,
+    pub c: [u8; 2],
+}
+
+#[test]
+fn test_schema() {
+    const PATH: &str = "test/schema";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::<V1>::new(PATH.to_owned()).expect("failed to create the table");
+    for id in 0..3 {
+        table
+            .insert(&mut V1 {
+                id,
+                a: 7 - id as u16,
+                b: DynanicBinary::new(format!("row {id}")),
+            })
+            .expect("failed to insert");
+    }
+    drop(table);
+    assert_eq!(
+        "version\t0\ncolumn\tid\tu32\t4\ncolumn\ta\tu16\t2\ncolumn\tb\tDynanicBinary<String>\t8\n",
+        read_to_string(format!("{PATH}/schema")).expect("failed to read the schema")
+    );
+
+    let Err(error) = TableFile::<V2>::new(PATH.to_owned()) else {
+        panic!("the table was opened with a different schema");
+    };
+    assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    assert_eq!(
+        format!(
+            "the schema of {PATH} changed: column a moved from position 1 to 2, \
+            column a changed type from u16 to u32, column a changed size from 2 to 4 bytes, \
+            column b moved from position 2 to 1, column c ([u8;2]) was added"
+        ),
+        error.to_string()
+    );
+
+    TableFile::<V1Qualified>::new(PATH.to_owned()).expect("failed to open with the paths");
+    let table = TableFile::<V1Indexed>::new(PATH.to_owned()).expect("failed to add the index");
+    assert_eq!(
+        vec![2, 1, 0],
+        table
+            .range_by_a(.., 0, None)
+            .expect("failed to read")
+            .iter()
+            .map(|row| row.id)
+            .collect::<Vec<u32>>()
+    );
+    assert_eq!(
+        Some(V1Indexed::schema()),
+        Schema::read(&format!("{PATH}/schema")).expect("failed to read the schema")
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
+
+fn dyn_files(path: &str) -> usize {
+    read_dir(format!("{path}/dyn"))
+        .expect("failed to read dyn")
+        .count()
+}
+
+/// The names and the contents of the files of `dyn/`, sorted by name.
+fn dyn_contents(path: &str) -> Vec<(String, Vec<u8>)> {
+    let mut contents = read_dir(format!("{path}/dyn"))
+        .expect("failed to read dyn")
+        .map(|entry| {
+            let entry = entry.expect("failed to read dyn");
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                read(entry.path()).expect("failed to read"),
+            )
+        })
+        .collect::<Vec<_>>();
+    contents.sort();
+    contents
+}
+
+#[test]
+fn test_migration() {
+    const PATH: &str = "test/migration";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::<V1>::new(PATH.to_owned()).expect("failed to create the table");
+    for id in 0..3 {
+        table
+            .insert(&mut V1 {
+                id,
+                a: 7 - id as u16,
+                b: DynanicBinary::new(format!("row {id}")),
+            })
+            .expect("failed to insert");
+    }
+    drop(table);
+    let contents = dyn_contents(PATH);
+
+    let table = TableFile::<V2>::migrate::<TableFile<V1>>(PATH.to_owned(), |old| V2 {
+        id: old.id,
+        b: old.b,
+        a: u32::from(old.a) * 10,
+        c: [old.id as u8, 1],
+    })
+    .expect("failed to migrate");
+    let rows = table.get_all().expect("failed to read");
+    assert_eq!(
+        vec![(0, 70, [0, 1]), (1, 60, [1, 1]), (2, 50, [2, 1])],
+        rows.iter()
+            .map(|row| (row.id, row.a, row.c))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec!["row 0", "row 1", "row 2"],
+        rows.iter()
+            .map(|row| row.b.data().as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(3, dyn_files(PATH));
+    assert_eq!(contents, dyn_contents(PATH));
+    drop(table);
+
+    let Err(error) = TableFile::<V3>::new(PATH.to_owned()) else {
+        panic!("the table was opened with a different version");
+    };
+    assert!(error.to_string().contains("version changed from 0 to 1"));
+    assert!(
+        TableFile::<V3>::migrate::<TableFile<V1>>(PATH.to_owned(), |_| unreachable!()).is_err()
+    );
+
+    let table = TableFile::<V3>::migrate::<TableFile<V2>>(PATH.to_owned(), |old| V3 {
+        id: old.id,
+        a: old.a,
+        c: old.c,
+    })
+    .expect("failed to migrate");
+    assert_eq!(0, dyn_files(PATH));
+    assert_eq!(
+        vec![2, 1],
+        table
+            .range_by_a(50..=60, 0, None)
+            .expect("failed to read")
+            .iter()
+            .map(|row| row.id)
+            .collect::<Vec<u32>>()
+    );
+    drop(table);
+    TableFile::<V3>::migrate::<TableFile<V2>>(PATH.to_owned(), |_| unreachable!())
+        .expect("failed to open the migrated table");
+
+    copy(
+        format!("{PATH}/main.bin"),
+        format!("{PATH}/main.migrate.bin"),
+    )
+    .expect("copy");
+    copy(format!("{PATH}/schema"), format!("{PATH}/schema.migrate")).expect("copy");
+    let table = TableFile::<V3>::new(PATH.to_owned()).expect("failed to finish the migration");
+    assert_eq!(3, table.len().expect("failed to read"));
+    assert!(!Path::new(&format!("{PATH}/schema.migrate")).exists());
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
@@ -71,7 +71,8 @@ pub fn test_table_get() {
     let mut table_clients =
         CachedTableFile::new(CLIENTS_PATH.to_owned()).expect("failed to create table_clients");
     for client in clients.iter_mut() {
-        assert!(
+        assert_eq!(
+            TableInsert::Inserted,
             table_clients
                 .insert(client)
                 .expect("failed to insert client")
@@ -84,7 +85,8 @@ pub fn test_table_get() {
     let mut table_entreprises = CachedTableFile::new(ENTREPRISES_PATH.to_owned())
         .expect("failed to create table_entreprises");
     for entreprise in entreprises.iter_mut() {
-        assert!(
+        assert_eq!(
+            TableInsert::Inserted,
             table_entreprises
                 .insert(entreprise)
                 .expect("failed to insert entreprises")
@@ -136,7 +138,8 @@ pub fn test_table_get() {
         TableGet::InternalError(error) => panic!("{error}"),
         TableGet::Err(error) => panic!("{error:?}"),
     }
-    assert!(
+    assert_eq!(
+        TableInsert::Inserted,
         table_clients
             .insert(&mut Client {
                 id: 4,
@@ -189,3 +192,227 @@ pub fn test_table_get() {
         }
     }
 }
+
+#[test]
+pub fn test_table_update() {
+    const PATH: &str = "test/test_tableUpdate";
+
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create table");
+    for (id, nom) in [(1, "Bob"), (2, "Fred")] {
+        assert_eq!(
+            TableInsert::Inserted,
+            table
+                .insert(&mut Client {
+                    id,
+                    nom: DynanicBinary::new(String::from(nom)),
+                    entreprise: Foreign::new(1),
+                })
+                .expect("insert")
+        );
+    }
+    let nb_dyns = || {
+        std::fs::read_dir(format!("{PATH}/dyn"))
+            .expect("dyn")
+            .count()
+    };
+    assert_eq!(2, nb_dyns());
+    let TableGet::Found(bob) = table.get_by_id(&1) else {
+        panic!("Bob suppossed to be found")
+    };
+
+    assert_eq!(
+        TableInsert::Updated,
+        table
+            .update_with(&1, |client| *client.nom.mut_data() = String::from("Bobby"))
+            .expect("update_with")
+    );
+    assert_eq!(2, nb_dyns());
+    let TableGet::Found(bobby) = table.get_by_id(&1) else {
+        panic!("Bobby suppossed to be found")
+    };
+    assert_ne!(bob.nom.id(), bobby.nom.id());
+    let TableGet::Found(clients) = table.get_by_nom(&DynanicBinary::new(String::from("Bobby")))
+    else {
+        panic!("Bobby suppossed to be found")
+    };
+    assert_eq!(1, clients.len());
+    assert_eq!(1, clients[0].id);
+    assert!(matches!(
+        table.get_by_nom(&DynanicBinary::new(String::from("Bob"))),
+        TableGet::NotFound
+    ));
+
+    let mut fred = Client {
+        id: 2,
+        nom: DynanicBinary::new(String::from("Freddy")),
+        entreprise: Foreign::new(2),
+    };
+    assert_eq!(
+        TableInsert::Updated,
+        table.update(&mut fred).expect("update")
+    );
+    assert_eq!(2, nb_dyns());
+    let TableGet::Found(found) = table.get_by_id(&2) else {
+        panic!("client 2 suppossed to be found")
+    };
+    assert_eq!(fred, found);
+    assert_eq!(2, *found.entreprise.id());
+
+    assert_eq!(
+        TableInsert::IdChanged,
+        table
+            .update_with(&2, |client| client.id = 3)
+            .expect("update_with")
+    );
+    assert!(matches!(table.get_by_id(&3), TableGet::NotFound));
+    assert!(
+        table
+            .update(&mut Client {
+                id: 3,
+                nom: DynanicBinary::new(String::from("Will")),
+                entreprise: Foreign::new(1),
+            })
+            .is_err()
+    );
+    assert_eq!(2, table.len().expect("len"));
+}
+
+#[test]
+pub fn test_table_range() {
+    const PATH: &str = "test/test_tableRange";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create table");
+    for (id, nom) in [
+        (5, "Bob"),
+        (1, "Alice"),
+        (3, "Bobby"),
+        (2, "Bernard"),
+        (4, "Bo"),
+        (6, "Carl"),
+    ] {
+        assert_eq!(
+            TableInsert::Inserted,
+            table
+                .insert(&mut Client {
+                    id,
+                    nom: DynanicBinary::new(String::from(nom)),
+                    entreprise: Foreign::new(1),
+                })
+                .expect("insert")
+        );
+    }
+    let ids = |clients: Vec<Client>| clients.iter().map(|c| c.id).collect::<Vec<usize>>();
+
+    assert_eq!(
+        vec![2, 3, 4],
+        ids(table.range_by_id(2..5, 0, None).expect("range"))
+    );
+    assert_eq!(
+        vec![3, 4, 5, 6],
+        ids(table.range_by_id(3.., 0, None).expect("range"))
+    );
+    assert_eq!(
+        vec![2, 3],
+        ids(table.range_by_id(..=5, 1, Some(2)).expect("range"))
+    );
+    assert!(table.range_by_id(7.., 0, None).expect("range").is_empty());
+    assert_eq!(
+        vec![5, 4, 3],
+        table
+            .iter_range(3..=5, 0, None)
+            .expect("iter_range")
+            .rev()
+            .map(|c| c.expect("row").id)
+            .collect::<Vec<usize>>()
+    );
+
+    let nom = |nom: &str| DynanicBinary::new(String::from(nom));
+    assert_eq!(
+        vec![2, 4, 5, 3],
+        ids(table
+            .range_by_nom(nom("B")..nom("C"), 0, None)
+            .expect("range"))
+    );
+    assert_eq!(
+        vec![5, 3, 6],
+        ids(table.range_by_nom(nom("Bo").., 1, None).expect("range"))
+    );
+    assert_eq!(
+        vec![4, 5, 3],
+        ids(table.prefix_by_nom("Bo", 0, None).expect("prefix"))
+    );
+    assert_eq!(
+        vec![5],
+        ids(table.prefix_by_nom("Bo", 1, Some(1)).expect("prefix"))
+    );
+    assert!(
+        table
+            .prefix_by_nom("D", 0, None)
+            .expect("prefix")
+            .is_empty()
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+struct Employe {
+    #[PrimaryKey]
+    id: usize,
+    #[Index]
+    age: u8,
+    #[Unique]
+    code: u32,
+    #[Index]
+    nom: DynanicBinary<String>,
+}
+
+#[test]
+pub fn test_table_range_indexes() {
+    const PATH: &str = "test/test_tableRangeIndexes";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create table");
+    for (id, age, code, nom) in [
+        (1, 40, 300, "Bob"),
+        (2, 25, 100, "Alice"),
+        (3, 33, 200, "Carl"),
+        (4, 25, 400, "Bo"),
+    ] {
+        assert_eq!(
+            TableInsert::Inserted,
+            table
+                .insert(&mut Employe {
+                    id,
+                    age,
+                    code,
+                    nom: DynanicBinary::new(String::from(nom)),
+                })
+                .expect("insert")
+        );
+    }
+    let ids = |employes: Vec<Employe>| employes.iter().map(|e| e.id).collect::<Vec<usize>>();
+
+    assert_eq!(
+        vec![2, 4, 3],
+        ids(table.range_by_age(20..35, 0, None).expect("range"))
+    );
+    assert_eq!(
+        vec![3, 1, 4],
+        ids(table.range_by_code(200.., 0, None).expect("range"))
+    );
+    assert_eq!(
+        vec![4, 1],
+        ids(table.prefix_by_nom("Bo", 0, None).expect("prefix"))
+    );
+    assert!(matches!(
+        table.get_by_code(&400),
+        TableGet::Found(Some(employe)) if employe.id == 4
+    ));
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
@@ -0,0 +1,275 @@
+use std::fs::{self, File, read_dir, remove_dir_all};
+
+use crate::prelude::*;
+use crate::wal::{Wal, WalOp};
+
+#[derive(Debug, Clone, PartialEq, Table)]
+struct Client {
+    #[PrimaryKey]
+    id: usize,
+    #[Unique]
+    nom: DynanicBinary<String>,
+    entreprise: Foreign<Entreprise>,
+}
+
+#[derive(Debug, Clone, PartialEq, Table)]
+struct Entreprise {
+    #[PrimaryKey]
+    id: usize,
+    nom: DynanicBinary<String>,
+}
+
+fn client(id: usize, nom: &str, entreprise: usize) -> Client {
+    Client {
+        id,
+        nom: DynanicBinary::new(String::from(nom)),
+        entreprise: Foreign::new(entreprise),
+    }
+}
+
+fn entreprise(id: usize, nom: &str) -> Entreprise {
+    Entreprise {
+        id,
+        nom: DynanicBinary::new(String::from(nom)),
+    }
+}
+
+fn new_tables(path: &str) -> (TableFile<Client>, TableFile<Entreprise>) {
+    if Path::new(path).exists() {
+        remove_dir_all(path).expect("path already exists");
+    }
+    let mut clients = TableFile::new(format!("{path}/clients")).expect("failed to create clients");
+    let mut entreprises =
+        TableFile::new(format!("{path}/entreprises")).expect("failed to create entreprises");
+    assert_eq!(
+        TableInsert::Inserted,
+        entreprises
+            .insert(&mut entreprise(1, "BigTech"))
+            .expect("insert")
+    );
+    assert_eq!(
+        TableInsert::Inserted,
+        clients.insert(&mut client(1, "Bob", 1)).expect("insert")
+    );
+    (clients, entreprises)
+}
+
+fn nb_dyns(path: &str) -> usize {
+    read_dir(format!("{path}/dyn")).expect("dyn").count()
+}
+
+fn nom<Row: Table + std::fmt::Debug>(
+    table: &TableFile<Row>,
+    id: &Row::ID,
+    nom: impl Fn(Row) -> String,
+) -> String {
+    match table.get_by_id(id) {
+        TableGet::Found(row) => nom(row),
+        e => panic!("get_by_id {e:?}"),
+    }
+}
+
+#[test]
+pub fn test_transaction_commit() {
+    const PATH: &str = "test/transaction_commit";
+    let (mut clients, mut entreprises) = new_tables(PATH);
+
+    let mut transaction = Transaction::new(format!("{PATH}/transaction")).expect("transaction");
+    let tx_clients = transaction.table(&mut clients);
+    let tx_entreprises = transaction.table(&mut entreprises);
+    assert_eq!(
+        TableInsert::Inserted,
+        transaction
+            .insert(tx_entreprises, &mut entreprise(2, "Mine"))
+            .expect("insert")
+    );
+    assert_eq!(
+        TableInsert::DuplicateId,
+        transaction
+            .insert(tx_entreprises, &mut entreprise(2, "Mine"))
+            .expect("insert")
+    );
+    assert_eq!(
+        TableInsert::Inserted,
+        transaction
+            .insert(tx_clients, &mut client(2, "Fred", 2))
+            .expect("insert")
+    );
+    assert_eq!(
+        TableInsert::DuplicateUnique("nom", 2),
+        transaction
+            .insert(tx_clients, &mut client(3, "Fred", 2))
+            .expect("insert")
+    );
+    assert_eq!(
+        TableInsert::Updated,
+        transaction
+            .update(tx_clients, &mut client(1, "Robert", 2))
+            .expect("update")
+    );
+    assert_eq!(
+        TableInsert::Inserted,
+        transaction
+            .insert(tx_clients, &mut client(3, "Bob", 1))
+            .expect("Bob is renamed in the transaction")
+    );
+    transaction.remove(tx_entreprises, &1).expect("remove");
+    transaction.commit().expect("commit");
+
+    assert_eq!(3, clients.len().expect("len"));
+    assert_eq!(1, entreprises.len().expect("len"));
+    assert_eq!("Robert", nom(&clients, &1, |c| c.nom.data().clone()));
+    assert_eq!("Fred", nom(&clients, &2, |c| c.nom.data().clone()));
+    assert_eq!("Bob", nom(&clients, &3, |c| c.nom.data().clone()));
+    for (nom, id) in [("Bob", 3), ("Fred", 2), ("Robert", 1)] {
+        let TableGet::Found(Some(client)) = clients.get_by_nom(&DynanicBinary::new(nom.to_owned()))
+        else {
+            panic!("get_by_nom {nom}")
+        };
+        assert_eq!(id, client.id);
+    }
+    assert_eq!("Mine", nom(&entreprises, &2, |e| e.nom.data().clone()));
+    assert!(matches!(entreprises.get_by_id(&1), TableGet::NotFound));
+    assert_eq!(3, nb_dyns(&format!("{PATH}/clients")));
+    assert_eq!(1, nb_dyns(&format!("{PATH}/entreprises")));
+    assert_eq!(
+        0,
+        read_dir(format!("{PATH}/transaction"))
+            .expect("transaction")
+            .count()
+    );
+}
+
+#[test]
+pub fn test_transaction_rollback() {
+    const PATH: &str = "test/transaction_rollback";
+    let (mut clients, mut entreprises) = new_tables(PATH);
+
+    let mut transaction = Transaction::new(format!("{PATH}/transaction")).expect("transaction");
+    let tx_clients = transaction.table(&mut clients);
+    let tx_entreprises = transaction.table(&mut entreprises);
+    transaction
+        .insert(tx_entreprises, &mut entreprise(2, "Mine"))
+        .expect("insert");
+    transaction
+        .update(tx_clients, &mut client(1, "Robert", 2))
+        .expect("update");
+    transaction.remove(tx_entreprises, &1).expect("remove");
+    transaction.rollback().expect("rollback");
+
+    assert_eq!("Bob", nom(&clients, &1, |c| c.nom.data().clone()));
+    assert_eq!("BigTech", nom(&entreprises, &1, |e| e.nom.data().clone()));
+    assert_eq!(1, entreprises.len().expect("len"));
+    assert_eq!(1, nb_dyns(&format!("{PATH}/clients")));
+    assert_eq!(1, nb_dyns(&format!("{PATH}/entreprises")));
+
+    {
+        let mut transaction = Transaction::new(format!("{PATH}/transaction")).expect("transaction");
+        let tx_clients = transaction.table(&mut clients);
+        transaction
+            .insert(tx_clients, &mut client(2, "Fred", 1))
+            .expect("insert");
+    }
+    assert_eq!(1, clients.len().expect("len"));
+    assert_eq!(1, nb_dyns(&format!("{PATH}/clients")));
+}
+
+#[test]
+pub fn test_transaction_recover() {
+    const PATH: &str = "test/transaction_recover";
+    let (clients, entreprises) = new_tables(PATH);
+    drop(entreprises);
+    drop(clients);
+
+    let clients_path = BDPath::new_main(format!("{PATH}/clients"));
+    let commit = format!("{PATH}/0.bin");
+    let mut wal = Wal::new(&clients_path).expect("wal");
+    wal.write(&[
+        WalOp::Transaction(Vec::from(commit.as_bytes())),
+        WalOp::Insert(client(2, "Fred", 1).as_bin(&clients_path).expect("as_bin")),
+    ])
+    .expect("write");
+    assert_eq!(2, nb_dyns(&clients_path.dir_path));
+
+    let clients = TableFile::<Client>::new(clients_path.dir_path.clone()).expect("recover");
+    assert_eq!(1, clients.len().expect("not commited"));
+    assert_eq!(1, nb_dyns(&clients_path.dir_path));
+    drop(clients);
+
+    wal.write(&[
+        WalOp::Transaction(Vec::from(commit.as_bytes())),
+        WalOp::Insert(client(2, "Fred", 1).as_bin(&clients_path).expect("as_bin")),
+    ])
+    .expect("write");
+    File::create(commit.as_str()).expect("commit");
+    let clients = TableFile::<Client>::new(clients_path.dir_path.clone()).expect("recover");
+    assert_eq!(2, clients.len().expect("commited"));
+    assert_eq!("Fred", nom(&clients, &2, |c| c.nom.data().clone()));
+    assert!(!Path::new(commit.as_str()).exists());
+}
+
+#[test]
+pub fn test_transaction_release_commit() {
+    const PATH: &str = "test/transaction_release_commit";
+    let (clients, entreprises) = new_tables(PATH);
+    drop(entreprises);
+    drop(clients);
+
+    let clients_path = BDPath::new_main(format!("{PATH}/clients"));
+    let entreprises_path = BDPath::new_main(format!("{PATH}/entreprises"));
+    let commit = format!("{PATH}/commit.bin");
+    let mut clients_wal = Wal::new(&clients_path).expect("wal");
+    let clients_entry = clients_wal
+        .write(&[
+            WalOp::Transaction(Vec::from(commit.as_bytes())),
+            WalOp::Insert(client(2, "Fred", 2).as_bin(&clients_path).expect("as_bin")),
+        ])
+        .expect("write");
+    let mut entreprises_wal = Wal::new(&entreprises_path).expect("wal");
+    let entreprises_entry = entreprises_wal
+        .write(&[
+            WalOp::Transaction(Vec::from(commit.as_bytes())),
+            WalOp::Insert(
+                entreprise(2, "SmallTech")
+                    .as_bin(&entreprises_path)
+                    .expect("as_bin"),
+            ),
+        ])
+        .expect("write");
+    fs::write(
+        commit.as_str(),
+        format!(
+            "{}\n{}",
+            clients_wal.entry_path(clients_entry),
+            entreprises_wal.entry_path(entreprises_entry)
+        ),
+    )
+    .expect("commit");
+
+    let mut clients = TableFile::<Client>::new(clients_path.dir_path.clone()).expect("recover");
+    assert_eq!(2, clients.len().expect("commited"));
+    assert!(Path::new(commit.as_str()).exists());
+    let mut entreprises =
+        TableFile::<Entreprise>::new(entreprises_path.dir_path.clone()).expect("recover");
+    assert_eq!(2, entreprises.len().expect("commited"));
+    assert!(!Path::new(commit.as_str()).exists());
+
+    let mut transaction = Transaction::new(format!("{PATH}/transaction")).expect("transaction");
+    let tx_clients = transaction.table(&mut clients);
+    let tx_entreprises = transaction.table(&mut entreprises);
+    transaction
+        .insert(tx_entreprises, &mut entreprise(3, "MidTech"))
+        .expect("insert");
+    transaction
+        .insert(tx_clients, &mut client(3, "Anna", 3))
+        .expect("insert");
+    transaction.commit().expect("commit");
+    assert_eq!(
+        0,
+        read_dir(format!("{PATH}/transaction"))
+            .expect("transaction")
+            .count()
+    );
+
+    remove_dir_all(PATH).expect("failed to remove the test folder");
+}
@@ -0,0 +1,66 @@
+use std::{
+    fs::{remove_dir_all, write},
+    num::NonZero,
+};
+
+use crate::{heap_file, prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Table)]
+pub struct Message {
+    #[PrimaryKey]
+    pub id: u32,
+    pub body: DynanicBinary<String>,
+    #[DynStorage(Heap)]
+    pub subject: DynanicBinary<String>,
+}
+
+fn message(id: u32) -> Message {
+    Message {
+        id,
+        body: DynanicBinary::new(format!("body {id}")),
+        subject: DynanicBinary::new(format!("subject {id}")),
+    }
+}
+
+#[test]
+fn test_vacuum_dyn() {
+    const PATH: &str = "test/vacuum_dyn";
+    if Path::new(PATH).exists() {
+        remove_dir_all(PATH).expect("PATH already exists");
+    }
+    let path = BDPath::new_main_str(PATH);
+    let mut table = TableFile::new(PATH.to_owned()).expect("failed to create the table");
+    let mut messages = (1..=3).map(message).collect::<Vec<Message>>();
+    for message in &mut messages {
+        table.insert(message).expect("failed to insert");
+    }
+    assert!(
+        table
+            .vacuum_dyn(false)
+            .expect("failed to vacuum")
+            .is_empty()
+    );
+
+    write(format!("{PATH}/dyn/100.bin"), "orphan").expect("failed to write");
+    write(format!("{PATH}/dyn/101.temp.bin"), "temp").expect("failed to write");
+    let record = heap_file::insert(&path, b"lost").expect("failed to insert");
+    let mut leaked = message(4);
+    leaked.as_bin(&path).expect("failed to write");
+    let leaked_body = leaked.body.id().expect("no id");
+
+    let mut expected = DynVacuum {
+        files: vec![leaked_body, NonZero::new(100).expect("zero")],
+        records: vec![record, leaked.subject.id().expect("no id")],
+        temporary: vec!["101.temp.bin".to_owned()],
+        bytes: 6 + 4 + 4 + 6 + 9,
+        recounted: Vec::new(),
+    };
+    expected.files.sort();
+    assert_eq!(expected, table.vacuum_dyn(true).expect("failed to vacuum"));
+    assert_eq!(expected, table.vacuum_dyn(false).expect("failed to vacuum"));
+    assert!(table.vacuum_dyn(true).expect("failed to vacuum").is_empty());
+    assert!(!Path::new(&format!("{PATH}/dyn/100.bin")).exists());
+    assert_eq!(messages, table.get_all().expect("failed to read"));
+
+    remove_dir_all(PATH).expect("failed to remove the test table");
+}
@@ -0,0 +1,141 @@
+use std::fs::{File, read_dir, remove_dir_all};
+use std::io::Write;
+
+use crate::prelude::*;
+use crate::wal::{Wal, WalOp};
+
+#[derive(Debug, Clone, PartialEq, Table)]
+struct Client {
+    #[PrimaryKey]
+    id: usize,
+    #[Index]
+    nom: DynanicBinary<String>,
+    #[Unique]
+    code: u32,
+}
+
+fn client(id: usize, nom: &str) -> Client {
+    Client {
+        id,
+        nom: DynanicBinary::new(String::from(nom)),
+        code: id as u32 * 10,
+    }
+}
+
+fn new_table(path: &str) -> TableFile<Client> {
+    if Path::new(path).exists() {
+        remove_dir_all(path).expect("path already exists");
+    }
+    let mut table = TableFile::new(path.to_owned()).expect("failed to create table");
+    for (id, nom) in [(1, "Bob"), (2, "Fred")] {
+        assert_eq!(
+            TableInsert::Inserted,
+            table.insert(&mut client(id, nom)).expect("insert")
+        );
+    }
+    table
+}
+
+fn nb_wal(path: &str) -> usize {
+    read_dir(format!("{path}/wal")).expect("wal").count()
+}
+
+fn nb_dyn(path: &str) -> usize {
+    read_dir(format!("{path}/dyn")).expect("dyn").count()
+}
+
+fn noms(table: &TableFile<Client>, nom: &str) -> Vec<usize> {
+    match table.get_by_nom(&DynanicBinary::new(String::from(nom))) {
+        TableGet::Found(clients) => clients.into_iter().map(|client| client.id).collect(),
+        TableGet::NotFound => Vec::new(),
+        e => panic!("get_by_nom {e:?}"),
+    }
+}
+
+#[test]
+pub fn test_wal_insert() {
+    const PATH: &str = "test/wal_insert";
+    let table = new_table(PATH);
+    assert_eq!(0, nb_wal(PATH));
+    drop(table);
+
+    let path = BDPath::new_main_str(PATH);
+    let mut will = client(3, "Will");
+    let bin = will.as_bin(&path).expect("as_bin");
+    Wal::new(&path)
+        .expect("wal")
+        .write(&[WalOp::Insert(bin.clone())])
+        .expect("write");
+    {
+        let mut main = File::options()
+            .append(true)
+            .open(path.full())
+            .expect("main");
+        main.write_all(&bin[..bin.len() / 2]).expect("partial row");
+    }
+
+    let table = TableFile::<Client>::new(PATH.to_owned()).expect("recover");
+    assert_eq!(0, nb_wal(PATH));
+    assert_eq!(3, table.len().expect("len"));
+    assert_eq!(vec![3], noms(&table, "Will"));
+    let TableGet::Found(found) = table.get_by_id(&3) else {
+        panic!("Will suppossed to be found")
+    };
+    assert_eq!(will, found);
+
+    drop(table);
+    let table = TableFile::<Client>::new(PATH.to_owned()).expect("reopen");
+    assert_eq!(3, table.len().expect("len"));
+}
+
+#[test]
+pub fn test_wal_remove() {
+    const PATH: &str = "test/wal_remove";
+    drop(new_table(PATH));
+
+    let path = BDPath::new_main_str(PATH);
+    Wal::new(&path)
+        .expect("wal")
+        .write(&[WalOp::Remove(1_usize.as_bin(&path).expect("as_bin"))])
+        .expect("write");
+    BinFile::<Client>::new(path.clone())
+        .expect("main")
+        .remove(0, Some(1))
+        .expect("remove");
+
+    let mut table = TableFile::<Client>::new(PATH.to_owned()).expect("recover");
+    assert_eq!(0, nb_wal(PATH));
+    assert_eq!(1, table.len().expect("len"));
+    assert!(noms(&table, "Bob").is_empty());
+    assert_eq!(vec![2], noms(&table, "Fred"));
+    assert_eq!(
+        TableInsert::Inserted,
+        table
+            .insert(&mut client(1, "Bob"))
+            .expect("unique code is free")
+    );
+}
+
+#[test]
+pub fn test_wal_replace() {
+    const PATH: &str = "test/wal_replace";
+    drop(new_table(PATH));
+
+    let path = BDPath::new_main_str(PATH);
+    let mut bob = client(1, "Robert");
+    Wal::new(&path)
+        .expect("wal")
+        .write(&[
+            WalOp::Remove(2_usize.as_bin(&path).expect("as_bin")),
+            WalOp::Update(bob.as_bin(&path).expect("as_bin")),
+        ])
+        .expect("write");
+
+    let table = TableFile::<Client>::new(PATH.to_owned()).expect("recover");
+    assert_eq!(0, nb_wal(PATH));
+    assert_eq!(1, table.len().expect("len"));
+    assert!(noms(&table, "Bob").is_empty());
+    assert!(noms(&table, "Fred").is_empty());
+    assert_eq!(vec![1], noms(&table, "Robert"));
+    assert_eq!(1, nb_dyn(PATH));
+}
@@ -0,0 +1,560 @@
+use std::{
+    any::{Any, TypeId},
+    cmp::Ordering,
+    collections::HashMap,
+    fs::{self, File, canonicalize, create_dir_all, remove_file, rename},
+    io::{self, Error, ErrorKind, Write},
+    marker::PhantomData,
+    mem,
+    path::Path,
+    ptr,
+};
+
+use crate::{
+    bin_file::BaseBinFile,
+    binary::Binary,
+    dedup,
+    dyn_binary::{dyn_ids, reference_dyn, remove_dyn},
+    foreign::Staging,
+    table::{SpecificTableFile, Table, TableInsert},
+    wal::WalOp,
+};
+
+/// A table added to a `Transaction`.
+pub struct TransactionTable<Row: Table> {
+    index: usize,
+    row: PhantomData<Row>,
+}
+
+impl<Row: Table> Clone for TransactionTable<Row> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Row: Table> Copy for TransactionTable<Row> {}
+
+impl<Row: Table> TransactionTable<Row> {
+    /// The table added at `index` in the transaction.
+    pub(crate) const fn at(index: usize) -> Self {
+        Self {
+            index,
+            row: PhantomData,
+        }
+    }
+}
+
+/// Groups changes on multiple tables so they are all applied or none are.
+///
+/// The changes are only validated and kept in memory until `commit`.
+/// Their dynamic data is written in new files so the rows outside of the transaction stay intact.
+/// Dropping the transaction without commiting it rollbacks it.
+pub struct Transaction<'a> {
+    path: String,
+    tables: Vec<Box<dyn UnspecifiedTransactionTable + 'a>>,
+    done: bool,
+}
+
+impl<'a> Transaction<'a> {
+    /// `path` is the folder where the commit files are written.
+    /// The wal of the tables keeps the absolute path of the commit file,
+    /// so they can be reopened from another working directory.
+    pub fn new(path: String) -> io::Result<Self> {
+        create_dir_all(path.as_str())?;
+        Ok(Self {
+            path: canonicalize(path)?.to_string_lossy().into_owned(),
+            tables: Vec::new(),
+            done: false,
+        })
+    }
+
+    pub fn table<Row: Table + 'static, BinFile: BaseBinFile<Row> + 'a>(
+        &mut self,
+        table: &'a mut SpecificTableFile<Row, BinFile>,
+    ) -> TransactionTable<Row>
+    where
+        Row::ID: 'static,
+    {
+        self.tables.push(Box::new(SpecificTransactionTable {
+            table,
+            staged: Vec::new(),
+            entry: None,
+        }));
+        TransactionTable::at(self.tables.len() - 1)
+    }
+
+    /// The first table added with rows of type `Row`.
+    pub fn table_of<Row: Table + 'static>(&self) -> Option<TransactionTable<Row>> {
+        let index = self
+            .tables
+            .iter()
+            .position(|table| table.row_type() == TypeId::of::<Row>())?;
+        Some(TransactionTable::at(index))
+    }
+
+    pub fn insert<Row: Table + 'static>(
+        &mut self,
+        table: TransactionTable<Row>,
+        data: &mut Row,
+    ) -> io::Result<TableInsert<Row::ID>>
+    where
+        Row::ID: 'static,
+    {
+        if let Some(column) = self.missing_parent(table, data)? {
+            return Ok(TableInsert::MissingReference(column));
+        }
+        downcast_insert(self.tables[table.index].insert(data)?)
+    }
+
+    pub fn update<Row: Table + 'static>(
+        &mut self,
+        table: TransactionTable<Row>,
+        data: &mut Row,
+    ) -> io::Result<TableInsert<Row::ID>>
+    where
+        Row::ID: 'static,
+    {
+        if let Some(column) = self.missing_parent(table, data)? {
+            return Ok(TableInsert::MissingReference(column));
+        }
+        downcast_insert(self.tables[table.index].update(data)?)
+    }
+
+    pub fn remove<Row: Table + 'static>(
+        &mut self,
+        table: TransactionTable<Row>,
+        id: &Row::ID,
+    ) -> io::Result<()>
+    where
+        Row::ID: 'static,
+    {
+        self.tables[table.index].remove(id)
+    }
+
+    /// The first column of `data` referencing a row missing from the tables added with `add_references`,
+    /// as they are with the changes staged so far.
+    fn missing_parent<Row: Table + 'static>(
+        &self,
+        table: TransactionTable<Row>,
+        data: &Row,
+    ) -> io::Result<Option<&'static str>> {
+        self.tables[table.index].missing_parent(data, &|path, id| self.staged(path, id))
+    }
+
+    fn staged(&self, path: &str, id: &dyn Any) -> Option<bool> {
+        self.tables
+            .iter()
+            .filter(|table| table.path() == path)
+            .find_map(|table| table.staged(id))
+    }
+
+    /// Once the commit file is written, the transaction will be applied
+    /// even if the process is killed, the next time the tables are opened.
+    ///
+    /// Fails without applying anything if a row references a row missing after the transaction,
+    /// or a removed row is still referenced, through the tables added with `add_references`.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.done = true;
+        let checked = self
+            .tables
+            .iter()
+            .try_for_each(|table| table.check_references(&|path, id| self.staged(path, id)));
+        if let Err(e) = checked {
+            self.base_rollback()?;
+            return Err(e);
+        }
+        let commit = self.next_commit();
+        let logged = self
+            .tables
+            .iter_mut()
+            .filter_map(|table| table.log(&commit).transpose())
+            .collect::<io::Result<Vec<String>>>()
+            .and_then(|entries| write_commit(&commit, &entries));
+        if let Err(e) = logged {
+            let temp = format!("{commit}.tmp");
+            if File::open(temp.as_str()).is_ok() {
+                remove_file(temp)?;
+            }
+            self.base_rollback()?;
+            return Err(e);
+        }
+
+        for table in &mut self.tables {
+            table.apply()?;
+        }
+        remove_file(commit)
+    }
+
+    pub fn rollback(mut self) -> io::Result<()> {
+        self.done = true;
+        self.base_rollback()
+    }
+
+    fn base_rollback(&mut self) -> io::Result<()> {
+        for table in &mut self.tables {
+            table.rollback()?;
+        }
+        Ok(())
+    }
+
+    /// A random name, so an entry a crash left in a wal can't be applied
+    /// by the commit file of a later transaction.
+    fn next_commit(&self) -> String {
+        format!("{}/{:016x}.bin", self.path, rand::random::<u64>())
+    }
+}
+
+/// The commit file lists the wal entries of the transaction, one by line.
+/// It is written whole or not at all.
+fn write_commit(commit: &str, entries: &[String]) -> io::Result<()> {
+    let temp = format!("{commit}.tmp");
+    {
+        let mut file = File::create(temp.as_str())?;
+        file.write_all(entries.join("\n").as_bytes())?;
+        file.sync_all()?;
+    }
+    rename(temp, commit)
+}
+
+/// Removes the commit file once every table of the transaction recovered its wal entry.
+pub(crate) fn release_commit(commit: &str) -> io::Result<()> {
+    let entries = match fs::read_to_string(commit) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    if entries.lines().all(|entry| !Path::new(entry).exists()) {
+        remove_file(commit)?;
+    }
+    Ok(())
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.base_rollback();
+        }
+    }
+}
+
+trait UnspecifiedTransactionTable {
+    fn row_type(&self) -> TypeId;
+    /// The folder of the table.
+    fn path(&self) -> &str;
+    /// `Some(true)` if the row `id` is inserted or updated, `Some(false)` if it is removed.
+    fn staged(&self, id: &dyn Any) -> Option<bool>;
+    fn missing_parent(
+        &self,
+        data: &dyn Any,
+        staging: Staging<'_>,
+    ) -> io::Result<Option<&'static str>>;
+    /// Checks the `References` of the staged rows and to the removed rows.
+    fn check_references(&self, staging: Staging<'_>) -> io::Result<()>;
+    fn insert(&mut self, data: &mut dyn Any) -> io::Result<TableInsert<Box<dyn Any>>>;
+    fn update(&mut self, data: &mut dyn Any) -> io::Result<TableInsert<Box<dyn Any>>>;
+    fn remove(&mut self, id: &dyn Any) -> io::Result<()>;
+    /// Writes the changes in the wal of the table, returns the absolute path of the entry.
+    fn log(&mut self, commit: &str) -> io::Result<Option<String>>;
+    fn apply(&mut self) -> io::Result<()>;
+    fn rollback(&mut self) -> io::Result<()>;
+}
+
+/// The bytes are the row as returned by `Binary::as_bin`.
+enum Staged<Row> {
+    Insert(Row, Vec<u8>),
+    Update(Row, Vec<u8>),
+    Remove,
+}
+
+struct SpecificTransactionTable<'a, Row: Table, BinFile: BaseBinFile<Row>> {
+    table: &'a mut SpecificTableFile<Row, BinFile>,
+    /// At most one change by id.
+    staged: Vec<(Row::ID, Staged<Row>)>,
+    entry: Option<usize>,
+}
+
+fn position<Row: Table>(staged: &[(Row::ID, Staged<Row>)], id: &Row::ID) -> Option<usize> {
+    staged
+        .iter()
+        .position(|(staged, _)| staged.partial_cmp(id) == Some(Ordering::Equal))
+}
+
+fn downcast_mut<T: Any>(data: &mut dyn Any) -> io::Result<&mut T> {
+    data.downcast_mut()
+        .ok_or_else(|| Error::other("wrong row type for the transaction table"))
+}
+
+fn downcast_insert<ID: Any>(insert: TableInsert<Box<dyn Any>>) -> io::Result<TableInsert<ID>> {
+    Ok(match insert {
+        TableInsert::Inserted => TableInsert::Inserted,
+        TableInsert::Updated => TableInsert::Updated,
+        TableInsert::Replaced => TableInsert::Replaced,
+        TableInsert::DuplicateId => TableInsert::DuplicateId,
+        TableInsert::DuplicateUnique(name, id) => TableInsert::DuplicateUnique(
+            name,
+            *id.downcast()
+                .map_err(|_| Error::other("wrong id type for the transaction table"))?,
+        ),
+        TableInsert::IdChanged => TableInsert::IdChanged,
+        TableInsert::MissingReference(name) => TableInsert::MissingReference(name),
+    })
+}
+
+impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTransactionTable<'_, Row, BinFile> {
+    fn exists(&self, id: &Row::ID, staged: Option<usize>) -> io::Result<bool> {
+        match staged {
+            Some(i) => Ok(!matches!(self.staged[i].1, Staged::Remove)),
+            None => Ok(self.table.search(id)?.is_ok()),
+        }
+    }
+
+    /// Checks the unique fields against the table as it will be after the transaction.
+    fn check_unique(&mut self, data: &mut Row) -> io::Result<TableInsert<Row::ID>> {
+        for index_file in &mut self.table.other_index {
+            if let Some((id, _)) = self.staged.iter().find(|(id, staged)| match staged {
+                Staged::Insert(row, _) | Staged::Update(row, _) => {
+                    data.id_cmp(id) != Some(Ordering::Equal) && index_file.unique_eq(row, data)
+                }
+                Staged::Remove => false,
+            }) {
+                return Ok(TableInsert::DuplicateUnique(index_file.name(), id.clone()));
+            }
+            if let Some(id) = index_file.check_unique(data)?
+                && data.id_cmp(&id) != Some(Ordering::Equal)
+                && position(&self.staged, &id).is_none()
+            {
+                return Ok(TableInsert::DuplicateUnique(index_file.name(), id));
+            }
+        }
+        Ok(TableInsert::Inserted)
+    }
+
+    /// Writes the dynamic data in new files.
+    fn as_new_bin(&self, data: &mut Row) -> io::Result<Vec<u8>> {
+        data.visit_dyn(&mut |id| *id = None);
+        data.as_bin(self.table.bin.path())
+    }
+
+    /// Deletes the dynamic data written for `staged`,
+    /// but the deduplicated data referenced by the table or by another staged row.
+    fn delete(&self, staged: &Staged<Row>) -> io::Result<()> {
+        let (Staged::Insert(row, _) | Staged::Update(row, _)) = staged else {
+            return Ok(());
+        };
+        let path = self.table.bin.path();
+        let mut ids = HashMap::new();
+        dyn_ids(&mut row.clone(), &mut ids);
+        let mut others = HashMap::new();
+        for (_, other) in &self.staged {
+            if let Staged::Insert(row, _) | Staged::Update(row, _) = other
+                && !ptr::eq(other, staged)
+            {
+                dyn_ids(&mut row.clone(), &mut others);
+            }
+        }
+        for id in ids.into_keys() {
+            if !dedup::is_dedup(id) {
+                remove_dyn(path, id)?;
+            } else if dedup::refs(path, id)? == 0 && !others.contains_key(&id) {
+                dedup::remove(path, id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<Row: Table + 'static, BinFile: BaseBinFile<Row>> UnspecifiedTransactionTable
+    for SpecificTransactionTable<'_, Row, BinFile>
+where
+    Row::ID: 'static,
+{
+    fn row_type(&self) -> TypeId {
+        TypeId::of::<Row>()
+    }
+
+    fn path(&self) -> &str {
+        self.table.bin.path().dir_path.as_str()
+    }
+
+    fn staged(&self, id: &dyn Any) -> Option<bool> {
+        let i = position(&self.staged, id.downcast_ref::<Row::ID>()?)?;
+        Some(!matches!(self.staged[i].1, Staged::Remove))
+    }
+
+    fn missing_parent(
+        &self,
+        data: &dyn Any,
+        staging: Staging<'_>,
+    ) -> io::Result<Option<&'static str>> {
+        let data = data
+            .downcast_ref::<Row>()
+            .ok_or_else(|| Error::other("wrong row type for the transaction table"))?;
+        for relation in self.table.relations.borrow().iter() {
+            if let Some(column) = relation.missing_parent(data, staging)? {
+                return Ok(Some(column));
+            }
+        }
+        Ok(None)
+    }
+
+    fn check_references(&self, staging: Staging<'_>) -> io::Result<()> {
+        for (id, staged) in &self.staged {
+            for relation in self.table.relations.borrow().iter() {
+                let error = match staged {
+                    Staged::Insert(row, _) | Staged::Update(row, _) => relation
+                        .missing_parent(row, staging)?
+                        .map(|column| format!("{column} references a missing row")),
+                    Staged::Remove => relation
+                        .referenced_by(id, staging)?
+                        .map(|column| format!("the row is still referenced by {column}")),
+                };
+                if let Some(error) = error {
+                    return Err(Error::other(error));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, data: &mut dyn Any) -> io::Result<TableInsert<Box<dyn Any>>> {
+        let data = downcast_mut::<Row>(data)?;
+        let staged = position(&self.staged, data.id());
+        if self.exists(data.id(), staged)? {
+            return Ok(TableInsert::DuplicateId);
+        }
+        if let TableInsert::DuplicateUnique(name, id) = self.check_unique(data)? {
+            return Ok(TableInsert::DuplicateUnique(name, Box::new(id)));
+        }
+
+        let bin = self.as_new_bin(data)?;
+        match staged {
+            Some(i) => self.staged[i].1 = Staged::Update(data.clone(), bin),
+            None => self
+                .staged
+                .push((data.id().clone(), Staged::Insert(data.clone(), bin))),
+        }
+        Ok(TableInsert::Inserted)
+    }
+
+    fn update(&mut self, data: &mut dyn Any) -> io::Result<TableInsert<Box<dyn Any>>> {
+        let data = downcast_mut::<Row>(data)?;
+        let staged = position(&self.staged, data.id());
+        if !self.exists(data.id(), staged)? {
+            return Err(Error::other("update not found"));
+        }
+        if let TableInsert::DuplicateUnique(name, id) = self.check_unique(data)? {
+            return Ok(TableInsert::DuplicateUnique(name, Box::new(id)));
+        }
+
+        let bin = self.as_new_bin(data)?;
+        match staged {
+            Some(i) => {
+                self.delete(&self.staged[i].1)?;
+                self.staged[i].1 = match self.staged[i].1 {
+                    Staged::Insert(_, _) => Staged::Insert(data.clone(), bin),
+                    Staged::Update(_, _) | Staged::Remove => Staged::Update(data.clone(), bin),
+                };
+            }
+            None => self
+                .staged
+                .push((data.id().clone(), Staged::Update(data.clone(), bin))),
+        }
+        Ok(TableInsert::Updated)
+    }
+
+    fn remove(&mut self, id: &dyn Any) -> io::Result<()> {
+        let id = id
+            .downcast_ref::<Row::ID>()
+            .ok_or_else(|| Error::other("wrong id type for the transaction table"))?;
+        match position(&self.staged, id) {
+            Some(i) => {
+                let staged = match self.staged[i].1 {
+                    Staged::Remove => return Err(Error::other("remove not found")),
+                    Staged::Insert(_, _) => self.staged.remove(i).1,
+                    Staged::Update(_, _) => mem::replace(&mut self.staged[i].1, Staged::Remove),
+                };
+                self.delete(&staged)
+            }
+            None if self.table.search(id)?.is_ok() => {
+                self.staged.push((id.clone(), Staged::Remove));
+                Ok(())
+            }
+            None => Err(Error::other("remove not found")),
+        }
+    }
+
+    fn log(&mut self, commit: &str) -> io::Result<Option<String>> {
+        if self.staged.is_empty() {
+            return Ok(None);
+        }
+        let mut ops = vec![WalOp::Transaction(Vec::from(commit.as_bytes()))];
+        for (id, staged) in &self.staged {
+            ops.push(match staged {
+                Staged::Insert(_, bin) => WalOp::Insert(bin.clone()),
+                Staged::Update(_, bin) => WalOp::Update(bin.clone()),
+                Staged::Remove => WalOp::Remove(id.clone().as_bin(self.table.bin.path())?),
+            });
+        }
+        let entry = self.table.wal.write(&ops)?;
+        self.entry = Some(entry);
+        let path = canonicalize(self.table.wal.entry_path(entry))?;
+        Ok(Some(path.to_string_lossy().into_owned()))
+    }
+
+    /// Adds the references to the deduplicated data, rewrites each index once,
+    /// then writes the rows one by one, the index files reading the dynamic data of the rows they remove.
+    fn apply(&mut self) -> io::Result<()> {
+        let mut staged = mem::take(&mut self.staged);
+        for (_, staged) in &mut staged {
+            if let Staged::Insert(row, _) | Staged::Update(row, _) = staged {
+                reference_dyn(row, self.table.bin.path())?;
+            }
+        }
+        let mut olds = Vec::with_capacity(staged.len());
+        for (id, staged) in &staged {
+            olds.push(match (staged, self.table.search(id)?) {
+                (Staged::Insert(..), Err(_)) => None,
+                (Staged::Update(..) | Staged::Remove, Ok(index)) => {
+                    Some(self.table.bin.get(index)?)
+                }
+                _ => return Err(Error::other("table changed during the transaction")),
+            });
+        }
+        let changes = staged
+            .iter()
+            .zip(&olds)
+            .map(|((_, staged), old)| match staged {
+                Staged::Insert(row, _) | Staged::Update(row, _) => (old.as_ref(), Some(row)),
+                Staged::Remove => (old.as_ref(), None),
+            })
+            .collect::<Vec<_>>();
+        for index_file in &mut self.table.other_index {
+            index_file.changes(&changes)?;
+        }
+        for ((id, staged), old) in staged.iter().zip(&olds) {
+            match (staged, self.table.search(id)?, old) {
+                (Staged::Insert(_, bin), Err(index), _) => self.table.bin.insert_bin(index, bin)?,
+                (Staged::Update(row, bin), Ok(index), Some(old)) => {
+                    self.table.bin.set_bin(index, bin)?;
+                    old.delete_replaced(row, self.table.bin.path())?;
+                }
+                (Staged::Remove, Ok(index), _) => self.table.bin.remove(index, Some(1))?,
+                _ => return Err(Error::other("table changed during the transaction")),
+            }
+        }
+        if let Some(entry) = self.entry.take() {
+            self.table.wal.done(entry)?;
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> io::Result<()> {
+        if let Some(entry) = self.entry.take() {
+            self.table.wal.done(entry)?;
+        }
+        for (_, staged) in mem::take(&mut self.staged) {
+            self.delete(&staged)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    fs::{read_dir, remove_file},
+    io,
+    num::NonZero,
+};
+
+use crate::{
+    bin_file::BaseBinFile,
+    dedup,
+    dyn_binary::{dyn_ids, remove_dyn},
+    heap_file,
+    table::{SpecificTableFile, Table},
+};
+
+/// The dynamic data that no row of a table references, found by `SpecificTableFile::vacuum_dyn`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DynVacuum {
+    /// The ids of the `dyn/{id}.bin` files.
+    pub files: Vec<NonZero<usize>>,
+    /// The ids of the records of the heap file.
+    pub records: Vec<NonZero<usize>>,
+    /// The names of the temporary files of the writes that were interrupted.
+    pub temporary: Vec<String>,
+    /// The size of all of them.
+    pub bytes: u64,
+    /// The ids of the deduplicated data still referenced but with a wrong count of references.
+    pub recounted: Vec<NonZero<usize>>,
+}
+
+impl DynVacuum {
+    pub const fn is_empty(&self) -> bool {
+        self.files.is_empty()
+            && self.records.is_empty()
+            && self.temporary.is_empty()
+            && self.recounted.is_empty()
+    }
+}
+
+impl<Row: Table, BinFile: BaseBinFile<Row>> SpecificTableFile<Row, BinFile> {
+    /// Deletes the dynamic data left by the writes that were interrupted or whose row wasn't written,
+    /// and fixes the counts of references of the deduplicated data, or only reports them if `dry_run`.
+    ///
+    /// Reads all the rows, the table must not be written by another process meanwhile.
+    pub fn vacuum_dyn(&mut self, dry_run: bool) -> io::Result<DynVacuum> {
+        let path = self.bin.path().clone();
+        let mut referenced = HashMap::new();
+        for row in self.iter()? {
+            dyn_ids(&mut row?, &mut referenced);
+        }
+
+        let mut vacuum = DynVacuum::default();
+        for entry in read_dir(path.dyn_path())? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            match name
+                .strip_suffix(".bin")
+                .and_then(|id| id.parse::<NonZero<usize>>().ok())
+            {
+                Some(id) if dedup::is_dedup(id) && referenced.contains_key(&id) => {
+                    if dedup::refs(&path, id)? != referenced[&id] {
+                        vacuum.recounted.push(id);
+                    }
+                    continue;
+                }
+                Some(id) if referenced.contains_key(&id) => continue,
+                Some(id) => vacuum.files.push(id),
+                None if name.ends_with(".temp.bin") => vacuum.temporary.push(name),
+                None => continue,
+            }
+            vacuum.bytes += entry.metadata()?.len();
+        }
+        for (id, len) in heap_file::records(&path)? {
+            if !referenced.contains_key(&id) {
+                vacuum.records.push(id);
+                vacuum.bytes += len as u64;
+            }
+        }
+        vacuum.files.sort();
+        vacuum.temporary.sort();
+        vacuum.recounted.sort();
+
+        if !dry_run {
+            for id in vacuum.files.iter().chain(&vacuum.records) {
+                remove_dyn(&path, *id)?;
+            }
+            for id in &vacuum.recounted {
+                dedup::set_refs(&path, *id, referenced[id])?;
+            }
+            for name in &vacuum.temporary {
+                remove_file(format!("{}/{name}", path.dyn_path()))?;
+            }
+        }
+        Ok(vacuum)
+    }
+}
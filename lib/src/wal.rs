@@ -0,0 +1,175 @@
+use std::{
+    cell::Cell,
+    fs::{self, File, create_dir_all, read_dir, remove_file},
+    io::{self, Error, Read, Write},
+    rc::Rc,
+};
+
+use crate::{
+    bd_path::BDPath,
+    encryption::{self, EncryptionKey},
+};
+
+/// A change to a table, written to the wal before being applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalOp {
+    /// The row as returned by `Binary::as_bin`.
+    Insert(Vec<u8>),
+    /// The row as returned by `Binary::as_bin`.
+    Update(Vec<u8>),
+    /// The id as returned by `Binary::as_bin`.
+    Remove(Vec<u8>),
+    Clear,
+    /// The path of the commit file of the transaction.
+    /// The other ops of the entry only apply if the file exists.
+    Transaction(Vec<u8>),
+}
+
+impl WalOp {
+    const fn kind(&self) -> u8 {
+        match self {
+            WalOp::Insert(_) => 0,
+            WalOp::Update(_) => 1,
+            WalOp::Remove(_) => 2,
+            WalOp::Clear => 3,
+            WalOp::Transaction(_) => 4,
+        }
+    }
+
+    fn data(&self) -> &[u8] {
+        match self {
+            WalOp::Insert(data)
+            | WalOp::Update(data)
+            | WalOp::Remove(data)
+            | WalOp::Transaction(data) => data,
+            WalOp::Clear => &[],
+        }
+    }
+
+    fn from_kind(kind: u8, data: Vec<u8>) -> io::Result<Self> {
+        match kind {
+            0 => Ok(WalOp::Insert(data)),
+            1 => Ok(WalOp::Update(data)),
+            2 => Ok(WalOp::Remove(data)),
+            3 => Ok(WalOp::Clear),
+            4 => Ok(WalOp::Transaction(data)),
+            _ => Err(Error::other(format!("unknown wal op {kind}"))),
+        }
+    }
+}
+
+/// The write ahead log of a table.
+/// Each entry is a group of `WalOp` that must all be applied,
+/// encrypted with the key of the table.
+pub struct Wal {
+    path: String,
+    /// Shared by the handles of the table, so they never write the same entry.
+    next: Rc<Cell<usize>>,
+    key: Option<EncryptionKey>,
+}
+
+impl Wal {
+    pub fn new(path: &BDPath) -> io::Result<Self> {
+        let wal = Wal {
+            path: path.wal_path(),
+            next: Rc::new(Cell::new(0)),
+            key: path.key,
+        };
+        create_dir_all(wal.path.as_str())?;
+        wal.next
+            .set(wal.entries_id()?.last().map_or(0, |last| last + 1));
+        Ok(wal)
+    }
+
+    /// Another handle on the same wal, without reading its folder.
+    pub(crate) fn share(&self) -> Self {
+        Wal {
+            path: self.path.clone(),
+            next: Rc::clone(&self.next),
+            key: self.key,
+        }
+    }
+
+    pub(crate) fn entry_path(&self, entry: usize) -> String {
+        format!("{}/{entry}.bin", self.path)
+    }
+
+    fn entries_id(&self) -> io::Result<Vec<usize>> {
+        let mut entries = Vec::new();
+        for file in read_dir(self.path.as_str())? {
+            let file = file?.path();
+            match file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".bin"))
+                .map(|name| name.parse::<usize>())
+            {
+                Some(Ok(entry)) => entries.push(entry),
+                _ => remove_file(file)?,
+            }
+        }
+        entries.sort_unstable();
+        Ok(entries)
+    }
+
+    /// The entries that were written but not done, in the order they were written.
+    pub fn entries(&self) -> io::Result<Vec<(usize, Vec<WalOp>)>> {
+        self.entries_id()?
+            .into_iter()
+            .map(|entry| {
+                let mut data = Vec::new();
+                File::open(self.entry_path(entry))?.read_to_end(&mut data)?;
+                let data = encryption::decrypt(self.key, &entry.to_le_bytes(), &data)?;
+                Ok((entry, Self::decode(&data)?))
+            })
+            .collect()
+    }
+
+    pub fn write(&mut self, ops: &[WalOp]) -> io::Result<usize> {
+        let entry = self.next.get();
+        self.next.set(entry + 1);
+
+        let temp = format!("{}/{entry}.temp.bin", self.path);
+        {
+            let mut file = File::create(temp.as_str())?;
+            file.write_all(&encryption::encrypt(
+                self.key,
+                &entry.to_le_bytes(),
+                &Self::encode(ops),
+            )?)?;
+            file.sync_all()?;
+        }
+        fs::rename(temp, self.entry_path(entry))?;
+        Ok(entry)
+    }
+
+    pub fn done(&self, entry: usize) -> io::Result<()> {
+        remove_file(self.entry_path(entry))
+    }
+
+    fn encode(ops: &[WalOp]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for op in ops {
+            data.push(op.kind());
+            data.extend_from_slice(&(op.data().len() as u64).to_le_bytes());
+            data.extend_from_slice(op.data());
+        }
+        data
+    }
+
+    fn decode(mut data: &[u8]) -> io::Result<Vec<WalOp>> {
+        let mut ops = Vec::new();
+        while let [kind, rest @ ..] = data {
+            let (len, rest) = rest
+                .split_first_chunk::<8>()
+                .ok_or_else(|| Error::other("wal entry is truncated"))?;
+            let len = u64::from_le_bytes(*len) as usize;
+            if rest.len() < len {
+                return Err(Error::other("wal entry is truncated"));
+            }
+            ops.push(WalOp::from_kind(*kind, Vec::from(&rest[..len]))?);
+            data = &rest[len..];
+        }
+        Ok(ops)
+    }
+}
@@ -12,8 +12,10 @@ pub fn binary_derive(input: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(input).expect("failed to parse the input");
     let struct_name = &ast.ident;
 
-    let syn::Data::Struct(ref data_struct) = ast.data else {
-        panic!("Binary derive only supports structs.")
+    let data_struct = match ast.data {
+        syn::Data::Struct(ref data_struct) => data_struct,
+        syn::Data::Enum(ref data_enum) => return binary_enum(&ast, data_enum).into(),
+        syn::Data::Union(_) => panic!("Binary derive only supports structs and enums."),
     };
     let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
 
@@ -22,6 +24,8 @@ pub fn binary_derive(input: TokenStream) -> TokenStream {
     let mut as_bin_statements = Vec::new();
     let mut bin_size_statements = Vec::new();
     let mut delete_statements = Vec::new();
+    let mut delete_replaced_statements = Vec::new();
+    let mut visit_dyn_statements = Vec::new();
 
     for field in &data_struct.fields {
         let field_name = field.ident.as_ref().expect("Field must have an identifier");
@@ -47,6 +51,14 @@ pub fn binary_derive(input: TokenStream) -> TokenStream {
         delete_statements.push(quote! {
             self.#field_name.delete(_path)?;
         });
+
+        delete_replaced_statements.push(quote! {
+            self.#field_name.delete_replaced(&_new.#field_name, _path)?;
+        });
+
+        visit_dyn_statements.push(quote! {
+            self.#field_name.visit_dyn(_visit);
+        });
     }
 
     quote! {
@@ -75,15 +87,545 @@ pub fn binary_derive(input: TokenStream) -> TokenStream {
                 #(#delete_statements)*
                 Ok(())
             }
+
+            fn delete_replaced(&self, _new: &Self, _path: &BDPath) -> std::io::Result<()>{
+                #(#delete_replaced_statements)*
+                Ok(())
+            }
+
+            fn visit_dyn(&mut self, _visit: &mut dyn FnMut(&mut Option<std::num::NonZero<usize>>)) {
+                #(#visit_dyn_statements)*
+            }
         }
     }
     .into()
 }
 
+/// The bindings of the fields of the variant and its patterns binding them, for `self` and `new`.
+fn variant_patterns(
+    enum_name: &Ident,
+    variant: &syn::Variant,
+) -> (
+    Vec<Ident>,
+    Vec<Ident>,
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+) {
+    let variant_name = &variant.ident;
+    let bindings: Vec<Ident> = (0..variant.fields.len())
+        .map(|i| Ident::new(format!("field{i}").as_str(), Span::call_site()))
+        .collect();
+    let new_bindings: Vec<Ident> = bindings
+        .iter()
+        .map(|binding| Ident::new(format!("new_{binding}").as_str(), Span::call_site()))
+        .collect();
+    let (pattern, new_pattern) = match &variant.fields {
+        syn::Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            let new_names = names.clone();
+            (
+                quote! {#enum_name::#variant_name { #(#names: #bindings),* }},
+                quote! {#enum_name::#variant_name { #(#new_names: #new_bindings),* }},
+            )
+        }
+        syn::Fields::Unnamed(_) => (
+            quote! {#enum_name::#variant_name(#(#bindings),*)},
+            quote! {#enum_name::#variant_name(#(#new_bindings),*)},
+        ),
+        syn::Fields::Unit => (
+            quote! {#enum_name::#variant_name},
+            quote! {#enum_name::#variant_name},
+        ),
+    };
+    (bindings, new_bindings, pattern, new_pattern)
+}
+
+/// The `Binary` impl of an enum: the index of the variant then its fields,
+/// padded to the size of the largest variant so all the values have the same size.
+fn binary_enum(ast: &syn::DeriveInput, data_enum: &syn::DataEnum) -> proc_macro2::TokenStream {
+    let enum_name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let discriminant = match data_enum.variants.len() {
+        0..=0x100 => quote! {u8},
+        0x101..=0x10000 => quote! {u16},
+        _ => quote! {u32},
+    };
+
+    let mut from_bin_arms = Vec::new();
+    let mut as_bin_arms = Vec::new();
+    let mut bin_size_statements = Vec::new();
+    let mut delete_arms = Vec::new();
+    let mut delete_replaced_arms = Vec::new();
+    let mut visit_dyn_arms = Vec::new();
+
+    for (index, variant) in data_enum.variants.iter().enumerate() {
+        let types: Vec<&syn::Type> = variant.fields.iter().map(|field| &field.ty).collect();
+        let (bindings, new_bindings, pattern, new_pattern) = variant_patterns(enum_name, variant);
+        let index = proc_macro2::Literal::usize_unsuffixed(index);
+
+        let offsets = (0..types.len()).map(|i| {
+            let previous = &types[..i];
+            quote! { <#discriminant>::bin_size() #(+ <#previous>::bin_size())* }
+        });
+        from_bin_arms.push(quote! {
+            #index => {
+                #(let #bindings = <#types>::from_bin(&_data[#offsets..], _path)?;)*
+                Ok(#pattern)
+            }
+        });
+        as_bin_arms.push(quote! {
+            #pattern => {
+                bin_data.extend_from_slice(&(#index as #discriminant).as_bin(_path)?);
+                #(bin_data.extend_from_slice(&#bindings.as_bin(_path)?);)*
+            }
+        });
+        bin_size_statements.push(quote! {
+            size = size.max(0 #(+ <#types>::bin_size())*);
+        });
+        delete_arms.push(quote! {
+            #pattern => {
+                #(#bindings.delete(_path)?;)*
+            }
+        });
+        delete_replaced_arms.push(quote! {
+            (#pattern, #new_pattern) => {
+                #(#bindings.delete_replaced(#new_bindings, _path)?;)*
+            }
+        });
+        visit_dyn_arms.push(quote! {
+            #pattern => {
+                #(#bindings.visit_dyn(_visit);)*
+            }
+        });
+    }
+
+    // The values of different variants only exist with more than one variant.
+    let other_variants = if data_enum.variants.len() == 1 {
+        quote! {}
+    } else {
+        quote! { _ => self.delete(_path)?, }
+    };
+
+    quote! {
+        impl #impl_generics Binary for #enum_name #ty_generics #where_clause {
+            fn from_bin(_data: &[u8], _path: &BDPath) -> std::io::Result<Self> {
+                match <#discriminant>::from_bin(_data, _path)? {
+                    #(#from_bin_arms)*
+                    _ => Err(std::io::Error::other("invalid variant")),
+                }
+            }
+
+            fn as_bin(&mut self, _path: &BDPath) -> std::io::Result<Vec<u8>> {
+                let mut bin_data = Vec::with_capacity(Self::bin_size());
+                match self {
+                    #(#as_bin_arms)*
+                }
+                bin_data.resize(Self::bin_size(), 0);
+                Ok(bin_data)
+            }
+
+            fn bin_size() -> usize {
+                let mut size = 0;
+                #(#bin_size_statements)*
+                <#discriminant>::bin_size() + size
+            }
+
+            fn delete(&self, _path: &BDPath) -> std::io::Result<()> {
+                match self {
+                    #(#delete_arms)*
+                }
+                Ok(())
+            }
+
+            fn delete_replaced(&self, _new: &Self, _path: &BDPath) -> std::io::Result<()> {
+                match (self, _new) {
+                    #(#delete_replaced_arms)*
+                    #other_variants
+                }
+                Ok(())
+            }
+
+            fn visit_dyn(&mut self, _visit: &mut dyn FnMut(&mut Option<std::num::NonZero<usize>>)) {
+                match self {
+                    #(#visit_dyn_arms)*
+                }
+            }
+        }
+    }
+}
+
+/// The `where_` and `order_by_` query methods of a field, as signatures and functions.
+/// `source` reads the rows in `bounds` when the field is the primary key or indexed,
+/// the rows are otherwise filtered and sorted in memory.
+///
+/// The methods of the other fields are bound with `for<'b>`, so they are only missing
+/// when the type of the field can't be compared.
+fn query_methods(
+    field_name: &Ident,
+    field_type: &syn::Type,
+    source: Option<(proc_macro2::TokenStream, proc_macro2::TokenStream)>,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let where_name = Ident::new(format!("where_{field_name}").as_str(), Span::call_site());
+    let order_name = Ident::new(format!("order_by_{field_name}").as_str(), Span::call_site());
+    let order_desc_name = Ident::new(
+        format!("order_by_{field_name}_desc").as_str(),
+        Span::call_site(),
+    );
+    let cmp = quote! {
+        |a, b| a.#field_name.partial_cmp(&b.#field_name).unwrap_or(std::cmp::Ordering::Equal)
+    };
+    let Some((sorted_by, source)) = source else {
+        return (
+            quote! {
+                fn #where_name<V: 'a>(self, op: Op, value: V) -> Self where #field_type: PartialOrd<V>;
+                fn #order_name(self) -> Self where for<'b> #field_type: PartialOrd;
+                fn #order_desc_name(self) -> Self where for<'b> #field_type: PartialOrd;
+            },
+            quote! {
+                fn #where_name<V: 'a>(self, op: Op, value: V) -> Self where #field_type: PartialOrd<V> {
+                    self.filter(move |row| op.matches(row.#field_name.partial_cmp(&value)))
+                }
+                fn #order_name(self) -> Self where for<'b> #field_type: PartialOrd {
+                    self.order_by_column(None, false, #cmp)
+                }
+                fn #order_desc_name(self) -> Self where for<'b> #field_type: PartialOrd {
+                    self.order_by_column(None, true, #cmp)
+                }
+            },
+        );
+    };
+    (
+        quote! {
+            fn #where_name(self, op: Op, value: #field_type) -> Self;
+            fn #order_name(self) -> Self;
+            fn #order_desc_name(self) -> Self;
+        },
+        quote! {
+            fn #where_name(self, op: Op, value: #field_type) -> Self {
+                let query = match op.bounds(value.clone()) {
+                    Some(bounds) => self.source(#sorted_by, move |table, offset, limit| #source),
+                    None => self,
+                };
+                query.filter(move |row| op.matches(row.#field_name.partial_cmp(&value)))
+            }
+            fn #order_name(self) -> Self {
+                self.order_by_column(Some(#sorted_by), false, #cmp)
+            }
+            fn #order_desc_name(self) -> Self {
+                self.order_by_column(Some(#sorted_by), true, #cmp)
+            }
+        },
+    )
+}
+
+/// The `join_` and `left_join_` query methods of a `Foreign` or `Option<Foreign>` field,
+/// as signatures and functions.
+fn join_methods(
+    struct_name: &Ident,
+    field_name: &Ident,
+    parent: &syn::Type,
+    optional: bool,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let join_name = Ident::new(format!("join_{field_name}").as_str(), Span::call_site());
+    let left_join_name = Ident::new(
+        format!("left_join_{field_name}").as_str(),
+        Span::call_site(),
+    );
+    let foreign = if optional {
+        quote! {|row| row.#field_name.as_ref().map(Foreign::id)}
+    } else {
+        quote! {|row| Some(row.#field_name.id())}
+    };
+    (
+        quote! {
+            fn #join_name<ParentBinFile: BaseBinFile<#parent>>(self, parents: &'a SpecificTableFile<#parent, ParentBinFile>) -> Join<'a, #struct_name, BinFile2, #parent, ParentBinFile>;
+            fn #left_join_name<ParentBinFile: BaseBinFile<#parent>>(self, parents: &'a SpecificTableFile<#parent, ParentBinFile>) -> Join<'a, #struct_name, BinFile2, #parent, ParentBinFile>;
+        },
+        quote! {
+            fn #join_name<ParentBinFile: BaseBinFile<#parent>>(self, parents: &'a SpecificTableFile<#parent, ParentBinFile>) -> Join<'a, #struct_name, BinFile2, #parent, ParentBinFile> {
+                self.join(parents, #foreign)
+            }
+            fn #left_join_name<ParentBinFile: BaseBinFile<#parent>>(self, parents: &'a SpecificTableFile<#parent, ParentBinFile>) -> Join<'a, #struct_name, BinFile2, #parent, ParentBinFile> {
+                self.left_join(parents, #foreign)
+            }
+        },
+    )
+}
+
+/// The storage set with `#[DynStorage(..)]` in `attrs`.
+fn dyn_storage(attrs: &[syn::Attribute]) -> Option<Ident> {
+    attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("DynStorage"))
+        .map(|attr| {
+            attr.parse_args::<Ident>()
+                .expect("DynStorage expects Files, Heap or Dedup")
+        })
+}
+
+/// The codec set with `#[Compression(..)]` in `attrs`.
+fn compression(attrs: &[syn::Attribute]) -> Option<Ident> {
+    attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("Compression"))
+        .map(|attr| {
+            attr.parse_args::<Ident>()
+                .expect("Compression expects None or Lz4")
+        })
+}
+
+/// The path given to `as_bin` for a field, with the storage and codec of the field or else of the table.
+fn dyn_storage_path(
+    attrs: &[syn::Attribute],
+    table_storage: Option<&Ident>,
+    table_codec: Option<&Ident>,
+) -> proc_macro2::TokenStream {
+    let storage = dyn_storage(attrs);
+    let codec = compression(attrs);
+    match (
+        storage.as_ref().or(table_storage),
+        codec.as_ref().or(table_codec),
+    ) {
+        (None, None) => quote! { _path },
+        (Some(storage), None) => quote! { &_path.with_dyn_storage(DynStorage::#storage) },
+        (None, Some(codec)) => quote! { &_path.with_codec(Codec::#codec) },
+        (Some(storage), Some(codec)) => {
+            quote! { &_path.with_dyn_storage(DynStorage::#storage).with_codec(Codec::#codec) }
+        }
+    }
+}
+
+/// The `Schema::column` call of a field.
+fn schema_column(field_name: &Ident, field_type: &syn::Type) -> proc_macro2::TokenStream {
+    let name = format!("{field_name}");
+    let ty = quote! {#field_type}.to_string().replace(' ', "");
+    quote! {
+        .column(#name, #ty, <#field_type>::bin_size())
+    }
+}
+
+/// The first generic type of `ty` if it is a `name<..>` path.
+fn generic_arg<'t>(ty: &'t syn::Type, name: &str) -> Option<&'t syn::Type> {
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != name {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+/// The `Parent` of a `Foreign<Parent>` or `Option<Foreign<Parent>>` type and if it is optional.
+fn foreign_parent(ty: &syn::Type) -> Option<(&syn::Type, bool)> {
+    match generic_arg(ty, "Option") {
+        Some(ty) => generic_arg(ty, "Foreign").map(|parent| (parent, true)),
+        None => generic_arg(ty, "Foreign").map(|parent| (parent, false)),
+    }
+}
+
+/// The expression of the column referencing `id`, of a `Foreign` or `Option<Foreign>` type.
+fn foreign_value(id: &proc_macro2::TokenStream, optional: bool) -> proc_macro2::TokenStream {
+    if optional {
+        quote! {Some(Foreign::new(#id))}
+    } else {
+        quote! {Foreign::new(#id)}
+    }
+}
+
+/// The `{child}s_of` accessors listing the rows referencing a parent, one trait per `Foreign` field.
+/// They are named `{child}s_of_{field}` when multiple fields reference the same table.
+fn children_accessors(struct_name: &Ident, fields: &syn::Fields) -> Vec<proc_macro2::TokenStream> {
+    let foreign_fields: Vec<_> = fields
+        .iter()
+        .filter(|field| {
+            !field
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("PrimaryKey"))
+        })
+        .filter_map(|field| {
+            let (parent, optional) = foreign_parent(&field.ty)?;
+            Some((field, parent, optional))
+        })
+        .collect();
+    let mut snake_name = String::new();
+    for (i, c) in struct_name.to_string().chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake_name.push('_');
+        }
+        snake_name.extend(c.to_lowercase());
+    }
+
+    foreign_fields
+        .iter()
+        .map(|(field, parent, optional)| {
+            let field_name = field.ident.as_ref().expect("Field must have an identifier");
+            let parent_key = quote! {#parent}.to_string();
+            let shared = foreign_fields
+                .iter()
+                .filter(|(_, other, _)| quote! {#other}.to_string() == parent_key)
+                .count()
+                > 1;
+            let fn_name = if shared {
+                format!("{snake_name}s_of_{field_name}")
+            } else {
+                format!("{snake_name}s_of")
+            };
+            let fn_name = Ident::new(fn_name.as_str(), Span::call_site());
+            let camel_field: String = field_name
+                .to_string()
+                .split('_')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                        .unwrap_or_default()
+                })
+                .collect();
+            let trait_name = Ident::new(
+                format!("{struct_name}Of{camel_field}").as_str(),
+                Span::call_site(),
+            );
+            let range_name =
+                Ident::new(format!("range_by_{field_name}").as_str(), Span::call_site());
+            let value = foreign_value(&quote! {parent.id().clone()}, *optional);
+            quote! {
+                trait #trait_name {
+                    fn #fn_name<ChildBinFile: BaseBinFile<#struct_name>>(&self, parent: &#parent, children: &SpecificTableFile<#struct_name, ChildBinFile>) -> io::Result<Vec<#struct_name>>;
+                }
+                impl<BinFile2: BaseBinFile<#parent>> #trait_name for SpecificTableFile<#parent, BinFile2> {
+                    fn #fn_name<ChildBinFile: BaseBinFile<#struct_name>>(&self, parent: &#parent, children: &SpecificTableFile<#struct_name, ChildBinFile>) -> io::Result<Vec<#struct_name>> {
+                        children.#range_name(#value..=#value, 0, None)
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// The `References` impls of the `#[References(..)]` fields, one per referenced table.
+fn references_impls(struct_name: &Ident, fields: &syn::Fields) -> Vec<proc_macro2::TokenStream> {
+    let mut parents: Vec<(String, &syn::Type, Vec<_>, Vec<_>, Vec<_>, Vec<_>)> = Vec::new();
+    for field in fields {
+        let Some(attr) = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("References"))
+        else {
+            continue;
+        };
+        let on_delete: Ident = attr
+            .parse_args()
+            .expect("References expects Restrict, Cascade or SetNull");
+        let field_name = field.ident.as_ref().expect("Field must have an identifier");
+        let in_name = format!("{field_name}");
+        let (parent, optional) = foreign_parent(&field.ty)
+            .expect("References only supports Foreign and Option<Foreign> fields");
+
+        let key = quote! {#parent}.to_string();
+        let index = parents
+            .iter()
+            .position(|(other, ..)| *other == key)
+            .unwrap_or_else(|| {
+                parents.push((key, parent, Vec::new(), Vec::new(), Vec::new(), Vec::new()));
+                parents.len() - 1
+            });
+        let (.., referencing, referenced, references, set_null) = &mut parents[index];
+        let range_name = Ident::new(format!("range_by_{field_name}").as_str(), Span::call_site());
+        let value = foreign_value(&quote! {_id.clone()}, optional);
+        referencing.push(quote! {
+            rows.extend(_table.#range_name(#value..=#value, 0, None)?);
+        });
+        let field_type = &field.ty;
+        let not_null = if optional {
+            quote! {(std::ops::Bound::Excluded(None), std::ops::Bound::<#field_type>::Unbounded)}
+        } else {
+            quote! {..}
+        };
+        referenced.push(quote! {
+            if !_table.#range_name(#not_null, 0, Some(1))?.is_empty() {
+                return Ok(Some(#in_name));
+            }
+        });
+        if optional {
+            references.push(quote! {
+                if let Some(foreign) = &self.#field_name {
+                    references.push((#in_name, OnDelete::#on_delete, foreign.id()));
+                }
+            });
+            set_null.push(quote! {
+                if _column == #in_name {
+                    self.#field_name = None;
+                }
+            });
+        } else {
+            assert!(
+                on_delete != "SetNull",
+                "SetNull requires an Option<Foreign> field"
+            );
+            references.push(quote! {
+                references.push((#in_name, OnDelete::#on_delete, self.#field_name.id()));
+            });
+        }
+    }
+
+    parents
+        .into_iter()
+        .map(|(_, parent, referencing, referenced, references, set_null)| {
+            quote! {
+                impl References<#parent> for #struct_name {
+                    fn referencing<BinFile2: BaseBinFile<Self>>(_table: &SpecificTableFile<Self, BinFile2>, _id: &<#parent as Table>::ID) -> io::Result<Vec<Self>> {
+                        let mut rows = Vec::new();
+                        #(#referencing)*
+                        rows.sort_by(|a: &Self, b: &Self| a.id().partial_cmp(b.id()).unwrap_or(std::cmp::Ordering::Equal));
+                        rows.dedup_by(|a, b| a.id().partial_cmp(b.id()) == Some(std::cmp::Ordering::Equal));
+                        Ok(rows)
+                    }
+
+                    fn referenced<BinFile2: BaseBinFile<Self>>(_table: &SpecificTableFile<Self, BinFile2>) -> io::Result<Option<&'static str>> {
+                        #(#referenced)*
+                        Ok(None)
+                    }
+
+                    fn references(&self) -> Vec<(&'static str, OnDelete, &<#parent as Table>::ID)> {
+                        let mut references = Vec::new();
+                        #(#references)*
+                        references
+                    }
+
+                    fn set_null(&mut self, _column: &'static str) {
+                        #(#set_null)*
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
 /// # Panics
 /// Will panic if cant parse the input
 #[expect(clippy::too_many_lines)]
-#[proc_macro_derive(Table, attributes(PrimaryKey, Cached, Index, Unique))]
+#[proc_macro_derive(
+    Table,
+    attributes(
+        PrimaryKey,
+        Cached,
+        Index,
+        Unique,
+        References,
+        Version,
+        DynStorage,
+        Compression
+    )
+)]
 pub fn table_row_macro(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
@@ -109,17 +651,49 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
         .as_ref()
         .expect("Field must have an identifier");
     let primary_field_type = &primary_field.ty;
+    let version = ast
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("Version"))
+        .map_or(0, |attr| {
+            attr.parse_args::<syn::LitInt>()
+                .and_then(|version| version.base10_parse::<u32>())
+                .expect("Version expects an integer")
+        });
 
     let struct_name = &ast.ident;
+    let table_storage = dyn_storage(&ast.attrs);
+    let table_codec = compression(&ast.attrs);
+    let primary_path = dyn_storage_path(
+        &primary_field.attrs,
+        table_storage.as_ref(),
+        table_codec.as_ref(),
+    );
+    let schema_compression = table_codec
+        .as_ref()
+        .map(|codec| quote! { .compression(Codec::#codec) });
 
     let mut field_declarations = Vec::new();
     let mut from_bin_assignments = Vec::new();
     let mut as_bin_statements = Vec::new();
     let mut bin_size_statements = Vec::new();
     let mut delete_statements = Vec::new();
+    let mut delete_replaced_statements = Vec::new();
+    let mut visit_dyn_statements = Vec::new();
     let mut get_indexes_statements = Vec::new();
     let mut get_indexes_functions_signature = Vec::new();
     let mut get_indexes_functions = Vec::new();
+    let (query_signature, query_function) = query_methods(
+        primary_field_name,
+        primary_field_type,
+        Some((
+            quote! {SortedBy::Id},
+            quote! {table.iter_range(bounds, offset, limit)},
+        )),
+    );
+    let mut query_functions_signature = vec![query_signature];
+    let mut query_functions = vec![query_function];
+    let mut schema_statements = vec![schema_column(primary_field_name, primary_field_type)];
 
     for field in fields {
         let field_name = field.ident.as_ref().expect("Field must have an identifier");
@@ -137,8 +711,9 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
             offset += <#field_type>::bin_size();
         });
 
+        let path = dyn_storage_path(&field.attrs, table_storage.as_ref(), table_codec.as_ref());
         as_bin_statements.push(quote! {
-            bin_data.extend_from_slice(&self.#field_name.as_bin(_path)?);
+            bin_data.extend_from_slice(&self.#field_name.as_bin(#path)?);
         });
 
         bin_size_statements.push(quote! {
@@ -149,6 +724,15 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
             self.#field_name.delete(_path)?;
         });
 
+        delete_replaced_statements.push(quote! {
+            self.#field_name.delete_replaced(&_new.#field_name, _path)?;
+        });
+
+        visit_dyn_statements.push(quote! {
+            self.#field_name.visit_dyn(_visit);
+        });
+        schema_statements.push(schema_column(field_name, field_type));
+
         let cached = field
             .attrs
             .iter()
@@ -158,8 +742,37 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
             .iter()
             .any(|attr| attr.path().is_ident("Unique"));
 
-        if unique || field.attrs.iter().any(|attr| attr.path().is_ident("Index")) {
+        let indexed = unique
+            || foreign_parent(field_type).is_some()
+            || field.attrs.iter().any(|attr| attr.path().is_ident("Index"));
+        let (query_signature, query_function) = query_methods(
+            field_name,
+            field_type,
+            indexed.then(|| {
+                let in_name = format!("{field_name}");
+                let iter_name =
+                    Ident::new(format!("iter_by_{field_name}").as_str(), Span::call_site());
+                (
+                    quote! {SortedBy::Index(#in_name)},
+                    quote! {table.#iter_name(bounds, offset, limit)},
+                )
+            }),
+        );
+        query_functions_signature.push(query_signature);
+        query_functions.push(query_function);
+        if let Some((parent, optional)) = foreign_parent(field_type) {
+            let (join_signature, join_function) =
+                join_methods(struct_name, field_name, parent, optional);
+            query_functions_signature.push(join_signature);
+            query_functions.push(join_function);
+        }
+
+        if indexed {
             let in_name = format!("{field_name}");
+            let i = get_indexes_statements.len();
+            schema_statements.push(quote! {
+                .index(#in_name, #unique)
+            });
 
             let index_file = if cached {
                 quote! {CachedIndexFile<#field_type, #struct_name>}
@@ -167,17 +780,15 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
                 quote! {IndexFile<#field_type, #struct_name>}
             };
             get_indexes_statements.push(quote! {
-                Box::new(<#index_file>::new(BDPath::new_index(path.clone(), #in_name.to_owned()), Box::new(|row: &#struct_name| &row.#field_name), #unique)?),
+                Box::new(<#index_file>::new(BDPath::new_index(path.dir_path.clone(), #in_name.to_owned()).with_key(path.key), #in_name, Box::new(|row: &#struct_name| &row.#field_name), #unique)?),
             });
 
             let fn_name = Ident::new(format!("get_by_{field_name}").as_str(), Span::call_site());
 
-            let i = get_indexes_functions.len();
-
             let get_gens = if cached {
-                quote! {CachedBinFile<IndexRow<#field_type>>}
+                quote! {CachedBinFile<IndexRow<#field_type, #primary_field_type>>}
             } else {
-                quote! {BinFile<IndexRow<#field_type>>}
+                quote! {BinFile<IndexRow<#field_type, #primary_field_type>>}
             };
             if unique {
                 get_indexes_functions_signature.push(quote! {
@@ -193,11 +804,11 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
                         }[..]
                         {
                             [] => TableGet::Found(None),
-                            [data] => {
-                                match self.get_by_index(data.index) {
-                                    Ok(data) => TableGet::Found(Some(data)),
-                                    Err(e) => TableGet::Err(e),
-                                }
+                            [data] => match self.get_by_id(&data.id) {
+                                TableGet::Found(data) => TableGet::Found(Some(data)),
+                                TableGet::NotFound => TableGet::InternalError("index references a missing row".to_owned()),
+                                TableGet::InternalError(e) => TableGet::InternalError(e),
+                                TableGet::Err(e) => TableGet::Err(e),
                             },
                             _ => TableGet::InternalError("multiple with the same id".to_owned()),
                         }
@@ -215,14 +826,55 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
                             IndexGet::InternalError(e) => return TableGet::InternalError(e),
                             IndexGet::Err(e) => return TableGet::Err(e),
                         };
-                        match index
-                            .into_iter()
-                            .map(|index| self.get_by_index(index.index))
-                            .collect::<io::Result<Vec<#struct_name>>>()
-                        {
-                            Ok(datas) => TableGet::Found(datas),
-                            Err(e) => TableGet::Err(e),
+                        let mut datas = Vec::with_capacity(index.len());
+                        for index in index {
+                            match self.get_by_id(&index.id) {
+                                TableGet::Found(data) => datas.push(data),
+                                TableGet::NotFound => return TableGet::InternalError("index references a missing row".to_owned()),
+                                TableGet::InternalError(e) => return TableGet::InternalError(e),
+                                TableGet::Err(e) => return TableGet::Err(e),
+                            }
                         }
+                        TableGet::Found(datas)
+                    }
+                });
+            }
+
+            let range_name =
+                Ident::new(format!("range_by_{field_name}").as_str(), Span::call_site());
+            get_indexes_functions_signature.push(quote! {
+                fn #range_name(&self, range: impl std::ops::RangeBounds<#field_type>, offset: usize, limit: Option<usize>) -> io::Result<Vec<#struct_name>>;
+            });
+            get_indexes_functions.push(quote! {
+                fn #range_name(&self, range: impl std::ops::RangeBounds<#field_type>, offset: usize, limit: Option<usize>) -> io::Result<Vec<#struct_name>> {
+                    let index = unsafe{self.get_index_file::<#field_type, #get_gens>(#i)}.range(&range, offset, limit)?;
+                    self.get_by_ids(index.iter().map(|index| &index.id))
+                }
+            });
+
+            let iter_name = Ident::new(format!("iter_by_{field_name}").as_str(), Span::call_site());
+            get_indexes_functions_signature.push(quote! {
+                fn #iter_name(&self, range: impl std::ops::RangeBounds<#field_type>, offset: usize, limit: Option<usize>) -> io::Result<impl Iterator<Item = io::Result<#struct_name>> + '_>;
+            });
+            get_indexes_functions.push(quote! {
+                fn #iter_name(&self, range: impl std::ops::RangeBounds<#field_type>, offset: usize, limit: Option<usize>) -> io::Result<impl Iterator<Item = io::Result<#struct_name>> + '_> {
+                    let index = unsafe{self.get_index_file::<#field_type, #get_gens>(#i)}.iter_range(&range, offset, limit)?;
+                    Ok(self.iter_ids(index.map(|index| Ok(index?.id))))
+                }
+            });
+
+            if quote! {#field_type}.to_string().replace(' ', "") == "DynanicBinary<String>" {
+                let prefix_name = Ident::new(
+                    format!("prefix_by_{field_name}").as_str(),
+                    Span::call_site(),
+                );
+                get_indexes_functions_signature.push(quote! {
+                    fn #prefix_name(&self, prefix: &str, offset: usize, limit: Option<usize>) -> io::Result<Vec<#struct_name>>;
+                });
+                get_indexes_functions.push(quote! {
+                    fn #prefix_name(&self, prefix: &str, offset: usize, limit: Option<usize>) -> io::Result<Vec<#struct_name>> {
+                        let index = unsafe{self.get_index_file::<#field_type, #get_gens>(#i)}.prefix(prefix, offset, limit)?;
+                        self.get_by_ids(index.iter().map(|index| &index.id))
                     }
                 });
             }
@@ -233,6 +885,9 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
         format!("{struct_name}TableFileGets").as_str(),
         Span::call_site(),
     );
+    let query_trait_name = Ident::new(format!("{struct_name}Query").as_str(), Span::call_site());
+    let references_impls = references_impls(struct_name, fields);
+    let children_accessors = children_accessors(struct_name, fields);
 
     quote! {
         impl #impl_generics Binary for #struct_name #ty_generics #where_clause {
@@ -247,7 +902,7 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
             }
 
             fn as_bin(&mut self, _path: &BDPath) -> std::io::Result<Vec<u8>> {
-                let mut bin_data = self.#primary_field_name.as_bin(_path)?;
+                let mut bin_data = self.#primary_field_name.as_bin(#primary_path)?;
                 #(#as_bin_statements)*
                 Ok(bin_data)
             }
@@ -258,11 +913,26 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
                 size
             }
 
+            fn key_size() -> usize {
+                <#primary_field_type>::bin_size()
+            }
+
             fn delete(&self, _path: &BDPath) -> std::io::Result<()>{
                 self.#primary_field_name.delete(_path)?;
                 #(#delete_statements)*
                 Ok(())
             }
+
+            fn delete_replaced(&self, _new: &Self, _path: &BDPath) -> std::io::Result<()>{
+                self.#primary_field_name.delete_replaced(&_new.#primary_field_name, _path)?;
+                #(#delete_replaced_statements)*
+                Ok(())
+            }
+
+            fn visit_dyn(&mut self, _visit: &mut dyn FnMut(&mut Option<std::num::NonZero<usize>>)) {
+                self.#primary_field_name.visit_dyn(_visit);
+                #(#visit_dyn_statements)*
+            }
         }
 
         impl #impl_generics Table for #struct_name #ty_generics #where_clause {
@@ -272,11 +942,18 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
                 &self.#primary_field_name
             }
 
-            fn get_indexes(path: String) -> io::Result<Vec<Box<dyn UnspecifiedIndex<#struct_name>>>>{
+            fn get_indexes(path: &BDPath) -> io::Result<Vec<Box<dyn UnspecifiedIndex<#struct_name>>>>{
                 Ok(vec![
                     #(#get_indexes_statements)*
                 ])
             }
+
+            fn schema() -> Schema {
+                Schema::default()
+                    .version(#version)
+                    #schema_compression
+                    #(#schema_statements)*
+            }
         }
 
         trait #trait_name {
@@ -285,6 +962,17 @@ pub fn table_row_macro(input: TokenStream) -> TokenStream {
         impl<BinFile2: BaseBinFile<#struct_name>> #trait_name for SpecificTableFile<#struct_name, BinFile2> {
             #(#get_indexes_functions)*
         }
+
+        trait #query_trait_name<'a, BinFile2: BaseBinFile<#struct_name> + 'a> {
+            #(#query_functions_signature)*
+        }
+        impl<'a, BinFile2: BaseBinFile<#struct_name> + 'a> #query_trait_name<'a, BinFile2> for Query<'a, #struct_name, BinFile2> {
+            #(#query_functions)*
+        }
+
+        #(#references_impls)*
+
+        #(#children_accessors)*
     }
     .into()
 }